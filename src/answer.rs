@@ -0,0 +1,193 @@
+use crate::unification::Term;
+use crate::vm::{AtomType, VirtualMachine};
+use std::fmt;
+
+// A term from an answer, with its atoms, strings and variables resolved to
+// their names.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Atom(String),
+    Integer(i64),
+    // A boolean, i.e. `true` or `false`.
+    Boolean(bool),
+    String(String),
+    Tuple(Vec<Value>),
+    // A list whose tail is not bound by the answer, e.g. `[a | t]`.
+    List(Vec<Value>, Box<Value>),
+    // A variable that is not bound by the answer.
+    Variable(String),
+}
+
+impl Value {
+    pub(crate) fn from_term(term: &Term<AtomType>, vm: &VirtualMachine) -> Value {
+        let name = |id: &AtomType| match vm.lookup_interned(id) {
+            Some(name) => name.to_string(),
+            None => id.to_string(),
+        };
+        match term {
+            Term::Atom(a) => Value::Atom(name(a)),
+            Term::Integer(n) => Value::Integer(*n),
+            Term::String(s) => Value::String(name(s)),
+            Term::Tuple(terms) => {
+                Value::Tuple(terms.iter().map(|t| Value::from_term(t, vm)).collect())
+            }
+            Term::Cons(h, t) => {
+                let mut values = vec![Value::from_term(h, vm)];
+                let mut tail = t;
+                while let Term::Cons(h, t) = &**tail {
+                    values.push(Value::from_term(h, vm));
+                    tail = t;
+                }
+                Value::List(values, Box::new(Value::from_term(tail, vm)))
+            }
+            Term::Variable(_) => Value::Variable(vm.term_to_string(term)),
+        }
+    }
+
+    pub fn as_atom(&self) -> Option<&str> {
+        match self {
+            Value::Atom(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_tuple(&self) -> Option<&[Value]> {
+        match self {
+            Value::Tuple(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Atom(a) => write!(f, "{}", a),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Tuple(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::List(values, tail) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, " | {}]", tail)
+            }
+            Value::Variable(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+// An answer to a query, binding each of the query's variables to a value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Answer {
+    // Sorted by variable name.
+    bindings: Vec<(String, Value)>,
+}
+
+impl Answer {
+    pub(crate) fn new(mut bindings: Vec<(String, Value)>) -> Self {
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        Answer { bindings }
+    }
+
+    // Return the value bound to the variable `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.bindings
+            .iter()
+            .find(|(variable, _)| variable == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.bindings
+            .iter()
+            .map(|(variable, value)| (variable.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+}
+
+// The result of evaluating a program, from the value of its last statement.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    // An answer, which may have no bindings, e.g. for a table literal.
+    Answer(Answer),
+    // There are no (more) answers.
+    No,
+    Value(Value),
+    // Any other value, such as a stream or a relation, in its printed form.
+    Other(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::answer::*;
+
+    #[test]
+    fn answers() {
+        let answer = Answer::new(vec![
+            ("y".to_string(), Value::Integer(175)),
+            (
+                "x".to_string(),
+                Value::Tuple(vec![
+                    Value::Atom("abraham".to_string()),
+                    Value::String("Abraham".to_string()),
+                ]),
+            ),
+        ]);
+        assert_eq!(answer.len(), 2);
+        assert_eq!(answer.get("y").and_then(Value::as_integer), Some(175));
+        assert_eq!(answer.get("y").and_then(Value::as_atom), None);
+        assert_eq!(answer.get("z"), None);
+        let x = answer.get("x").and_then(Value::as_tuple).unwrap();
+        assert_eq!(x[0].as_atom(), Some("abraham"));
+        assert_eq!(x[1].as_str(), Some("Abraham"));
+        assert_eq!(
+            answer.get("x").unwrap().to_string(),
+            "[abraham, \"Abraham\"]"
+        );
+        let names: Vec<&str> = answer.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["x", "y"]);
+    }
+}
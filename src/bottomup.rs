@@ -0,0 +1,896 @@
+use crate::datalog::{self, Goal, Rule};
+use crate::facts::FactTable;
+use crate::parser::AST;
+use crate::unification::Term;
+use crate::vm::{AtomType, Value, VirtualMachine};
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+// Bottom-up evaluation of relations that are Datalog. A relation is
+// materialised into a fact table by semi-naive fixpoint iteration: each round
+// only joins the rows derived in the previous round with the rows derived
+// before it, so no row is derived twice from the same rows, unlike the
+// top-down search of a recursive relation.
+
+type Row = Vec<Term<AtomType>>;
+
+// The Datalog rules of a relation, with the variables the relations they
+// call are bound to.
+pub struct Definition {
+    arity: usize,
+    rules: Vec<Rule>,
+    relations: HashMap<String, u64>,
+}
+
+impl Definition {
+    // Translate a named relation to Datalog, looking up the relations it
+    // calls, or describe why it isn't Datalog.
+    pub fn new<F: Fn(&str) -> Option<u64>>(ast: &AST, lookup: F) -> Result<Definition, String> {
+        let arity = match ast {
+            AST::Relation(_, parameters, _) => parameters.len(),
+            _ => return Err("Expected a relation.".to_string()),
+        };
+        let rules = datalog::rules(ast).map_err(|err| err.msg)?;
+        let mut relations = HashMap::new();
+        for rule in &rules {
+            for goal in &rule.body {
+                if let Goal::Literal(literal) = goal {
+                    match lookup(&literal.relation) {
+                        Some(id) => {
+                            relations.insert(literal.relation.to_string(), id);
+                        }
+                        None => return Err(format!("{} isn't defined.", literal.relation)),
+                    }
+                }
+            }
+        }
+        Ok(Definition {
+            arity,
+            rules,
+            relations,
+        })
+    }
+}
+
+// An argument of a compiled rule, with its variables numbered and its
+// constants interned.
+enum Arg {
+    Variable(usize),
+    Constant(Term<AtomType>),
+    Anonymous,
+}
+
+enum Step {
+    Call(u64, Vec<Arg>),
+    Equals(Arg, Arg),
+}
+
+// A rule compiled for evaluation, with its equalities placed after the calls
+// that bind their variables.
+struct Compiled {
+    relation: u64,
+    head: Vec<Arg>,
+    steps: Vec<Step>,
+    variables: usize,
+}
+
+fn arg(
+    term: &datalog::Term,
+    variables: &mut HashMap<String, usize>,
+    vm: &mut VirtualMachine,
+) -> Arg {
+    match term {
+        datalog::Term::Variable(name) => {
+            let n = variables.len();
+            Arg::Variable(*variables.entry(name.to_string()).or_insert(n))
+        }
+        datalog::Term::Anonymous => Arg::Anonymous,
+        datalog::Term::Atom(s) => Arg::Constant(Term::Atom(vm.intern(s))),
+        datalog::Term::Integer(n) => Arg::Constant(Term::Integer(*n)),
+        datalog::Term::String(s) => Arg::Constant(Term::String(vm.intern(s))),
+    }
+}
+
+fn compile(
+    rule: &Rule,
+    relation: u64,
+    definition: &Definition,
+    vm: &mut VirtualMachine,
+) -> Compiled {
+    let mut variables = HashMap::new();
+    let mut calls = vec![];
+    let mut equalities = vec![];
+    for goal in &rule.body {
+        match goal {
+            Goal::Literal(literal) => {
+                let args = literal
+                    .args
+                    .iter()
+                    .map(|term| arg(term, &mut variables, vm))
+                    .collect();
+                calls.push(Step::Call(definition.relations[&literal.relation], args));
+            }
+            Goal::Equals(left, right) => equalities.push((
+                arg(left, &mut variables, vm),
+                arg(right, &mut variables, vm),
+            )),
+        }
+    }
+    let head = rule
+        .head
+        .args
+        .iter()
+        .map(|term| arg(term, &mut variables, vm))
+        .collect();
+    let mut bound = HashSet::new();
+    let mut steps = vec![];
+    let ready = |arg: &Arg, bound: &HashSet<usize>| match arg {
+        Arg::Variable(v) => bound.contains(v),
+        Arg::Constant(_) => true,
+        Arg::Anonymous => false,
+    };
+    let mut calls = calls.into_iter();
+    loop {
+        // Place the equalities that can be evaluated with the variables
+        // bound so far.
+        while let Some(i) = equalities
+            .iter()
+            .position(|(left, right)| ready(left, &bound) || ready(right, &bound))
+        {
+            let (left, right) = equalities.remove(i);
+            for arg in [&left, &right] {
+                if let Arg::Variable(v) = arg {
+                    bound.insert(*v);
+                }
+            }
+            steps.push(Step::Equals(left, right));
+        }
+        match calls.next() {
+            Some(call) => {
+                if let Step::Call(_, args) = &call {
+                    for arg in args {
+                        if let Arg::Variable(v) = arg {
+                            bound.insert(*v);
+                        }
+                    }
+                }
+                steps.push(call);
+            }
+            None => break,
+        }
+    }
+    Compiled {
+        relation,
+        head,
+        steps,
+        variables: variables.len(),
+    }
+}
+
+fn value<'a>(arg: &'a Arg, bindings: &'a [Option<Term<AtomType>>]) -> Option<&'a Term<AtomType>> {
+    match arg {
+        Arg::Variable(v) => bindings[*v].as_ref(),
+        Arg::Constant(c) => Some(c),
+        Arg::Anonymous => None,
+    }
+}
+
+// The rows added to each relation in the last round of a semi-naive join.
+struct Delta<'a> {
+    rows: &'a HashMap<u64, Vec<Row>>,
+    new: HashMap<u64, HashSet<&'a Row>>,
+}
+
+impl<'a> Delta<'a> {
+    fn new(rows: &'a HashMap<u64, Vec<Row>>) -> Self {
+        Delta {
+            rows,
+            new: rows
+                .iter()
+                .map(|(relation, rows)| (*relation, rows.iter().collect()))
+                .collect(),
+        }
+    }
+}
+
+// Join the steps of a rule from step `i` with the bindings so far, pushing a
+// row of its head for each solution. If `delta` holds a step, that step only
+// reads the new rows, the steps before it only the old rows, and the steps
+// after it every row, so each combination of rows with a new one is joined
+// once.
+fn join(
+    rule: &Compiled,
+    i: usize,
+    delta: Option<(usize, &Delta)>,
+    tables: &HashMap<u64, Ref<FactTable<AtomType>>>,
+    bindings: &mut Vec<Option<Term<AtomType>>>,
+    out: &mut Vec<Row>,
+) {
+    let step = match rule.steps.get(i) {
+        Some(step) => step,
+        None => {
+            let row: Option<Row> = rule
+                .head
+                .iter()
+                .map(|arg| value(arg, bindings).cloned())
+                .collect();
+            if let Some(row) = row {
+                out.push(row);
+            }
+            return;
+        }
+    };
+    match step {
+        Step::Equals(left, right) => {
+            match (
+                value(left, bindings).cloned(),
+                value(right, bindings).cloned(),
+            ) {
+                (Some(a), Some(b)) => {
+                    if a == b {
+                        join(rule, i + 1, delta, tables, bindings, out);
+                    }
+                }
+                (Some(t), None) | (None, Some(t)) => {
+                    let unbound = [left, right].into_iter().find_map(|arg| match arg {
+                        Arg::Variable(v) if bindings[*v].is_none() => Some(*v),
+                        _ => None,
+                    });
+                    match unbound {
+                        Some(v) => {
+                            bindings[v] = Some(t);
+                            join(rule, i + 1, delta, tables, bindings, out);
+                            bindings[v] = None;
+                        }
+                        // `_` is equal to any term.
+                        None => join(rule, i + 1, delta, tables, bindings, out),
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        Step::Call(relation, args) => {
+            let mut solve = |row: &Row, bindings: &mut Vec<Option<Term<AtomType>>>| {
+                let mut bound = vec![];
+                let mut matches = true;
+                for (arg, term) in args.iter().zip(row) {
+                    match arg {
+                        Arg::Variable(v) => match &bindings[*v] {
+                            Some(value) => matches = value == term,
+                            None => {
+                                bindings[*v] = Some(term.clone());
+                                bound.push(*v);
+                            }
+                        },
+                        Arg::Constant(c) => matches = c == term,
+                        Arg::Anonymous => {}
+                    }
+                    if !matches {
+                        break;
+                    }
+                }
+                if matches {
+                    join(rule, i + 1, delta, tables, bindings, out);
+                }
+                for v in bound {
+                    bindings[v] = None;
+                }
+            };
+            match delta {
+                Some((j, delta)) if j == i => {
+                    for row in &delta.rows[relation] {
+                        solve(row, bindings);
+                    }
+                }
+                _ => {
+                    let table = &tables[relation];
+                    let new = match delta {
+                        Some((j, delta)) if i < j => delta.new.get(relation),
+                        _ => None,
+                    };
+                    let mut solve = |row: &Row, bindings: &mut Vec<Option<Term<AtomType>>>| {
+                        if !new.is_some_and(|new| new.contains(row)) {
+                            solve(row, bindings);
+                        }
+                    };
+                    // Use the index of the first bound argument, if any.
+                    let index = args.iter().enumerate().find_map(|(column, arg)| {
+                        value(arg, bindings).map(|v| (column, v.clone()))
+                    });
+                    match index {
+                        Some((column, value)) => {
+                            for row in table.lookup(column, &value) {
+                                solve(row, bindings);
+                            }
+                        }
+                        None => {
+                            for row in table.rows() {
+                                solve(row, bindings);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+type Table = Rc<RefCell<FactTable<AtomType>>>;
+
+// Whether a rule derives `row`, joining its body with its head bound to the
+// row.
+fn derives(rule: &Compiled, row: &Row, tables: &HashMap<u64, Ref<FactTable<AtomType>>>) -> bool {
+    let mut bindings = vec![None; rule.variables];
+    for (arg, term) in rule.head.iter().zip(row) {
+        match arg {
+            Arg::Variable(v) => match &bindings[*v] {
+                Some(value) if value != term => return false,
+                Some(_) => {}
+                None => bindings[*v] = Some(term.clone()),
+            },
+            Arg::Constant(c) if c != term => return false,
+            _ => {}
+        }
+    }
+    let mut out = vec![];
+    join(rule, 0, None, tables, &mut bindings, &mut out);
+    !out.is_empty()
+}
+
+// A materialised relation, together with the relations defined by rules that
+// it depends upon. The derived tables are kept up to date as rows are added
+// to and removed from the fact relations they read: new rows are propagated
+// semi-naively, and removed rows by deleting every row that may depend upon
+// them, then rederiving those that still hold.
+pub struct View {
+    rules: Vec<Compiled>,
+    base: HashMap<u64, Table>,
+    derived: HashMap<u64, Table>,
+}
+
+impl View {
+    fn tables(&self) -> HashMap<u64, Ref<'_, FactTable<AtomType>>> {
+        self.base
+            .iter()
+            .chain(self.derived.iter())
+            .map(|(relation, table)| (*relation, table.borrow()))
+            .collect()
+    }
+
+    // Join each rule with the rows in `delta`, or in full if it is None,
+    // returning the rows derived for each relation.
+    fn join(&self, delta: Option<&HashMap<u64, Vec<Row>>>) -> Vec<(u64, Vec<Row>)> {
+        let tables = self.tables();
+        let delta = delta.map(Delta::new);
+        let mut rows = vec![];
+        for rule in &self.rules {
+            let mut out = vec![];
+            let mut bindings = vec![None; rule.variables];
+            match &delta {
+                None => join(rule, 0, None, &tables, &mut bindings, &mut out),
+                Some(delta) => {
+                    // Each call that has new rows is joined with them.
+                    for (i, step) in rule.steps.iter().enumerate() {
+                        if let Step::Call(relation, _) = step {
+                            if delta.rows.contains_key(relation) {
+                                join(rule, 0, Some((i, delta)), &tables, &mut bindings, &mut out);
+                            }
+                        }
+                    }
+                }
+            }
+            rows.push((rule.relation, out));
+        }
+        rows
+    }
+
+    // Derive rows until no new ones are found, starting from the rows just
+    // added in `delta`, or by evaluating every rule in full if it is None.
+    fn derive(&self, mut delta: Option<HashMap<u64, Vec<Row>>>) {
+        loop {
+            let mut new: HashMap<u64, Vec<Row>> = HashMap::new();
+            for (relation, rows) in self.join(delta.as_ref()) {
+                let mut table = self.derived[&relation].borrow_mut();
+                for row in rows {
+                    if table.insert(row.clone()) {
+                        new.entry(relation).or_default().push(row);
+                    }
+                }
+            }
+            if new.is_empty() {
+                return;
+            }
+            delta = Some(new);
+        }
+    }
+
+    // Find the derived rows with a derivation that uses a row in `deleted`,
+    // which must still be in its table.
+    fn overdelete(&self, deleted: HashMap<u64, Vec<Row>>) -> HashMap<u64, HashSet<Row>> {
+        let mut removed: HashMap<u64, HashSet<Row>> = HashMap::new();
+        let mut delta = deleted;
+        while !delta.is_empty() {
+            let rows = self.join(Some(&delta));
+            delta = HashMap::new();
+            for (relation, rows) in rows {
+                let removed = removed.entry(relation).or_default();
+                for row in rows {
+                    if removed.insert(row.clone()) {
+                        delta.entry(relation).or_default().push(row);
+                    }
+                }
+            }
+        }
+        removed
+    }
+
+    // Remove the rows found by `overdelete` once the deleted rows are gone,
+    // then add back those that still have a derivation, and the rows that
+    // follow from them.
+    fn rederive(&self, removed: HashMap<u64, HashSet<Row>>) {
+        for (relation, rows) in &removed {
+            let mut table = self.derived[relation].borrow_mut();
+            for row in rows {
+                table.remove(row);
+            }
+        }
+        let mut rederived = vec![];
+        {
+            let tables = self.tables();
+            for rule in &self.rules {
+                if let Some(rows) = removed.get(&rule.relation) {
+                    for row in rows {
+                        if derives(rule, row, &tables) {
+                            rederived.push((rule.relation, row.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        let mut delta: HashMap<u64, Vec<Row>> = HashMap::new();
+        for (relation, row) in rederived {
+            if self.derived[&relation].borrow_mut().insert(row.clone()) {
+                delta.entry(relation).or_default().push(row);
+            }
+        }
+        if !delta.is_empty() {
+            self.derive(Some(delta));
+        }
+    }
+}
+
+// Find the materialised relations that read `table`, with the variables it is
+// bound to in each. Views of relations that have since been redefined are
+// dropped.
+fn views<'a>(
+    vm: &'a mut VirtualMachine,
+    table: &Table,
+) -> Result<Vec<(&'a View, Vec<u64>)>, String> {
+    let env = &vm.env;
+    vm.views.retain(
+        |id, view| matches!(env.get(id), Some(Value::Facts(t)) if Rc::ptr_eq(t, &view.derived[id])),
+    );
+    if let Some(id) = vm
+        .views
+        .iter()
+        .find_map(|(id, view)| Rc::ptr_eq(table, &view.derived[id]).then_some(*id))
+    {
+        return Err(format!(
+            "{} is materialised, so its rows can't be changed.",
+            vm.lookup_variable(&id).cloned().unwrap_or_default()
+        ));
+    }
+    Ok(vm
+        .views
+        .values()
+        .filter_map(|view| {
+            let relations: Vec<u64> = view
+                .base
+                .iter()
+                .filter(|(_, t)| Rc::ptr_eq(table, t))
+                .map(|(relation, _)| *relation)
+                .collect();
+            (!relations.is_empty()).then_some((view, relations))
+        })
+        .collect())
+}
+
+// Add rows to a fact relation, and propagate them to the materialised
+// relations that read it. Returns the number of new rows.
+pub fn assert(vm: &mut VirtualMachine, table: &Table, rows: Vec<Row>) -> Result<i64, String> {
+    let views = views(vm, table)?;
+    let mut added = vec![];
+    for row in rows {
+        if table.borrow_mut().insert(row.clone()) {
+            added.push(row);
+        }
+    }
+    if !added.is_empty() {
+        for (view, relations) in views {
+            let delta = relations
+                .into_iter()
+                .map(|relation| (relation, added.clone()))
+                .collect();
+            view.derive(Some(delta));
+        }
+    }
+    Ok(added.len() as i64)
+}
+
+// Remove rows from a fact relation, and the rows derived from them from the
+// materialised relations that read it. Returns the number of rows removed.
+pub fn retract(vm: &mut VirtualMachine, table: &Table, rows: Vec<Row>) -> Result<i64, String> {
+    let views = views(vm, table)?;
+    let mut deleted: Vec<Row> = vec![];
+    for row in rows {
+        if table.borrow().contains(&row) && !deleted.contains(&row) {
+            deleted.push(row);
+        }
+    }
+    if deleted.is_empty() {
+        return Ok(0);
+    }
+    // Every view must see the deleted rows before any are removed.
+    let removed: Vec<_> = views
+        .iter()
+        .map(|(view, relations)| {
+            view.overdelete(
+                relations
+                    .iter()
+                    .map(|relation| (*relation, deleted.clone()))
+                    .collect(),
+            )
+        })
+        .collect();
+    for row in &deleted {
+        table.borrow_mut().remove(row);
+    }
+    for ((view, _), removed) in views.iter().zip(removed) {
+        view.rederive(removed);
+    }
+    Ok(deleted.len() as i64)
+}
+
+// Materialise the relation bound to `id`, together with the relations
+// defined by rules that it depends upon, and bind it to a fact table of its
+// rows that is kept up to date as facts are asserted and retracted. Returns
+// the number of rows.
+pub fn materialise(vm: &mut VirtualMachine, id: u64) -> Result<i64, String> {
+    let name = |vm: &VirtualMachine, id: u64| vm.lookup_variable(&id).cloned().unwrap_or_default();
+    let mut definitions: Vec<(u64, Rc<Definition>)> = vec![];
+    let mut base: HashMap<u64, Table> = HashMap::new();
+    let mut pending = vec![id];
+    while let Some(next) = pending.pop() {
+        if base.contains_key(&next) || definitions.iter().any(|(relation, _)| *relation == next) {
+            continue;
+        }
+        match vm.rules.get(&next) {
+            Some(Ok(definition)) => {
+                pending.extend(definition.relations.values());
+                definitions.push((next, definition.clone()));
+            }
+            rules => match vm.env.get(&next) {
+                Some(Value::Facts(table)) => {
+                    base.insert(next, table.clone());
+                }
+                _ => {
+                    return Err(match rules {
+                        Some(Err(reason)) => {
+                            format!("{} can't be materialised: {}", name(vm, next), reason)
+                        }
+                        _ => format!(
+                        "{} can't be materialised, as it isn't defined by facts or Datalog rules.",
+                        name(vm, next)
+                    ),
+                    })
+                }
+            },
+        }
+    }
+    // A fact relation is already materialised.
+    if let Some(table) = base.get(&id) {
+        return Ok(table.borrow().len() as i64);
+    }
+
+    let mut arities: HashMap<u64, usize> = base
+        .iter()
+        .map(|(relation, table)| (*relation, table.borrow().arity))
+        .collect();
+    for (relation, definition) in &definitions {
+        arities.insert(*relation, definition.arity);
+    }
+    let mut rules = vec![];
+    for (relation, definition) in &definitions {
+        for rule in &definition.rules {
+            for goal in &rule.body {
+                if let Goal::Literal(literal) = goal {
+                    let arity = arities[&definition.relations[&literal.relation]];
+                    if arity != literal.args.len() {
+                        return Err(format!(
+                            "Expected {} argument(s) to {} in {}.",
+                            arity,
+                            literal.relation,
+                            name(vm, *relation)
+                        ));
+                    }
+                }
+            }
+            rules.push(compile(rule, *relation, definition, vm));
+        }
+    }
+
+    let view = View {
+        rules,
+        base,
+        derived: definitions
+            .iter()
+            .map(|(relation, definition)| {
+                (
+                    *relation,
+                    Rc::new(RefCell::new(FactTable::new(definition.arity))),
+                )
+            })
+            .collect(),
+    };
+    view.derive(None);
+    let table = view.derived[&id].clone();
+    let count = table.borrow().len() as i64;
+    vm.env.insert(id, Value::Facts(table));
+    vm.views.insert(id, view);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Ref, RefCell};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::answer::Outcome;
+    use crate::bottomup::*;
+    use crate::errors::Error;
+    use crate::Interpreter;
+
+    fn run(tern: &mut Interpreter, src: &str) -> String {
+        match tern.eval(src) {
+            Ok(Some(Outcome::Value(value))) => value.to_string(),
+            Err(Error::Runtime(err)) => err.msg,
+            _ => panic!("expected a value from {}", src),
+        }
+    }
+
+    #[test]
+    fn materialise() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Edge(1, 2). Edge(2, 3). Edge(3, 1). Edge(3, 4).
+             rel Path(x, y) {
+                 disj { Edge(x, y) | var (z) { conj { Edge(x, z), Path(z, y) } } }
+             }
+             Sink(x) :- Path(_, x), x == 4.
+             Start(x, 'start) :- Edge(x, _), x == 1.
+             rel Pair(x, y) { conj { Sink(x), y == x } }
+             Closure(x, y) :- Edge(x, y).
+             Closure(x, z) :- Closure(x, y), Closure(y, z).",
+        )
+        .unwrap();
+        assert_eq!(run(&mut tern, "materialise(Path)"), "12");
+        assert_eq!(run(&mut tern, "run(y, Path(1, y))"), "[2, 3, 1, 4]");
+        assert_eq!(run(&mut tern, "run(x, Path(x, 4))"), "[3, 2, 1]");
+        assert_eq!(run(&mut tern, "materialise(Sink)"), "1");
+        assert_eq!(run(&mut tern, "run(x, Sink(x))"), "[4]");
+        assert_eq!(run(&mut tern, "materialise(Start)"), "1");
+        assert_eq!(run(&mut tern, "run(s, Start(1, s))"), "[start]");
+        assert_eq!(run(&mut tern, "materialise(Pair)"), "1");
+        assert_eq!(run(&mut tern, "run(y, Pair(4, y))"), "[4]");
+        // A rule that calls a derived relation twice joins the new rows of
+        // either call with the other.
+        assert_eq!(run(&mut tern, "materialise(Closure)"), "12");
+        assert_eq!(run(&mut tern, "run(y, Closure(4, y))"), "[]");
+        assert_eq!(run(&mut tern, "assert(Edge, 4, 1)"), "1");
+        assert_eq!(run(&mut tern, "length(Closure)"), "16");
+        assert_eq!(run(&mut tern, "retract(Edge, 3, 4)"), "1");
+        assert_eq!(run(&mut tern, "length(Closure)"), "12");
+        assert_eq!(run(&mut tern, "retract(Edge, 4, 1)"), "1");
+        assert_eq!(run(&mut tern, "length(Closure)"), "9");
+        // A fact relation is already materialised.
+        assert_eq!(run(&mut tern, "materialise(Edge)"), "3");
+    }
+
+    #[test]
+    fn maintenance() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Edge(1, 2). Edge(2, 3).
+             Path(x, y) :- Edge(x, y).
+             Path(x, z) :- Path(x, y), Edge(y, z).
+             Reach(y) :- Path(1, y).",
+        )
+        .unwrap();
+        assert_eq!(run(&mut tern, "materialise(Path)"), "3");
+        assert_eq!(run(&mut tern, "materialise(Reach)"), "2");
+        assert_eq!(run(&mut tern, "assert(Edge, 3, 4)"), "1");
+        assert_eq!(run(&mut tern, "assert(Edge, 3, 4)"), "0");
+        assert_eq!(run(&mut tern, "run(y, Path(1, y))"), "[2, 3, 4]");
+        assert_eq!(run(&mut tern, "run(y, Reach(y))"), "[2, 3, 4]");
+        // Fact declarations are propagated too.
+        tern.eval("Edge(4, 1).").unwrap();
+        assert_eq!(run(&mut tern, "length(Path)"), "16");
+        assert_eq!(run(&mut tern, "run(y, Reach(y))"), "[2, 3, 4, 1]");
+
+        assert_eq!(run(&mut tern, "retract(Edge, 4, 1)"), "1");
+        assert_eq!(run(&mut tern, "retract(Edge, 4, 1)"), "0");
+        assert_eq!(run(&mut tern, "length(Path)"), "6");
+        assert_eq!(run(&mut tern, "run(x, Path(x, 1))"), "[]");
+        // Rows with another derivation are kept.
+        assert_eq!(run(&mut tern, "assert(Edge, 1, 3)"), "1");
+        assert_eq!(run(&mut tern, "retract(Edge, 2, 3)"), "1");
+        assert_eq!(run(&mut tern, "length(Path)"), "4");
+        assert_eq!(run(&mut tern, "run(y, Reach(y))"), "[2, 3, 4]");
+        assert_eq!(run(&mut tern, "assert(Edge, 4, 4)"), "1");
+        assert_eq!(run(&mut tern, "retract(Edge, 4, 4)"), "1");
+        assert_eq!(run(&mut tern, "run(x, Path(x, 4))"), "[3, 1]");
+        // The maintained rows are those evaluated from scratch.
+        assert_eq!(run(&mut tern, "materialise(Path)"), "4");
+        assert_eq!(run(&mut tern, "materialise(Reach)"), "3");
+
+        assert_eq!(
+            run(&mut tern, "assert(Path, 4, 1)"),
+            "Path is materialised, so its rows can't be changed."
+        );
+        assert_eq!(
+            run(&mut tern, "retract(Edge, 1)"),
+            "TypeError: Fact has the wrong number of arguments."
+        );
+        assert_eq!(
+            run(&mut tern, "assert(Edge, 1, [2])"),
+            "TypeError: Expected constant in fact."
+        );
+        assert_eq!(run(&mut tern, "retract(Node, 1)"), "0");
+        assert_eq!(run(&mut tern, "assert(Node, 1)"), "1");
+        assert_eq!(run(&mut tern, "run(x, Node(x))"), "[1]");
+        // Redefining a materialised relation stops it being maintained.
+        tern.eval("rel Path(x, y) { Edge(y, x) }").unwrap();
+        assert_eq!(run(&mut tern, "assert(Edge, 4, 5)"), "1");
+        assert_eq!(run(&mut tern, "run(y, Path(5, y))"), "[4]");
+    }
+
+    #[test]
+    fn loading() {
+        let path = std::env::temp_dir().join(format!("tern-loading-{}.csv", std::process::id()));
+        // More rows than are added at once, with duplicates in different
+        // batches.
+        let rows: String = (0..10000)
+            .map(|i| format!("{},{}\n", i % 2, i % 5000))
+            .collect();
+        std::fs::write(&path, rows).unwrap();
+        let mut tern = Interpreter::new();
+        tern.eval("Edge(0, 0). Reach(y) :- Edge(1, y).").unwrap();
+        assert_eq!(run(&mut tern, "materialise(Reach)"), "0");
+        let src = format!("load_csv({:?}, Edge)", path.to_str().unwrap());
+        assert_eq!(run(&mut tern, &src), "4999");
+        assert_eq!(run(&mut tern, "length(Reach)"), "2500");
+
+        // No rows are loaded from a file with an error, even after the first
+        // batch.
+        let rows: String = (0..5000).map(|i| format!("1,{}\n", i + 5000)).collect();
+        std::fs::write(&path, rows + "1,2,3\n").unwrap();
+        assert_eq!(
+            run(&mut tern, &src),
+            format!("{}:5001: Expected 2 fields but found 3.", path.display())
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(run(&mut tern, "length(Edge)"), "5000");
+        assert_eq!(run(&mut tern, "length(Reach)"), "2500");
+
+        let path = path.with_extension("json");
+        let rows: String = (0..5000)
+            .map(|i| format!("{{\"from\": 1, \"to\": {}}}\n", i + 5000))
+            .collect();
+        std::fs::write(&path, rows + "{\"from\": 1}\n").unwrap();
+        let src = format!("load_json({:?}, Edge)", path.to_str().unwrap());
+        assert_eq!(
+            run(&mut tern, &src),
+            format!("{}: row 5001: Expected 2 keys but found 1.", path.display())
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(run(&mut tern, "length(Edge)"), "5000");
+    }
+
+    #[test]
+    fn errors() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "P('a).
+             rel Wrap(l) { var (x) { conj { P(x), l == [x] } } }
+             rel Open(x, y) { x == y }
+             fn f(x) { return x }",
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut tern, "materialise(Wrap)"),
+            "Wrap can't be materialised: Lists in Wrap have no Datalog equivalent."
+        );
+        assert_eq!(
+            run(&mut tern, "materialise(Open)"),
+            "Open can't be materialised: Variable x in a rule for Open isn't bound by a call to a relation."
+        );
+        assert_eq!(
+            run(&mut tern, "materialise(f)"),
+            "f can't be materialised, as it isn't defined by facts or Datalog rules."
+        );
+        tern.eval(
+            "Q(x, _) :- P(x).
+             rel R(x, y) { conj { P(x), y == _ } }",
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut tern, "materialise(Q)"),
+            "Q can't be materialised: `_` isn't allowed in the head of a rule for Q."
+        );
+        assert_eq!(
+            run(&mut tern, "materialise(R)"),
+            "R can't be materialised: `_` isn't allowed in the head of a rule for R."
+        );
+    }
+
+    #[test]
+    fn equals() {
+        // Q(x, y) :- B(y), _ == x, with x already bound, as when a row of Q
+        // is rederived: `_ == x` keeps the binding of x for every row of B.
+        let table = Rc::new(RefCell::new(FactTable::new(1)));
+        table.borrow_mut().insert(vec![Term::Atom(1)]);
+        table.borrow_mut().insert(vec![Term::Atom(2)]);
+        let tables: HashMap<u64, Ref<FactTable<AtomType>>> = [(7, table.borrow())].into();
+        let rule = Compiled {
+            relation: 8,
+            head: vec![Arg::Variable(0), Arg::Variable(1)],
+            steps: vec![
+                Step::Call(7, vec![Arg::Variable(1)]),
+                Step::Equals(Arg::Anonymous, Arg::Variable(0)),
+            ],
+            variables: 2,
+        };
+        let mut bindings = vec![Some(Term::Atom(3)), None];
+        let mut out = vec![];
+        join(&rule, 0, None, &tables, &mut bindings, &mut out);
+        assert_eq!(
+            out,
+            [
+                [Term::Atom(3), Term::Atom(1)],
+                [Term::Atom(3), Term::Atom(2)]
+            ]
+        );
+        assert_eq!(bindings, [Some(Term::Atom(3)), None]);
+    }
+
+    #[test]
+    fn delta() {
+        // Closure(x, z) :- Closure(x, y), Closure(y, z), where (3, 4) and
+        // (4, 5) were derived in the last round.
+        let edge = |x, y| vec![Term::Integer(x), Term::Integer(y)];
+        let table = Rc::new(RefCell::new(FactTable::new(2)));
+        for row in [edge(1, 2), edge(2, 3), edge(3, 4), edge(4, 5)] {
+            table.borrow_mut().insert(row);
+        }
+        let view = View {
+            rules: vec![Compiled {
+                relation: 7,
+                head: vec![Arg::Variable(0), Arg::Variable(2)],
+                steps: vec![
+                    Step::Call(7, vec![Arg::Variable(0), Arg::Variable(1)]),
+                    Step::Call(7, vec![Arg::Variable(1), Arg::Variable(2)]),
+                ],
+                variables: 3,
+            }],
+            base: HashMap::new(),
+            derived: [(7, table)].into(),
+        };
+        let delta = [(7, vec![edge(3, 4), edge(4, 5)])].into();
+        // (3, 4) joined with (4, 5) is found by the first call only, since
+        // the first call reads only the old rows when the second reads the
+        // new ones.
+        assert_eq!(view.join(Some(&delta)), [(7, vec![edge(3, 5), edge(2, 4)])]);
+    }
+}
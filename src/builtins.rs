@@ -0,0 +1,708 @@
+use crate::bottomup;
+use crate::unification::{reify, Term};
+use crate::vm::{AtomType, Value, VirtualMachine};
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+// The implementation of a native function, which receives its arguments and
+// pushes its results to the stack.
+pub type NativeFn = fn(&mut VirtualMachine, Vec<Value>) -> Result<(), String>;
+
+// A function implemented in Rust, which is called using the CallNative opcode
+// with the index of the function in BUILTINS.
+pub struct Builtin {
+    pub name: &'static str,
+    // The number of required arguments, followed by the number of optional
+    // arguments, or VARIADIC if any number of arguments can follow.
+    pub arity: usize,
+    pub optional: usize,
+    // The position of an argument that names a relation, e.g. the relation to
+    // load facts into. It is passed as the relation's variable rather than
+    // its value, and the relation is defined if it does not exist.
+    pub relation: Option<usize>,
+    // Whether the answers of the stream it returns report the free variables
+    // of its arguments, even if they are unbound, e.g. those of the goal
+    // passed to `solve`.
+    pub reports: bool,
+    pub function: NativeFn,
+}
+
+pub const VARIADIC: usize = usize::MAX;
+
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "solve",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: true,
+        function: solve,
+    },
+    Builtin {
+        name: "next",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: next,
+    },
+    Builtin {
+        name: "run",
+        arity: 2,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: run,
+    },
+    Builtin {
+        name: "length",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: length,
+    },
+    Builtin {
+        name: "keys",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: keys,
+    },
+    Builtin {
+        name: "insert",
+        arity: 3,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: insert,
+    },
+    Builtin {
+        name: "remove",
+        arity: 2,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: remove,
+    },
+    Builtin {
+        name: "print",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: print,
+    },
+    Builtin {
+        name: "println",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: println,
+    },
+    Builtin {
+        name: "format",
+        arity: 1,
+        optional: VARIADIC,
+        relation: None,
+        reports: false,
+        function: format,
+    },
+    Builtin {
+        name: "atom_name",
+        arity: 1,
+        optional: 0,
+        relation: None,
+        reports: false,
+        function: atom_name,
+    },
+    Builtin {
+        name: "load_csv",
+        arity: 2,
+        optional: 1,
+        relation: Some(1),
+        reports: false,
+        function: load_csv,
+    },
+    Builtin {
+        name: "load_json",
+        arity: 2,
+        optional: 0,
+        relation: Some(1),
+        reports: false,
+        function: load_json,
+    },
+    Builtin {
+        name: "materialise",
+        arity: 1,
+        optional: 0,
+        relation: Some(0),
+        reports: false,
+        function: materialise,
+    },
+    Builtin {
+        name: "assert",
+        arity: 1,
+        optional: VARIADIC,
+        relation: Some(0),
+        reports: false,
+        function: assert,
+    },
+    Builtin {
+        name: "retract",
+        arity: 1,
+        optional: VARIADIC,
+        relation: Some(0),
+        reports: false,
+        function: retract,
+    },
+];
+
+// Return the index of the builtin function `name`.
+pub fn lookup(name: &str) -> Option<usize> {
+    BUILTINS.iter().position(|builtin| builtin.name == name)
+}
+
+// Solve the goal to produce a stream.
+// Goal -> Stream
+fn solve(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    match args.pop() {
+        Some(Value::Goal(goal)) => {
+            let substs = HashMap::new();
+            vm.stack
+                .push(Value::Stream(goal.solve(&substs), Rc::new(vec![])));
+            Ok(())
+        }
+        _ => Err("TypeError: Expected goal.".to_string()),
+    }
+}
+
+// Call next on the stream, pushing a table to the stack, or None if there
+// are no more answers.
+// Stream -> Stream Table
+fn next(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    match args.pop() {
+        Some(Value::Stream(mut stream, variables)) => {
+            match stream.next() {
+                _ if vm.goal_error.borrow().is_some() => {
+                    return Err(vm.goal_error.take().unwrap_or_default())
+                }
+                Some(substs) => {
+                    let table = vm.answer_table(&substs, &variables);
+                    vm.stack.push(Value::Stream(stream, variables));
+                    vm.stack.push(table);
+                }
+                None => vm.stack.push(Value::None),
+            }
+            Ok(())
+        }
+        // TODO: Add value to error message...
+        _ => Err("Unexpected value.".to_string()),
+    }
+}
+
+// Solve the goal, pushing a tuple holding the value of the term in each
+// answer. Like `run*` in miniKanren, this does not terminate if the goal has
+// infinitely many answers.
+// Term Goal -> Term
+fn run(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    let goal = match args.pop() {
+        Some(Value::Goal(goal)) => goal,
+        _ => return Err("TypeError: Expected goal.".to_string()),
+    };
+    let term = match args.pop() {
+        Some(Value::Term(term)) => term,
+        _ => return Err("TypeError: Expected term.".to_string()),
+    };
+    let values = goal
+        .solve(&HashMap::new())
+        .map(|substs| reify(&term, &substs))
+        .collect();
+    vm.stack.push(Value::Term(Term::Tuple(values)));
+    Ok(())
+}
+
+// Push the number of elements of a tuple, characters of a string, fields of a
+// table or rows of a fact relation.
+// Value -> Term
+fn length(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    let length = match args.pop() {
+        Some(Value::Term(Term::Tuple(terms))) => terms.len(),
+        Some(Value::Term(Term::String(s))) => match vm.lookup_interned(&s) {
+            Some(s) => s.chars().count(),
+            None => 0,
+        },
+        Some(Value::Table(table)) => table.borrow().len(),
+        Some(Value::Facts(table)) => table.borrow().len(),
+        _ => return Err("TypeError: Expected tuple, string, table or relation.".to_string()),
+    };
+    vm.stack.push(Value::Term(Term::Integer(length as i64)));
+    Ok(())
+}
+
+// Push a tuple of the keys of a table, in the order of their names.
+// Table -> Term
+fn keys(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    match args.pop() {
+        Some(Value::Table(table)) => {
+            let mut keys: Vec<Term<AtomType>> = table.borrow().keys().cloned().collect();
+            keys.sort_by_key(|key| vm.term_to_string(key));
+            vm.stack.push(Value::Term(Term::Tuple(keys)));
+            Ok(())
+        }
+        _ => Err("TypeError: Expected table.".to_string()),
+    }
+}
+
+// Set a field of a table, pushing the value it replaced, or None if the
+// field is new.
+// Table Term Value -> Value
+fn insert(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    let value = match args.pop() {
+        Some(Value::Stream(..)) => {
+            return Err("TypeError: Streams can't be stored in tables.".to_string())
+        }
+        Some(value) => value,
+        None => return Err("Stack underflow.".to_string()),
+    };
+    let key = match args.pop() {
+        Some(Value::Term(key)) => key,
+        _ => return Err("TypeError: Expected term.".to_string()),
+    };
+    match args.pop() {
+        Some(Value::Table(table)) => {
+            let old = table.borrow_mut().insert(key, value);
+            vm.stack.push(old.unwrap_or(Value::None));
+            Ok(())
+        }
+        _ => Err("TypeError: Expected table.".to_string()),
+    }
+}
+
+// Remove a field from a table, pushing its value, or None if there was no
+// such field.
+// Table Term -> Value
+fn remove(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    let key = match args.pop() {
+        Some(Value::Term(key)) => key,
+        _ => return Err("TypeError: Expected term.".to_string()),
+    };
+    match args.pop() {
+        Some(Value::Table(table)) => {
+            let old = table.borrow_mut().remove(&key);
+            vm.stack.push(old.unwrap_or(Value::None));
+            Ok(())
+        }
+        _ => Err("TypeError: Expected table.".to_string()),
+    }
+}
+
+// Write a value to the output, and push an empty table.
+// Value -> Table
+fn print(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    let value = args.pop().ok_or("Stack underflow.")?;
+    let text = vm.display(&value);
+    write!(vm.output, "{}", text).map_err(|err| err.to_string())?;
+    vm.stack.push(Value::new_table(HashMap::new()));
+    Ok(())
+}
+
+// Write a value to the output on its own line, and push an empty table.
+// Value -> Table
+fn println(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    let value = args.pop().ok_or("Stack underflow.")?;
+    let text = vm.display(&value);
+    writeln!(vm.output, "{}", text).map_err(|err| err.to_string())?;
+    vm.stack.push(Value::new_table(HashMap::new()));
+    Ok(())
+}
+
+// Replace each `{}` in the format string with the next argument, and push
+// the result as a string. `{{` and `}}` stand for literal braces.
+// Term value1 value2 ... valuen -> Term
+fn format(vm: &mut VirtualMachine, args: Vec<Value>) -> Result<(), String> {
+    let mut args = args.into_iter();
+    let template = match args.next() {
+        Some(Value::Term(Term::String(s))) => vm.lookup_interned(&s).cloned().unwrap_or_default(),
+        _ => return Err("TypeError: Expected string as format string.".to_string()),
+    };
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                text.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                match args.next() {
+                    Some(value) => text += &vm.display(&value),
+                    None => return Err("Too few arguments for format string.".to_string()),
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    if args.next().is_some() {
+        return Err("Too many arguments for format string.".to_string());
+    }
+    let s = vm.intern(&text);
+    vm.stack.push(Value::Term(Term::String(s)));
+    Ok(())
+}
+
+// Push the name of an atom as a string.
+// Term -> Term
+fn atom_name(vm: &mut VirtualMachine, mut args: Vec<Value>) -> Result<(), String> {
+    match args.pop() {
+        // Atoms and strings share the same interned names.
+        Some(Value::Term(Term::Atom(a))) => {
+            vm.stack.push(Value::Term(Term::String(a)));
+            Ok(())
+        }
+        _ => Err("TypeError: Expected atom.".to_string()),
+    }
+}
+
+// Return the filename and relation arguments of a load function.
+fn load_args(vm: &VirtualMachine, args: &[Value]) -> Result<(String, u64), String> {
+    let path = match &args[0] {
+        Value::Term(Term::String(s)) => match vm.lookup_interned(s) {
+            Some(path) => path.to_string(),
+            None => s.to_string(),
+        },
+        _ => return Err("TypeError: Expected string as filename.".to_string()),
+    };
+    match &args[1] {
+        Value::Term(Term::Variable(key)) => Ok((path, *key)),
+        _ => Err("TypeError: Expected variable.".to_string()),
+    }
+}
+
+// Load the rows of a CSV file into the fact relation `name`, skipping the
+// first row if 'header is passed, and push the number of rows loaded.
+// filename name ['header] -> Term
+fn load_csv(vm: &mut VirtualMachine, args: Vec<Value>) -> Result<(), String> {
+    let (path, key) = load_args(vm, &args)?;
+    let header = match args.get(2) {
+        None => false,
+        Some(Value::Term(Term::Atom(a)))
+            if vm.lookup_interned(a).map(|s| s.as_str()) == Some("header") =>
+        {
+            true
+        }
+        Some(_) => return Err("Expected 'header as third argument to load_csv.".to_string()),
+    };
+    let count = vm.load_csv(&path, key, header)?;
+    vm.stack.push(Value::Term(Term::Integer(count)));
+    Ok(())
+}
+
+// Load the objects in a JSON file into the fact relation `name`, and push
+// the number of rows loaded.
+// filename name -> Term
+fn load_json(vm: &mut VirtualMachine, args: Vec<Value>) -> Result<(), String> {
+    let (path, key) = load_args(vm, &args)?;
+    let count = vm.load_json(&path, key)?;
+    vm.stack.push(Value::Term(Term::Integer(count)));
+    Ok(())
+}
+
+// Evaluate the relation `name` bottom-up and bind it to a fact table of its
+// rows, and push the number of rows.
+// name -> Term
+fn materialise(vm: &mut VirtualMachine, args: Vec<Value>) -> Result<(), String> {
+    let key = match &args[0] {
+        Value::Term(Term::Variable(key)) => *key,
+        _ => return Err("TypeError: Expected variable.".to_string()),
+    };
+    let count = bottomup::materialise(vm, key)?;
+    vm.stack.push(Value::Term(Term::Integer(count)));
+    Ok(())
+}
+
+// Return the relation and row of constants passed to assert or retract.
+fn fact_args(args: Vec<Value>) -> Result<(u64, Vec<Term<AtomType>>), String> {
+    let mut args = args.into_iter();
+    let key = match args.next() {
+        Some(Value::Term(Term::Variable(key))) => key,
+        _ => return Err("TypeError: Expected variable.".to_string()),
+    };
+    let row = args
+        .map(|arg| match arg {
+            Value::Term(term @ (Term::Atom(_) | Term::Integer(_) | Term::String(_))) => Ok(term),
+            _ => Err("TypeError: Expected constant in fact.".to_string()),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((key, row))
+}
+
+// Add a row to the fact relation `name`, defining it if it does not exist,
+// and push the number of new rows.
+// name args... -> Term
+fn assert(vm: &mut VirtualMachine, args: Vec<Value>) -> Result<(), String> {
+    let (key, row) = fact_args(args)?;
+    let table = vm.fact_table(key, row.len())?;
+    let count = bottomup::assert(vm, &table, vec![row])?;
+    vm.stack.push(Value::Term(Term::Integer(count)));
+    Ok(())
+}
+
+// Remove a row from the fact relation `name`, and push the number of rows
+// removed.
+// name args... -> Term
+fn retract(vm: &mut VirtualMachine, args: Vec<Value>) -> Result<(), String> {
+    let (key, row) = fact_args(args)?;
+    let count = match vm.env.get(&key) {
+        None => 0,
+        Some(Value::Facts(_)) => {
+            let table = vm.fact_table(key, row.len())?;
+            bottomup::retract(vm, &table, vec![row])?
+        }
+        Some(_) => return Err("TypeError: Expected fact relation.".to_string()),
+    };
+    vm.stack.push(Value::Term(Term::Integer(count)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::answer::{Outcome, Value};
+    use crate::errors::Error;
+    use crate::Interpreter;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    // An output sink whose contents can be read after it is handed to the
+    // interpreter.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn eval(tern: &mut Interpreter, src: &str) -> Value {
+        match tern.eval(src) {
+            Ok(Some(Outcome::Value(value))) => value,
+            _ => panic!("expected a value from {}", src),
+        }
+    }
+
+    #[test]
+    fn shared_tables() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('terach, 'nachor).
+            Father('terach, 'haran).
+            rel Member(x, l) {
+                disj { l == [x | _] | var (t) { conj { l == [_ | t], Member(x, t) } } }
+            }",
+        )
+        .unwrap();
+        // Two bindings of the same table see each other's changes.
+        tern.eval(
+            "let t = {'a: 1}
+            let u = t
+            insert(u, 'b, 2)
+            t.c = 3",
+        )
+        .unwrap();
+        assert_eq!(eval(&mut tern, "keys(t)").to_string(), "[a, b, c]");
+        assert_eq!(eval(&mut tern, "keys(u)").to_string(), "[a, b, c]");
+        // So does a table reached through another table or a parameter.
+        tern.eval(
+            "let outer = {'inner: t}
+            let inner = outer.inner
+            inner.d = 4
+            fn clear(table, k) { remove(table, k) }
+            clear(outer.inner, 'a)",
+        )
+        .unwrap();
+        assert_eq!(eval(&mut tern, "keys(u)").to_string(), "[b, c, d]");
+        assert_eq!(eval(&mut tern, "t.d").as_integer(), Some(4));
+
+        // A table can be changed while the answers of a stream are
+        // enumerated, e.g. to accumulate them.
+        tern.eval(
+            "let sons = {}
+            for a in solve(Father('terach, x)) { sons[a.x] = 'terach }",
+        )
+        .unwrap();
+        assert_eq!(
+            eval(&mut tern, "keys(sons)").to_string(),
+            "[abraham, haran, nachor]"
+        );
+        // `keys` returns the keys when it is called, so removing them while
+        // iterating over them visits every key.
+        tern.eval(
+            "let seen = {}
+            for a in solve(Member(k, keys(sons))) {
+                remove(sons, a.k)
+                seen[a.k] = length(sons)
+            }",
+        )
+        .unwrap();
+        assert_eq!(eval(&mut tern, "length(sons)").as_integer(), Some(0));
+        assert_eq!(eval(&mut tern, "seen.abraham").as_integer(), Some(2));
+        assert_eq!(eval(&mut tern, "seen.haran").as_integer(), Some(1));
+        assert_eq!(eval(&mut tern, "seen.nachor").as_integer(), Some(0));
+    }
+
+    #[test]
+    fn builtins() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('terach, 'nachor).
+            Father('terach, 'haran).",
+        )
+        .unwrap();
+        let sons = eval(&mut tern, "run(x, Father('terach, x))");
+        assert_eq!(sons.to_string(), "[abraham, nachor, haran]");
+        assert_eq!(
+            eval(&mut tern, "length(run(x, Father('terach, x)))").as_integer(),
+            Some(3)
+        );
+        assert_eq!(eval(&mut tern, "length(\"héllo\")").as_integer(), Some(5));
+        assert_eq!(
+            eval(&mut tern, "length({'a: 'b, 'c: 'd})").as_integer(),
+            Some(2)
+        );
+        assert_eq!(
+            eval(&mut tern, "keys({'c: 'd, 'a: 'b})").to_string(),
+            "[a, c]"
+        );
+        assert_eq!(
+            eval(&mut tern, "atom_name('abraham)").as_str(),
+            Some("abraham")
+        );
+        tern.eval("let t = {'a: 'b}").unwrap();
+        assert_eq!(eval(&mut tern, "insert(t, 'a, 'c)").as_atom(), Some("b"));
+        assert_eq!(eval(&mut tern, "remove(t, 'a)").as_atom(), Some("c"));
+        assert_eq!(tern.eval("remove(t, 'a)").unwrap(), Some(Outcome::No));
+        assert_eq!(eval(&mut tern, "length(t)").as_integer(), Some(0));
+        let output = Output::default();
+        tern.set_output(output.clone());
+        match tern.eval("print('abraham)") {
+            Ok(Some(Outcome::Answer(answer))) => assert!(answer.is_empty()),
+            _ => assert!(false),
+        }
+        assert_eq!(output.0.borrow().as_slice(), b"abraham");
+
+        match tern.eval("length('abraham)") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(
+                    err.msg,
+                    "TypeError: Expected tuple, string, table or relation."
+                )
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("atom_name(\"abraham\")") {
+            Err(Error::Runtime(err)) => assert_eq!(err.msg, "TypeError: Expected atom."),
+            _ => assert!(false),
+        }
+        match tern.eval("solve()") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Expected 1 argument to solve."),
+            _ => assert!(false),
+        }
+        match tern.eval("load_csv(\"family.csv\")") {
+            Err(Error::Syntax(err)) => {
+                assert_eq!(err.msg, "Expected 2 to 3 arguments to load_csv.")
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("load_json(\"family.json\", 'father)") {
+            Err(Error::Syntax(err)) => {
+                assert_eq!(
+                    err.msg,
+                    "Expected relation name as argument 2 to load_json."
+                )
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn output() {
+        let mut tern = Interpreter::new();
+        let output = Output::default();
+        tern.set_output(output.clone());
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('terach, 'nachor).
+            for answer in solve(Father(x, y)) {
+                println(format(\"{} is father of {}\", answer.x, answer.y))
+            }
+            print(\"a string, \")
+            print(42)
+            println(\"\")
+            println(next(solve(Father(x, 'nachor))))",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "terach is father of abraham\nterach is father of nachor\na string, 42\n{x: terach}\n"
+        );
+        assert_eq!(
+            eval(&mut tern, "format(\"{{{}}} {}\", ['a, \"b\"], 'c)").as_str(),
+            Some("{[a, \"b\"]} c")
+        );
+
+        match tern.eval("format(\"{} {}\", 'a)") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "Too few arguments for format string.")
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("format(\"{}\", 'a, 'b)") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "Too many arguments for format string.")
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("format('a)") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "TypeError: Expected string as format string.")
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("format()") {
+            Err(Error::Syntax(err)) => {
+                assert_eq!(err.msg, "Expected at least 1 argument to format.")
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn retract_while_iterating() {
+        let mut tern = Interpreter::new();
+        let output = Output::default();
+        tern.set_output(output.clone());
+        // The answers to a goal are those of the rows when it was solved.
+        tern.eval(
+            "E(1). E(2). E(3). E(4).
+            for a in solve(E(x)) {
+                retract(E, a.x)
+                println(a.x)
+            }",
+        )
+        .unwrap();
+        assert_eq!(output.0.borrow().as_slice(), b"1\n2\n3\n4\n");
+        assert_eq!(eval(&mut tern, "run(x, E(x))").to_string(), "[]");
+    }
+}
@@ -1,17 +1,58 @@
+use crate::bottomup;
+use crate::builtins;
 use crate::errors::SyntaxError;
-use crate::parser::AST;
+use crate::module;
+use crate::parser::{Clause, AST};
 use crate::vm::{CallableKind, Opcode, VirtualMachine};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::rc::Rc;
 
 pub struct Context {
     pub bindings: Vec<HashMap<String, u64>>,
+    // Free variables of the top-level statement being generated. These are
+    // implicitly fresh and scoped to that statement.
+    pub free_variables: HashMap<String, u64>,
+    // Each reference to a free variable, in order, to find the variables a
+    // goal refers to once it is generated.
+    pub references: Vec<u64>,
+    // Nesting depth of the relation bodies being generated. Variables used
+    // in a relation body must be declared, and are never reported in answers.
+    pub relation_depth: usize,
+    // Index of the first scope of the function body being generated, or 0
+    // at the top level. Let bindings in a function are local to its call.
+    pub function_scope: usize,
+    // The loops being generated, innermost last, with whether each keeps a
+    // stream on the stack and the positions of its breaks, which jump to
+    // the end of the loop once it is generated.
+    pub loops: Vec<(bool, Vec<usize>)>,
+    // The modules imported by any module, the directory that imports are
+    // relative to, and the modules imported with `as`, by name.
+    pub modules: Rc<RefCell<module::Modules>>,
+    pub directory: PathBuf,
+    pub namespaces: HashMap<String, module::Exports>,
+    // The clauses of the relations defined by clauses, by variable, which
+    // later clauses for the same relation are added to, and the variables
+    // of the relations defined by facts.
+    pub clauses: HashMap<u64, Vec<Clause>>,
+    pub facts: HashSet<u64>,
 }
 
 impl Context {
     pub fn new() -> Context {
         Context {
             bindings: vec![HashMap::new()],
+            free_variables: HashMap::new(),
+            references: Vec::new(),
+            relation_depth: 0,
+            function_scope: 0,
+            loops: Vec::new(),
+            modules: Rc::new(RefCell::new(module::Modules::default())),
+            directory: PathBuf::from("."),
+            namespaces: HashMap::new(),
+            clauses: HashMap::new(),
+            facts: HashSet::new(),
         }
     }
 
@@ -24,6 +65,17 @@ impl Context {
         None
     }
 
+    // Look up `s` in the scopes of the current function body, or in every
+    // scope at the top level.
+    pub fn lookup_local(&self, s: &str) -> Option<u64> {
+        for binding in self.bindings[self.function_scope..].iter().rev() {
+            if let Some(id) = binding.get(s) {
+                return Some(*id);
+            }
+        }
+        None
+    }
+
     pub fn push(&mut self) {
         self.bindings.push(HashMap::new())
     }
@@ -42,6 +94,33 @@ impl Context {
             .unwrap()
             .insert(value.to_string(), id);
     }
+
+    // Look up a relation of an imported module, e.g. `f.Father`.
+    pub fn qualified(&self, name: &str) -> Option<u64> {
+        let (module, name) = name.split_once('.')?;
+        self.namespaces.get(module)?.get(name).copied()
+    }
+
+    // Look up the free variable `name` of the current statement, creating
+    // a fresh variable the first time it is used.
+    pub fn free_variable(&mut self, name: &str, vm: &mut VirtualMachine) -> u64 {
+        let id = match self.free_variables.get(name) {
+            Some(id) => *id,
+            None => {
+                let id = vm.new_variable(&name.to_string());
+                self.free_variables.insert(name.to_string(), id);
+                id
+            }
+        };
+        self.references.push(id);
+        id
+    }
+
+    // Forget the free variables of the statement just generated.
+    pub fn clear_free_variables(&mut self) {
+        self.free_variables.clear();
+        self.references.clear();
+    }
 }
 
 pub fn generate(
@@ -81,8 +160,12 @@ pub fn generate(
         AST::Var(declarations, body) => {
             ctx.push();
             for declaration in declarations {
-                if let AST::Variable(v) = declaration {
-                    let id = vm.new_variable(v);
+                if let AST::Variable(v, _) = declaration {
+                    let id = if ctx.relation_depth > 0 {
+                        vm.new_hidden_variable(v)
+                    } else {
+                        vm.new_variable(v)
+                    };
                     ctx.insert(id, v);
                 } else {
                     unreachable!()
@@ -94,44 +177,129 @@ pub fn generate(
         AST::Atom(s) => {
             instr.push(Opcode::Atom(vm.intern(s)));
         }
-        AST::Variable(v) => {
+        AST::Variable(v, offset) => {
             if let Some(id) = ctx.lookup(v) {
                 instr.push(Opcode::Variable(id));
+                // Inside a relation, names that are not logic variables refer
+                // to values, e.g. the captured arguments of a function.
+                if ctx.relation_depth > 0 && !vm.hidden.contains(&id) {
+                    instr.push(Opcode::GetEnv);
+                }
+            } else if ctx.relation_depth > 0 {
+                let msg = "Undeclared variable: ".to_string() + v;
+                return Err(SyntaxError {
+                    msg,
+                    offset: *offset,
+                });
             } else {
-                let id = vm.new_variable(v);
-                ctx.insert(id, v);
-                instr.push(Opcode::Variable(id));
+                instr.push(Opcode::Variable(ctx.free_variable(v, vm)));
             }
         }
+        AST::Integer(n) => {
+            instr.push(Opcode::Integer(*n));
+        }
+        AST::String(s) => {
+            instr.push(Opcode::String(vm.intern(s)));
+        }
+        AST::AnonymousVariable => {
+            // Every occurrence of `_` is a distinct variable.
+            let id = vm.new_anonymous_variable();
+            instr.push(Opcode::Variable(id));
+        }
         AST::FnCall(name, args, offset) => {
-            for arg in args {
-                generate(arg, ctx, vm, instr)?;
-            }
-            // So far, we just have two builtin functions...
-            if name == "solve" {
-                instr.push(Opcode::Solve);
-            } else if name == "next" {
-                instr.push(Opcode::Next);
-            } else {
-                // TODO: We're requiring functions to be defined before use here,
-                // is this the behaviour we want?
-                if let Some(id) = ctx.lookup(name) {
-                    instr.push(Opcode::Variable(id));
-                    instr.push(Opcode::GetEnv);
-                    instr.push(Opcode::Call);
+            // TODO: We're requiring functions to be defined before use here,
+            // is this the behaviour we want?
+            if let Some(id) = ctx
+                .lookup(name)
+                .or_else(|| vm.globals.get(name).copied())
+                .or_else(|| ctx.qualified(name))
+            {
+                for arg in args {
+                    generate(arg, ctx, vm, instr)?;
+                }
+                instr.push(Opcode::Variable(id));
+                if ctx.relation_depth > 0 && vm.hidden.contains(&id) {
+                    // The relation is bound to a logic variable, e.g. a
+                    // parameter, so it is only known once the goal is solved.
+                    instr.push(Opcode::Apply(args.len()));
                 } else {
-                    let msg = "Undefined function: ".to_string() + name;
+                    instr.push(Opcode::GetEnv);
+                    instr.push(Opcode::Call(args.len()));
+                }
+            } else if let Some(index) = builtins::lookup(name) {
+                let builtin = &builtins::BUILTINS[index];
+                if args.len() < builtin.arity
+                    || args.len() > builtin.arity.saturating_add(builtin.optional)
+                {
+                    let msg = match builtin.optional {
+                        0 if builtin.arity == 1 => format!("Expected 1 argument to {}.", name),
+                        0 => format!("Expected {} arguments to {}.", builtin.arity, name),
+                        builtins::VARIADIC if builtin.arity == 1 => {
+                            format!("Expected at least 1 argument to {}.", name)
+                        }
+                        builtins::VARIADIC => {
+                            format!("Expected at least {} arguments to {}.", builtin.arity, name)
+                        }
+                        n => format!(
+                            "Expected {} to {} arguments to {}.",
+                            builtin.arity,
+                            builtin.arity + n,
+                            name
+                        ),
+                    };
                     return Err(SyntaxError {
                         msg,
                         offset: *offset,
                     });
                 }
+                let references = ctx.references.len();
+                for (i, arg) in args.iter().enumerate() {
+                    if builtin.relation != Some(i) {
+                        generate(arg, ctx, vm, instr)?;
+                    } else if let AST::BindingRef(relation) = arg {
+                        if let Some(id) = ctx.lookup(relation) {
+                            instr.push(Opcode::Variable(id));
+                        } else {
+                            let id = vm.new_variable(relation);
+                            ctx.insert(id, relation);
+                            instr.push(Opcode::Variable(id));
+                        }
+                    } else {
+                        return Err(SyntaxError {
+                            msg: format!(
+                                "Expected relation name as argument {} to {}.",
+                                i + 1,
+                                name
+                            ),
+                            offset: *offset,
+                        });
+                    }
+                }
+                instr.push(Opcode::CallNative(index, args.len()));
+                // The free variables of a goal are reported in its answers,
+                // even if they are unbound.
+                if builtin.reports {
+                    let mut variables = ctx.references[references..].to_vec();
+                    variables.sort();
+                    variables.dedup();
+                    if !variables.is_empty() {
+                        instr.push(Opcode::Report(Rc::new(variables)));
+                    }
+                }
+            } else {
+                let msg = "Undefined function: ".to_string() + name;
+                return Err(SyntaxError {
+                    msg,
+                    offset: *offset,
+                });
             }
         }
         AST::Program(statements) => {
             for statement in statements {
+                ctx.clear_free_variables();
                 generate(statement, ctx, vm, instr)?;
             }
+            ctx.clear_free_variables();
         }
         AST::Table(fields) => {
             instr.push(Opcode::NewTable);
@@ -144,8 +312,222 @@ pub fn generate(
                 gen_set_table = !gen_set_table;
             }
         }
+        AST::List(elements, tail) => {
+            for element in elements {
+                generate(element, ctx, vm, instr)?;
+            }
+            match tail {
+                Some(tail) => {
+                    generate(tail, ctx, vm, instr)?;
+                    instr.push(Opcode::Cons(elements.len()));
+                }
+                None => instr.push(Opcode::Tuple(elements.len())),
+            }
+        }
+        AST::Match(value, arms) => {
+            generate(value, ctx, vm, instr)?;
+            // Each arm tries to match its pattern, skipping to the next arm if
+            // it does not match, or evaluates its expression and jumps to the
+            // end of the match. The value is None if no arm matches.
+            let mut arm_instrs = Vec::new();
+            for (pattern, expr) in arms {
+                ctx.push();
+                let mut arm_instr = Vec::new();
+                let mut variables = Vec::new();
+                let mut body = Vec::new();
+                let result = generate_pattern(pattern, ctx, vm, &mut arm_instr, &mut variables)
+                    .and_then(|_| generate(expr, ctx, vm, &mut body));
+                ctx.pop();
+                result?;
+                arm_instr.push(Opcode::Match(Rc::new(variables), body.len() + 1));
+                arm_instr.extend(body);
+                arm_instrs.push(arm_instr);
+            }
+            let mut end = 2;
+            for arm_instr in arm_instrs.iter_mut().rev() {
+                arm_instr.push(Opcode::Jump(end));
+                end += arm_instr.len();
+            }
+            for arm_instr in arm_instrs {
+                instr.extend(arm_instr);
+            }
+            instr.push(Opcode::Pop);
+            instr.push(Opcode::None);
+        }
+        AST::Field(table, key) => {
+            generate(table, ctx, vm, instr)?;
+            generate(key, ctx, vm, instr)?;
+            instr.push(Opcode::GetTable);
+        }
+        AST::Assign(field, value) => match &**field {
+            AST::Field(table, key) => {
+                generate(table, ctx, vm, instr)?;
+                generate(key, ctx, vm, instr)?;
+                generate(value, ctx, vm, instr)?;
+                instr.push(Opcode::SetTable);
+                instr.push(Opcode::Pop);
+            }
+            _ => {
+                return Err(SyntaxError {
+                    msg: "Expected field in assignment.".to_string(),
+                    offset: 0,
+                })
+            }
+        },
+        AST::Boolean(value) => instr.push(Opcode::Boolean(*value)),
+        AST::If(condition, then, otherwise) => {
+            generate(condition, ctx, vm, instr)?;
+            let jump = instr.len();
+            instr.push(Opcode::JumpIfFalse(0));
+            generate_block(then, ctx, vm, instr)?;
+            if otherwise.is_empty() {
+                instr[jump] = Opcode::JumpIfFalse(instr.len() - jump - 1);
+            } else {
+                let skip = instr.len();
+                instr.push(Opcode::Jump(0));
+                instr[jump] = Opcode::JumpIfFalse(instr.len() - jump - 1);
+                generate_block(otherwise, ctx, vm, instr)?;
+                instr[skip] = Opcode::Jump(instr.len() - skip - 1);
+            }
+        }
+        AST::While(condition, body) => {
+            let start = instr.len();
+            generate(condition, ctx, vm, instr)?;
+            let jump = instr.len();
+            instr.push(Opcode::JumpIfFalse(0));
+            ctx.loops.push((false, Vec::new()));
+            let result = generate_block(body, ctx, vm, instr);
+            let (_, breaks) = ctx.loops.pop().unwrap_or_default();
+            result?;
+            instr.push(Opcode::Loop(instr.len() - start));
+            instr[jump] = Opcode::JumpIfFalse(instr.len() - jump - 1);
+            for jump in breaks {
+                instr[jump] = Opcode::Jump(instr.len() - jump - 1);
+            }
+        }
+        AST::For(name, stream, body) => {
+            // The stream stays on the stack while the loop runs, and Next
+            // consumes it once there are no more answers.
+            generate(stream, ctx, vm, instr)?;
+            let start = instr.len();
+            instr.push(Opcode::Next);
+            let jump = instr.len();
+            instr.push(Opcode::JumpIfNone(0));
+            ctx.push();
+            let id = vm.new_variable(name);
+            ctx.insert(id, name);
+            instr.push(Opcode::Variable(id));
+            instr.push(Opcode::Swap);
+            instr.push(Opcode::SetEnv);
+            ctx.loops.push((true, Vec::new()));
+            let result = generate_block(body, ctx, vm, instr);
+            let (_, breaks) = ctx.loops.pop().unwrap_or_default();
+            ctx.pop();
+            result?;
+            instr.push(Opcode::Loop(instr.len() - start));
+            instr[jump] = Opcode::JumpIfNone(instr.len() - jump - 1);
+            for jump in breaks {
+                instr[jump] = Opcode::Jump(instr.len() - jump - 1);
+            }
+        }
+        AST::Import(path, alias, offset) => {
+            if ctx.function_scope != 0 {
+                return Err(SyntaxError {
+                    msg: "Unexpected `import` inside function.".to_string(),
+                    offset: *offset,
+                });
+            }
+            let exports = module::import(path, ctx, vm, instr, *offset)?;
+            match alias {
+                Some(alias) => {
+                    ctx.namespaces.insert(alias.to_string(), exports);
+                }
+                None => {
+                    for (name, id) in exports.iter() {
+                        ctx.insert(*id, name);
+                    }
+                }
+            }
+        }
+        AST::Clauses(name, clauses, offset) => {
+            let error = |msg: String| SyntaxError {
+                msg,
+                offset: *offset,
+            };
+            // Clauses for a relation defined by clauses in an earlier program
+            // are added to its clauses.
+            let defined = ctx.lookup_local(name);
+            if defined.is_some_and(|id| ctx.facts.contains(&id)) {
+                return Err(error(format!(
+                    "{} is defined by both clauses and facts.",
+                    name
+                )));
+            }
+            let mut clauses = clauses.clone();
+            if let Some(earlier) = defined.and_then(|id| ctx.clauses.get(&id)) {
+                if earlier[0].0.len() != clauses[0].0.len() {
+                    return Err(error(format!(
+                        "Expected {} argument(s) in clause for {}.",
+                        earlier[0].0.len(),
+                        name
+                    )));
+                }
+                clauses.splice(0..0, earlier.iter().cloned());
+            }
+            // The clauses are compiled as one relation, whose parameters are
+            // unified with the head of each clause. Each clause is a branch
+            // of a disjunction with its own fresh variables.
+            let arity = clauses.first().map_or(0, |(arguments, _)| arguments.len());
+            let parameters: Vec<AST> = (0..arity)
+                .map(|i| AST::Variable(format!("${}", i), 0))
+                .collect();
+            let mut branches = vec![];
+            for (arguments, goals) in &clauses {
+                let mut names = vec![];
+                for ast in arguments.iter().chain(goals) {
+                    clause_variables(ast, &mut names);
+                }
+                let mut conj: Vec<AST> = parameters
+                    .iter()
+                    .zip(arguments)
+                    .map(|(parameter, argument)| {
+                        AST::Equals(Box::new(parameter.clone()), Box::new(argument.clone()))
+                    })
+                    .collect();
+                conj.extend(goals.iter().cloned());
+                let body = AST::Conj(conj);
+                if names.is_empty() {
+                    branches.push(body);
+                } else {
+                    let declarations = names.into_iter().map(|name| AST::Variable(name, 0));
+                    branches.push(AST::Var(declarations.collect(), Box::new(body)));
+                }
+            }
+            let body = AST::Disj(branches);
+            let relation = AST::Relation(Some(name.to_string()), parameters, Box::new(body));
+            generate(&relation, ctx, vm, instr)?;
+            if let Some(id) = ctx.lookup_local(name) {
+                ctx.clauses.insert(id, clauses);
+            }
+        }
+        AST::Break(offset) => match ctx.loops.last_mut() {
+            Some((stream, breaks)) => {
+                // Breaking out of a `for` loop discards its stream.
+                if *stream {
+                    instr.push(Opcode::Pop);
+                }
+                breaks.push(instr.len());
+                instr.push(Opcode::Jump(0));
+            }
+            None => {
+                return Err(SyntaxError {
+                    msg: "Unexpected `break` outside of loop.".to_string(),
+                    offset: *offset,
+                })
+            }
+        },
         AST::LetBinding(name, value) => {
-            if let Some(id) = ctx.lookup(name) {
+            if let Some(id) = ctx.lookup_local(name) {
                 instr.push(Opcode::Variable(id));
             } else {
                 let id = vm.new_variable(name);
@@ -156,55 +538,290 @@ pub fn generate(
             instr.push(Opcode::SetEnv);
         }
         AST::BindingRef(name) => {
-            if let Some(id) = ctx.lookup(name) {
+            if ctx.free_variables.contains_key(name) {
+                instr.push(Opcode::Variable(ctx.free_variable(name, vm)));
+            } else if let Some(id) = ctx.lookup(name) {
                 instr.push(Opcode::Variable(id));
+                instr.push(Opcode::GetEnv);
             } else {
-                let id = vm.new_variable(name);
-                ctx.insert(id, name);
-                instr.push(Opcode::Variable(id));
+                // Unbound names in a query refer to fresh logic variables,
+                // e.g. the `x` in `next(solve(Female(x)))`.
+                instr.push(Opcode::Variable(ctx.free_variable(name, vm)));
             }
-            instr.push(Opcode::GetEnv);
         }
         AST::Relation(name, parameters, body) => {
-            if let Some(id) = ctx.lookup(name) {
+            // An anonymous relation is left on the stack as a value.
+            if let Some(name) = name {
+                if let Some(id) = ctx.lookup_local(name) {
+                    ctx.clauses.remove(&id);
+                    instr.push(Opcode::Variable(id));
+                } else {
+                    let id = vm.new_variable(name);
+                    ctx.insert(id, name);
+                    instr.push(Opcode::Variable(id));
+                }
+            }
+            // A relation defined in a function captures the local variables
+            // of the function, e.g. relations passed as its arguments.
+            let mut captured = vec![];
+            if ctx.function_scope > 0 {
+                for binding in &ctx.bindings[ctx.function_scope..] {
+                    captured.extend(binding.values().copied());
+                }
+            }
+            ctx.push();
+            ctx.relation_depth += 1;
+            let mut params = vec![];
+            for parameter in parameters {
+                match parameter {
+                    AST::Variable(name, _) => {
+                        let id = vm.new_hidden_variable(name);
+                        params.push(id);
+                        ctx.insert(id, name);
+                    }
+                    _ => unreachable!("Relation parameters must only include variables"),
+                }
+            }
+            let mut body_instr = vec![];
+            let result = generate(body, ctx, vm, &mut body_instr);
+            ctx.relation_depth -= 1;
+            ctx.pop();
+            result?;
+            body_instr.push(Opcode::Ret);
+            instr.push(Opcode::Callable {
+                kind: CallableKind::Relation,
+                parameters: Rc::new(params),
+                instructions: Rc::new(body_instr),
+                ip: 0,
+            });
+            if !captured.is_empty() {
+                captured.sort();
+                instr.push(Opcode::Capture(Rc::new(captured)));
+            }
+            if let Some(name) = name {
+                instr.push(Opcode::SetEnv);
+                // Relations defined at the top level can be materialised if
+                // they are Datalog.
+                if ctx.function_scope == 0 && ctx.relation_depth == 0 {
+                    if let Some(id) = ctx.lookup_local(name) {
+                        let definition = bottomup::Definition::new(ast, |relation| {
+                            ctx.lookup(relation)
+                                .or_else(|| vm.globals.get(relation).copied())
+                        });
+                        vm.rules.insert(id, definition.map(Rc::new));
+                    }
+                }
+            }
+        }
+        AST::Fact(name, args, offset) => {
+            let id = match ctx.lookup(name) {
+                Some(id) if ctx.clauses.contains_key(&id) => {
+                    return Err(SyntaxError {
+                        msg: format!("{} is defined by both clauses and facts.", name),
+                        offset: *offset,
+                    })
+                }
+                Some(id) => id,
+                None => {
+                    let id = vm.new_variable(name);
+                    ctx.insert(id, name);
+                    id
+                }
+            };
+            ctx.facts.insert(id);
+            instr.push(Opcode::Variable(id));
+            for arg in args {
+                generate(arg, ctx, vm, instr)?;
+            }
+            instr.push(Opcode::Fact(args.len()));
+        }
+        AST::Function(name, parameters, body) => {
+            if let Some(id) = ctx.lookup_local(name) {
                 instr.push(Opcode::Variable(id));
             } else {
                 let id = vm.new_variable(name);
                 ctx.insert(id, name);
                 instr.push(Opcode::Variable(id));
             }
+            // The body has its own scope, and its free variables are scoped to
+            // the function rather than to the statement defining it.
+            let function_scope = ctx.function_scope;
+            let free_variables = std::mem::take(&mut ctx.free_variables);
+            let references = std::mem::take(&mut ctx.references);
+            let loops = std::mem::take(&mut ctx.loops);
             ctx.push();
+            ctx.function_scope = ctx.bindings.len() - 1;
             let mut params = vec![];
             for parameter in parameters {
                 match parameter {
-                    AST::Variable(name) => {
+                    AST::Variable(name, _) => {
                         let id = vm.new_variable(name);
                         params.push(id);
                         ctx.insert(id, name);
                     }
-                    _ => unreachable!("Relation parameters must only include variables"),
+                    _ => unreachable!("Function parameters must only include variables"),
                 }
             }
             let mut body_instr = vec![];
-            generate(body, ctx, vm, &mut body_instr)?;
-            body_instr.push(Opcode::Ret);
+            let mut result = Ok(());
+            for (i, statement) in body.iter().enumerate() {
+                result = generate(statement, ctx, vm, &mut body_instr);
+                if result.is_err() {
+                    break;
+                }
+                // Only the value of the last statement is returned.
+                if i + 1 < body.len() && has_value(statement) {
+                    body_instr.push(Opcode::Pop);
+                }
+            }
             ctx.pop();
+            ctx.function_scope = function_scope;
+            ctx.free_variables = free_variables;
+            ctx.references = references;
+            ctx.loops = loops;
+            result?;
+            body_instr.push(Opcode::Ret);
             instr.push(Opcode::Callable {
-                kind: CallableKind::Relation,
+                kind: CallableKind::Function,
                 parameters: Rc::new(params),
                 instructions: Rc::new(body_instr),
                 ip: 0,
             });
             instr.push(Opcode::SetEnv);
         }
+        AST::Return(value, offset) => {
+            if ctx.function_scope == 0 {
+                return Err(SyntaxError {
+                    msg: "Unexpected `return` outside of function.".to_string(),
+                    offset: *offset,
+                });
+            }
+            generate(value, ctx, vm, instr)?;
+            instr.push(Opcode::Ret);
+        }
     }
 
     Ok(())
 }
 
+// Generate a pattern of a match. The variables of the pattern are new
+// variables in the current scope, which are bound by the match.
+fn generate_pattern(
+    pattern: &AST,
+    ctx: &mut Context,
+    vm: &mut VirtualMachine,
+    instr: &mut Vec<Opcode>,
+    variables: &mut Vec<u64>,
+) -> Result<(), SyntaxError> {
+    match pattern {
+        AST::Variable(name, _) => {
+            // A variable that appears twice must match the same value.
+            let id = match ctx.bindings.last().and_then(|binding| binding.get(name)) {
+                Some(id) => *id,
+                None => {
+                    let id = vm.new_variable(name);
+                    ctx.insert(id, name);
+                    variables.push(id);
+                    id
+                }
+            };
+            instr.push(Opcode::Variable(id));
+        }
+        AST::List(elements, tail) => {
+            for element in elements {
+                generate_pattern(element, ctx, vm, instr, variables)?;
+            }
+            match tail {
+                Some(tail) => {
+                    generate_pattern(tail, ctx, vm, instr, variables)?;
+                    instr.push(Opcode::Cons(elements.len()));
+                }
+                None => instr.push(Opcode::Tuple(elements.len())),
+            }
+        }
+        _ => generate(pattern, ctx, vm, instr)?,
+    }
+    Ok(())
+}
+
+// Generate the statements of a block in their own scope, discarding their
+// values.
+fn generate_block(
+    statements: &[AST],
+    ctx: &mut Context,
+    vm: &mut VirtualMachine,
+    instr: &mut Vec<Opcode>,
+) -> Result<(), SyntaxError> {
+    ctx.push();
+    let mut result = Ok(());
+    for statement in statements {
+        result = generate(statement, ctx, vm, instr);
+        if result.is_err() {
+            break;
+        }
+        if has_value(statement) {
+            instr.push(Opcode::Pop);
+        }
+    }
+    ctx.pop();
+    result
+}
+
+// Collect the names of the variables in a clause, in the order they appear.
+fn clause_variables(ast: &AST, names: &mut Vec<String>) {
+    match ast {
+        AST::Variable(name, _) if !names.contains(name) => {
+            names.push(name.to_string());
+        }
+        AST::Conj(goals) | AST::Disj(goals) => {
+            for goal in goals {
+                clause_variables(goal, names);
+            }
+        }
+        AST::Equals(left, right) => {
+            clause_variables(left, names);
+            clause_variables(right, names);
+        }
+        AST::FnCall(_, arguments, _) => {
+            for argument in arguments {
+                clause_variables(argument, names);
+            }
+        }
+        AST::List(elements, tail) => {
+            for element in elements {
+                clause_variables(element, names);
+            }
+            if let Some(tail) = tail {
+                clause_variables(tail, names);
+            }
+        }
+        // The variables of a nested `var` are declared by it.
+        _ => {}
+    }
+}
+
+// Whether a statement leaves a value on the stack.
+pub fn has_value(statement: &AST) -> bool {
+    !matches!(
+        statement,
+        AST::LetBinding(_, _)
+            | AST::Assign(_, _)
+            | AST::If(_, _, _)
+            | AST::While(_, _)
+            | AST::For(_, _, _)
+            | AST::Break(_)
+            | AST::Import(_, _, _)
+            | AST::Relation(Some(_), _, _)
+            | AST::Clauses(_, _, _)
+            | AST::Fact(_, _, _)
+            | AST::Function(_, _, _)
+            | AST::Return(_, _)
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{codegen, parser, tokenizer, unification::Term, vm};
+    use crate::{builtins, codegen, parser, tokenizer, unification::Term, vm};
     use std::rc::Rc;
 
     macro_rules! generate {
@@ -222,9 +839,10 @@ mod tests {
         }};
     }
 
-    fn dump_instructions(instr: &Vec<vm::Opcode>) {
-        for ip in 0..instr.len() {
-            println!("{:04}| {:?}", ip, instr[ip]);
+    #[allow(dead_code)]
+    fn dump_instructions(instr: &[vm::Opcode]) {
+        for (ip, opcode) in instr.iter().enumerate() {
+            println!("{:04}| {:?}", ip, opcode);
         }
     }
 
@@ -239,10 +857,14 @@ mod tests {
             &mut vm,
             &mut instr
         );
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert!(substs.is_empty());
         } else {
             assert!(false);
@@ -260,10 +882,14 @@ mod tests {
             &mut vm,
             &mut instr
         );
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert!(substs.is_empty());
         } else {
             assert!(false);
@@ -276,10 +902,14 @@ mod tests {
         let mut vm = vm::VirtualMachine::new();
         let mut instr = Vec::new();
         generate!("'olive == 'olive", &mut ctx, &mut vm, &mut instr);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert!(substs.is_empty());
         } else {
             assert!(false);
@@ -292,12 +922,56 @@ mod tests {
         let mut vm = vm::VirtualMachine::new();
         let mut instr = Vec::new();
         generate!("var (q) { q == 'olive }", &mut ctx, &mut vm, &mut instr);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
+            assert_eq!(substs.len(), 1);
+            assert_eq!(
+                substs
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
+            assert_eq!(vm.lookup_variable(&0).unwrap(), "q");
+            assert_eq!(vm.lookup_interned(&2).unwrap(), "olive");
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn anonymous_variables() {
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "var (q) { conj { q == 'olive, _ == 'oil, _ == 'olive, _q == 'oil } }",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(substs.len(), 1);
-            assert_eq!(substs.get(&Term::Variable(0)).unwrap(), &Term::Atom(2));
+            assert_eq!(
+                substs
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
             assert_eq!(vm.lookup_variable(&0).unwrap(), "q");
             assert_eq!(vm.lookup_interned(&2).unwrap(), "olive");
         } else {
@@ -318,8 +992,15 @@ mod tests {
         );
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(substs.len(), 1);
-            assert_eq!(substs.get(&Term::Variable(0)).unwrap(), &Term::Atom(2));
+            assert_eq!(
+                substs
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
             assert_eq!(vm.lookup_variable(&0).unwrap(), "q");
             assert_eq!(vm.lookup_interned(&2).unwrap(), "olive");
         } else {
@@ -355,8 +1036,15 @@ mod tests {
         );
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.pop() {
+            let substs = substs.borrow();
             assert_eq!(substs.len(), 1);
-            assert_eq!(substs.get(&Term::Variable(3)).unwrap(), &Term::Atom(4));
+            assert_eq!(
+                substs
+                    .get(&Term::Variable(3))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(4)
+            );
             assert_eq!(vm.lookup_variable(&3).unwrap(), "q");
             assert_eq!(vm.lookup_interned(&4).unwrap(), "oil");
         } else {
@@ -364,8 +1052,15 @@ mod tests {
         }
         vm.stack.pop();
         if let Some(vm::Value::Table(substs)) = vm.stack.pop() {
+            let substs = substs.borrow();
             assert_eq!(substs.len(), 1);
-            assert_eq!(substs.get(&Term::Variable(0)).unwrap(), &Term::Atom(2));
+            assert_eq!(
+                substs
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
             assert_eq!(vm.lookup_variable(&0).unwrap(), "q");
             assert_eq!(vm.lookup_interned(&2).unwrap(), "olive");
         } else {
@@ -381,9 +1076,22 @@ mod tests {
         generate!("{x: 'olive, y: 'oil}", &mut ctx, &mut vm, &mut instr);
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(table)) = vm.stack.last() {
+            let table = table.borrow();
             assert_eq!(table.len(), 2);
-            assert_eq!(table.get(&Term::Variable(0)).unwrap(), &Term::Atom(2));
-            assert_eq!(table.get(&Term::Variable(3)).unwrap(), &Term::Atom(5));
+            assert_eq!(
+                table
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
+            assert_eq!(
+                table
+                    .get(&Term::Variable(3))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(5)
+            );
             assert_eq!(vm.lookup_variable(&0).unwrap(), "x");
             assert_eq!(vm.lookup_interned(&2).unwrap(), "olive");
             assert_eq!(vm.lookup_variable(&3).unwrap(), "y");
@@ -411,9 +1119,22 @@ mod tests {
             assert!(false);
         }
         if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
             assert_eq!(table.len(), 2);
-            assert_eq!(table.get(&Term::Variable(0)).unwrap(), &Term::Atom(2));
-            assert_eq!(table.get(&Term::Variable(3)).unwrap(), &Term::Atom(5));
+            assert_eq!(
+                table
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
+            assert_eq!(
+                table
+                    .get(&Term::Variable(3))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(5)
+            );
             assert_eq!(vm.lookup_variable(&0).unwrap(), "x");
             assert_eq!(vm.lookup_interned(&2).unwrap(), "olive");
             assert_eq!(vm.lookup_variable(&3).unwrap(), "y");
@@ -444,16 +1165,9 @@ mod tests {
         );
         assert!(vm.run(Rc::new(instr)).is_ok());
         assert_eq!(vm.stack.len(), 1);
-        if let Some(vm::Value::Callable {
-            kind,
-            parameters,
-            instructions,
-            ip,
-        }) = vm.stack.last()
-        {
-            assert!(*kind == vm::CallableKind::Relation);
-            assert!(parameters.len() == 1);
-            assert!(*ip == 0);
+        if let Some(vm::Value::Closure(closure)) = vm.stack.last() {
+            assert!(closure.parameters.len() == 1);
+            assert!(closure.captured.is_empty());
         } else {
             assert!(false);
         }
@@ -478,8 +1192,15 @@ mod tests {
         assert!(vm.run(Rc::new(instr)).is_ok());
         assert_eq!(vm.stack.len(), 2);
         if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
             assert_eq!(table.len(), 1);
-            assert_eq!(table.get(&Term::Variable(0)).unwrap(), &Term::Atom(2));
+            assert_eq!(
+                table
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
             assert_eq!(vm.lookup_variable(&0).unwrap(), "x");
             assert_eq!(vm.lookup_interned(&2).unwrap(), "sarah");
         } else {
@@ -506,12 +1227,440 @@ mod tests {
         assert!(vm.run(Rc::new(instr)).is_ok());
         assert_eq!(vm.stack.len(), 2);
         if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
+            assert!(table.is_empty());
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn facts() {
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "Father('terach, 'abraham).
+            Father('haran, 'lot).
+            Father('haran, 'milcah).
+            next(solve(Father('haran, y)))
+            ",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 2);
+        if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
             assert_eq!(table.len(), 1);
-            assert_eq!(table.get(&Term::Variable(2)).unwrap(), &Term::Atom(4));
-            assert_eq!(vm.lookup_variable(&2).unwrap(), "x");
+            assert_eq!(
+                table
+                    .get(&Term::Variable(7))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(5)
+            );
+            assert_eq!(vm.lookup_variable(&7).unwrap(), "y");
+            assert_eq!(vm.lookup_interned(&5).unwrap(), "lot");
+        } else {
+            assert!(false);
+        }
+        if let Some(vm::Value::Facts(table)) = vm.env.get(&0) {
+            assert_eq!(table.borrow().arity, 2);
+            assert_eq!(table.borrow().len(), 3);
+        } else {
+            assert!(false);
+        }
+
+        // Facts must have the same arity as the relation.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "Father('terach, 'abraham). Father('terach).",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        assert!(vm.run(Rc::new(instr)).is_err());
+
+        // So must calls to the relation.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "Male('abraham). next(solve(Male(x, y)))",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        match vm.run(Rc::new(instr)) {
+            Err(err) => assert_eq!(err.msg, "TypeError: Expected 1 argument(s) but found 2."),
+            Ok(()) => assert!(false),
+        }
+    }
+
+    #[test]
+    fn load_csv() {
+        let dir = std::env::temp_dir();
+        let csv = dir.join(format!("tern-load-csv-{}.csv", std::process::id()));
+        let tsv = dir.join(format!("tern-load-csv-{}.tsv", std::process::id()));
+        std::fs::write(
+            &csv,
+            "parent,child,age\nterach,abraham,175\n\"Haran, the elder\",lot,\"75\"\n",
+        )
+        .unwrap();
+        std::fs::write(&tsv, "haran\tmilcah\nharan\tlot\nharan\tlot\n").unwrap();
+
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        let src = format!(
+            "load_csv({:?}, Father, 'header)
+            load_csv({:?}, Child)
+            next(solve(Father(x, 'lot, y)))",
+            csv.to_str().unwrap(),
+            tsv.to_str().unwrap()
+        );
+        generate!(&src, &mut ctx, &mut vm, &mut instr);
+        let result = vm.run(Rc::new(instr));
+        std::fs::remove_file(&csv).unwrap();
+        std::fs::remove_file(&tsv).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(vm.stack.len(), 4);
+        if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
+            assert_eq!(table.len(), 2);
+            let x = table
+                .get(&Term::Variable(7))
+                .and_then(vm::Value::as_term)
+                .unwrap();
+            assert_eq!(vm.term_to_string(x), "\"Haran, the elder\"");
+            let y = table
+                .get(&Term::Variable(10))
+                .and_then(vm::Value::as_term)
+                .unwrap();
+            assert_eq!(vm.term_to_string(y), "\"75\"");
+        } else {
+            assert!(false);
+        }
+        assert!(matches!(vm.stack.pop(), Some(vm::Value::Stream(..))));
+        // Duplicate rows are only counted once.
+        for _ in 0..2 {
+            if let Some(vm::Value::Term(Term::Integer(count))) = vm.stack.pop() {
+                assert_eq!(count, 2);
+            } else {
+                assert!(false);
+            }
+        }
+        if let Some(vm::Value::Facts(table)) = vm.env.get(&1) {
+            let table = table.borrow();
+            assert_eq!(table.arity, 3);
+            assert_eq!(table.rows().next().unwrap()[2], Term::Integer(175));
+        } else {
+            assert!(false);
+        }
+
+        // Records must all have the same number of fields.
+        std::fs::write(&csv, "terach,abraham\nharan\n").unwrap();
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        let src = format!("load_csv({:?}, Father)", csv.to_str().unwrap());
+        generate!(&src, &mut ctx, &mut vm, &mut instr);
+        let result = vm.run(Rc::new(instr));
+        std::fs::remove_file(&csv).unwrap();
+        match result {
+            Err(err) => assert_eq!(
+                err.msg,
+                format!(
+                    "{}:2: Expected 2 fields but found 1.",
+                    csv.to_str().unwrap()
+                )
+            ),
+            Ok(()) => assert!(false),
+        }
+    }
+
+    #[test]
+    fn load_json() {
+        let path = std::env::temp_dir().join(format!("tern-load-json-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            "{\"name\": \"abraham\", \"age\": 175, \"sons\": [\"isaac\"]}
+            {\"age\": 75, \"name\": \"lot\", \"sons\": {\"first\": true, \"weight\": 1.5e2}}",
+        )
+        .unwrap();
+
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        let src = format!(
+            "load_json({:?}, Person)
+            next(solve(Person(x, 75, y)))",
+            path.to_str().unwrap()
+        );
+        generate!(&src, &mut ctx, &mut vm, &mut instr);
+        let result = vm.run(Rc::new(instr));
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_ok());
+        if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
+            assert_eq!(table.len(), 2);
+            let x = table
+                .get(&Term::Variable(3))
+                .and_then(vm::Value::as_term)
+                .unwrap();
+            assert_eq!(vm.term_to_string(x), "\"lot\"");
+            let y = table
+                .get(&Term::Variable(5))
+                .and_then(vm::Value::as_term)
+                .unwrap();
+            assert_eq!(
+                vm.term_to_string(y),
+                "[[\"first\", true], [\"weight\", \"1.5e2\"]]"
+            );
+        } else {
+            assert!(false);
+        }
+        vm.stack.pop();
+        if let Some(vm::Value::Term(Term::Integer(count))) = vm.stack.pop() {
+            assert_eq!(count, 2);
+        } else {
+            assert!(false);
+        }
+
+        // Every object must have the same keys.
+        std::fs::write(&path, "[{\"a\": 1, \"b\": 2}, {\"a\": 1, \"c\": 2}]").unwrap();
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        let src = format!("load_json({:?}, R)", path.to_str().unwrap());
+        generate!(&src, &mut ctx, &mut vm, &mut instr);
+        let result = vm.run(Rc::new(instr));
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(err) => assert_eq!(
+                err.msg,
+                format!("{}: row 2: Expected key \"b\".", path.to_str().unwrap())
+            ),
+            Ok(()) => assert!(false),
+        }
+    }
+
+    #[test]
+    fn free_variables() {
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "rel Female(x) {
+                disj {
+                    x == 'sarah |
+                    x == 'milcah
+                }
+            }
+            next(solve(Female(x)))
+            ",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 2);
+        if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
+            assert_eq!(table.len(), 1);
+            assert_eq!(
+                table
+                    .get(&Term::Variable(6))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(4)
+            );
+            assert_eq!(vm.lookup_variable(&6).unwrap(), "x");
             assert_eq!(vm.lookup_interned(&4).unwrap(), "sarah");
         } else {
             assert!(false);
         }
+
+        // Only the variables of a goal are reported in its answers, not those
+        // of other goals in the same statement.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "{'a: next(solve(x == 'a)), 'b: next(solve(conj { x == y, z == z }))}",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        let reported: Vec<Vec<&str>> = instr
+            .iter()
+            .filter_map(|opcode| match opcode {
+                vm::Opcode::Report(variables) => Some(
+                    variables
+                        .iter()
+                        .map(|id| vm.lookup_variable(id).unwrap().as_str())
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reported, vec![vec!["x"], vec!["x", "y", "z"]]);
+
+        // Free variables are scoped to the statement that uses them.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "next(solve(x == 'olive))\nnext(solve(x == 'oil))",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        assert!(ctx.lookup("x").is_none());
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
+            assert_eq!(table.len(), 1);
+            assert_eq!(
+                table
+                    .get(&Term::Variable(3))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(4)
+            );
+            assert_eq!(vm.lookup_variable(&3).unwrap(), "x");
+        } else {
+            assert!(false);
+        }
+        vm.stack.pop();
+        if let Some(vm::Value::Table(table)) = vm.stack.pop() {
+            let table = table.borrow();
+            assert_eq!(table.len(), 1);
+            assert_eq!(
+                table
+                    .get(&Term::Variable(0))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(2)
+            );
+        } else {
+            assert!(false);
+        }
+
+        // Variables inside of relation bodies must be declared.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        match tokenizer::scan("rel R(x) { y == 'olive }") {
+            Ok(tokens) => match parser::parse(tokens) {
+                Ok(ast) => match codegen::generate(&ast, &mut ctx, &mut vm, &mut instr) {
+                    Ok(()) => assert!(false),
+                    Err(err) => {
+                        assert_eq!(err.msg, "Undeclared variable: y");
+                        assert_eq!(err.offset, 11);
+                    }
+                },
+                Err(err) => assert_eq!("parse failed", err.msg),
+            },
+            _ => assert!(false),
+        }
+        assert_eq!(ctx.relation_depth, 0);
+    }
+
+    #[test]
+    fn loops() {
+        // Breaking out of a `for` loop discards its stream, so nothing is left
+        // on the stack.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "let t = {}
+            for a in solve(disj { x == 'olive | x == 'oil }) {
+                t.x = a.x
+                break
+            }
+            while true { break }",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        assert!(ctx.loops.is_empty());
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert!(vm.stack.is_empty());
+        let t = ctx.lookup("t").unwrap();
+        let olive = vm.intern(&"olive".to_string());
+        let x = vm.intern(&"x".to_string());
+        if let Some(vm::Value::Table(table)) = vm.env.get(&t) {
+            let table = table.borrow();
+            assert_eq!(table.len(), 1);
+            assert_eq!(
+                table
+                    .get(&Term::Atom(x))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &Term::Atom(olive)
+            );
+        } else {
+            assert!(false);
+        }
+
+        // A loop that runs to the end leaves nothing on the stack either.
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        generate!(
+            "for a in solve(x == 'olive) { a }",
+            &mut ctx,
+            &mut vm,
+            &mut instr
+        );
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert!(vm.stack.is_empty());
+
+        let mut ctx = codegen::Context::new();
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        let ast =
+            parser::parse(tokenizer::scan("while true { fn f() { break } }").unwrap()).unwrap();
+        match codegen::generate(&ast, &mut ctx, &mut vm, &mut instr) {
+            Err(err) => {
+                assert_eq!(err.msg, "Unexpected `break` outside of loop.");
+                assert_eq!(err.offset, 26);
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn statement_values() {
+        // Expressions, including `match`, leave a value on the stack, while
+        // definitions and control flow don't.
+        let statement = |src: &str| match parser::parse(tokenizer::scan(src).unwrap()).unwrap() {
+            parser::AST::Program(mut statements) => statements.remove(0),
+            _ => panic!("expected a program"),
+        };
+        for src in [
+            "'a",
+            "match 'a { [h | t] => h, _ => 'b }",
+            "rel (x) { x == 'a }",
+            "Father(x, 'abraham)",
+        ] {
+            assert!(codegen::has_value(&statement(src)), "{}", src);
+        }
+        for src in [
+            "let x = 'a",
+            "if true { 'a }",
+            "while false { }",
+            "rel R(x) { x == 'a }",
+            "R('a).",
+            "fn f(x) { return x }",
+        ] {
+            assert!(!codegen::has_value(&statement(src)), "{}", src);
+        }
     }
 }
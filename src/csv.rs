@@ -0,0 +1,246 @@
+use crate::errors::DataError;
+use std::io::BufRead;
+
+pub struct Field {
+    pub value: String,
+    // True if the field was quoted, in which case it is always a string.
+    pub quoted: bool,
+}
+
+pub struct Record {
+    pub fields: Vec<Field>,
+    // Line on which the record starts.
+    pub line: usize,
+}
+
+// A streaming reader for delimiter separated values, e.g. CSV and TSV files.
+//
+// Fields may be quoted using `"`, in which case they may contain delimiters,
+// newlines and `""` to represent a quote. Whitespace around unquoted fields is
+// ignored, as are empty lines. A line of whitespace is a row, e.g. a row of
+// empty fields in a TSV file.
+pub struct Reader<R> {
+    input: R,
+    delimiter: char,
+    // Number of lines read so far.
+    line: usize,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R, delimiter: char) -> Self {
+        Reader {
+            input,
+            delimiter,
+            line: 0,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Option<Vec<char>>, DataError> {
+        let mut buffer = String::new();
+        match self.input.read_line(&mut buffer) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                self.line += 1;
+                if buffer.ends_with('\n') {
+                    buffer.pop();
+                    if buffer.ends_with('\r') {
+                        buffer.pop();
+                    }
+                }
+                Ok(Some(buffer.chars().collect()))
+            }
+            Err(err) => Err(DataError {
+                msg: err.to_string(),
+                line: self.line + 1,
+                column: 1,
+            }),
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, DataError> {
+        let mut chars = loop {
+            match self.read_line()? {
+                Some(chars) if chars.is_empty() => continue,
+                Some(chars) => break chars,
+                None => return Ok(None),
+            }
+        };
+        let line = self.line;
+        let mut fields = Vec::new();
+        let mut i = 0;
+        loop {
+            while i < chars.len() && chars[i] == ' ' && chars[i] != self.delimiter {
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == '"' {
+                let start = (self.line, i + 1);
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    if i == chars.len() {
+                        // Quoted fields may span multiple lines.
+                        match self.read_line()? {
+                            Some(next) => {
+                                value.push('\n');
+                                chars = next;
+                                i = 0;
+                                continue;
+                            }
+                            None => {
+                                return Err(DataError {
+                                    msg: "Unterminated quoted field.".to_string(),
+                                    line: start.0,
+                                    column: start.1,
+                                });
+                            }
+                        }
+                    }
+                    if chars[i] == '"' {
+                        if i + 1 < chars.len() && chars[i + 1] == '"' {
+                            value.push('"');
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    } else {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                }
+                while i < chars.len() && chars[i] == ' ' && chars[i] != self.delimiter {
+                    i += 1;
+                }
+                if i < chars.len() && chars[i] != self.delimiter {
+                    return Err(DataError {
+                        msg: "Expected delimiter after quoted field.".to_string(),
+                        line: self.line,
+                        column: i + 1,
+                    });
+                }
+                fields.push(Field {
+                    value,
+                    quoted: true,
+                });
+            } else {
+                let start = i;
+                while i < chars.len() && chars[i] != self.delimiter {
+                    i += 1;
+                }
+                let value: String = chars[start..i].iter().collect();
+                fields.push(Field {
+                    value: value.trim().to_string(),
+                    quoted: false,
+                });
+            }
+            if i < chars.len() {
+                // Skip the delimiter.
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(Some(Record { fields, line }))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Record, DataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// Quote a field for use in CSV output, if it contains a delimiter, a quote or
+// a newline.
+pub fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::*;
+
+    fn read(input: &str, delimiter: char) -> Vec<Vec<(String, bool)>> {
+        Reader::new(input.as_bytes(), delimiter)
+            .map(|record| {
+                record
+                    .unwrap()
+                    .fields
+                    .into_iter()
+                    .map(|field| (field.value, field.quoted))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn fields(values: &[(&str, bool)]) -> Vec<(String, bool)> {
+        values.iter().map(|(v, q)| (v.to_string(), *q)).collect()
+    }
+
+    #[test]
+    fn reading() {
+        assert!(read("", ',').is_empty());
+        assert_eq!(
+            read("terach,abraham\nterach, nachor\r\n\nharan,lot", ','),
+            vec![
+                fields(&[("terach", false), ("abraham", false)]),
+                fields(&[("terach", false), ("nachor", false)]),
+                fields(&[("haran", false), ("lot", false)]),
+            ]
+        );
+        assert_eq!(
+            read("\"Smith, John\",42,\n", ','),
+            vec![fields(&[("Smith, John", true), ("42", false), ("", false)])]
+        );
+        assert_eq!(
+            read("\"say \"\"hi\"\"\"\t\"two\nlines\"\n", '\t'),
+            vec![fields(&[("say \"hi\"", true), ("two\nlines", true)])]
+        );
+        assert_eq!(
+            read("a\tb\n\t\n\n", '\t'),
+            vec![
+                fields(&[("a", false), ("b", false)]),
+                fields(&[("", false), ("", false)]),
+            ]
+        );
+        assert_eq!(read("  \r\n", ','), vec![fields(&[("", false)])]);
+        assert_eq!(
+            read("a,b\tc", '\t'),
+            vec![fields(&[("a,b", false), ("c", false)])]
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("abraham"), "abraham");
+        assert_eq!(quote("Smith, John"), "\"Smith, John\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        let quoted = quote("two\nlines");
+        let record = Reader::new(quoted.as_bytes(), ',').next().unwrap().unwrap();
+        assert_eq!(record.fields[0].value, "two\nlines");
+    }
+
+    #[test]
+    fn errors() {
+        let mut reader = Reader::new("a,b\n\"c,d\n".as_bytes(), ',');
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(record.line, 1);
+        let err = reader.next().unwrap().err().unwrap();
+        assert_eq!(err.msg, "Unterminated quoted field.");
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, 1);
+
+        let mut reader = Reader::new("a,b\n\nc, \"d\"e".as_bytes(), ',');
+        assert!(reader.next().unwrap().is_ok());
+        let err = reader.next().unwrap().err().unwrap();
+        assert_eq!(err.msg, "Expected delimiter after quoted field.");
+        assert_eq!(err.line, 3);
+        assert_eq!(err.column, 7);
+    }
+}
@@ -0,0 +1,797 @@
+use crate::errors::SyntaxError;
+use crate::parser::AST;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Relations that are pure Horn clauses over constants, i.e. Datalog, and
+// their translation to the Datalog dialect of Soufflé.
+
+// A term of a Datalog rule, which is a variable or a constant.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Term {
+    Variable(String),
+    Anonymous,
+    Atom(String),
+    Integer(i64),
+    String(String),
+}
+
+// A call to a relation, e.g. `Father(x, 'abraham)`.
+#[derive(Clone, Debug)]
+pub struct Literal {
+    pub relation: String,
+    pub args: Vec<Term>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Goal {
+    Literal(Literal),
+    Equals(Term, Term),
+}
+
+// A rule deriving its head from a conjunction of goals, with the offset of
+// the relation it was translated from.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub head: Literal,
+    pub body: Vec<Goal>,
+    pub offset: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    Symbol,
+    Number,
+}
+
+// A Datalog program, with each relation defined either by facts or by rules.
+#[derive(Default)]
+pub struct Program {
+    pub facts: Vec<Literal>,
+    pub rules: Vec<Rule>,
+    // The relations in the order they are defined, with their arities.
+    relations: Vec<(String, usize)>,
+    // The relations defined by rules, to report relations defined twice.
+    derived: HashSet<String>,
+}
+
+fn error(msg: String, offset: usize) -> SyntaxError {
+    SyntaxError { msg, offset }
+}
+
+// The offset of the first node of an AST that has one, so errors about a
+// statement can point into it.
+fn offset(ast: &AST) -> usize {
+    match ast {
+        AST::Variable(_, offset)
+        | AST::FnCall(_, _, offset)
+        | AST::Return(_, offset)
+        | AST::Break(offset)
+        | AST::Import(_, _, offset) => *offset,
+        AST::Conj(asts) | AST::Disj(asts) | AST::Program(asts) | AST::Table(asts) => asts
+            .iter()
+            .map(offset)
+            .find(|offset| *offset > 0)
+            .unwrap_or(0),
+        AST::Equals(left, right) | AST::Assign(left, right) | AST::Field(left, right) => {
+            match offset(left) {
+                0 => offset(right),
+                offset => offset,
+            }
+        }
+        AST::Var(_, body) | AST::Relation(_, _, body) | AST::LetBinding(_, body) => offset(body),
+        AST::Fact(_, _, offset) | AST::Clauses(_, _, offset) => *offset,
+        _ => 0,
+    }
+}
+
+// A description of a statement that isn't a relation, for errors.
+fn describe(ast: &AST) -> &'static str {
+    match ast {
+        AST::LetBinding(_, _) => "Let bindings",
+        AST::Function(_, _, _) => "Functions",
+        AST::Import(_, _, _) => "Imports",
+        AST::Relation(None, _, _) => "Anonymous relations",
+        AST::If(_, _, _) | AST::While(_, _) | AST::For(_, _, _) | AST::Break(_) => "Control flow",
+        AST::Return(_, _) => "Return statements",
+        AST::Assign(_, _) => "Assignments",
+        _ => "Expressions",
+    }
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Program::default()
+    }
+
+    // Add the relations defined by the statements of a program.
+    pub fn add(&mut self, ast: &AST) -> Result<(), SyntaxError> {
+        match ast {
+            AST::Program(statements) => {
+                for statement in statements {
+                    self.add(statement)?;
+                }
+            }
+            AST::Fact(name, args, _) => {
+                if self.derived.contains(name) {
+                    return Err(error(
+                        format!("{} is defined by both rules and facts.", name),
+                        offset(ast),
+                    ));
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| term(arg, name, 0))
+                    .collect::<Result<Vec<Term>, SyntaxError>>()?;
+                self.define(name, args.len(), offset(ast))?;
+                self.facts.push(Literal {
+                    relation: name.to_string(),
+                    args,
+                });
+            }
+            AST::Relation(Some(name), parameters, body) => {
+                self.derive(name, parameters.len(), offset(ast))?;
+                let mut scope = HashSet::new();
+                let mut args = vec![];
+                for parameter in parameters {
+                    if let AST::Variable(name, _) = parameter {
+                        scope.insert(name.to_string());
+                        args.push(Term::Variable(name.to_string()));
+                    }
+                }
+                for body in conjunctions(body, name, &mut scope)? {
+                    self.rules.push(Rule {
+                        head: Literal {
+                            relation: name.to_string(),
+                            args: args.clone(),
+                        },
+                        body,
+                        offset: offset(ast),
+                    });
+                }
+            }
+            AST::Clauses(name, clauses, _) => {
+                let arity = clauses.first().map_or(0, |(args, _)| args.len());
+                self.derive(name, arity, offset(ast))?;
+                for (args, goals) in clauses {
+                    let goals = AST::Conj(goals.to_vec());
+                    let args = args
+                        .iter()
+                        .map(|arg| term(arg, name, offset(&goals)))
+                        .collect::<Result<Vec<Term>, SyntaxError>>()?;
+                    let mut scope = HashSet::new();
+                    for body in conjunctions(&goals, name, &mut scope)? {
+                        self.rules.push(Rule {
+                            head: Literal {
+                                relation: name.to_string(),
+                                args: args.clone(),
+                            },
+                            body,
+                            offset: offset(&goals),
+                        });
+                    }
+                }
+            }
+            _ => {
+                return Err(error(
+                    format!("{} have no Datalog equivalent.", describe(ast)),
+                    offset(ast),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, arity: usize, offset: usize) -> Result<(), SyntaxError> {
+        match self.relations.iter().find(|(relation, _)| relation == name) {
+            Some((_, n)) if *n != arity => Err(error(
+                format!("Expected {} argument(s) in fact for {}.", n, name),
+                offset,
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.relations.push((name.to_string(), arity));
+                Ok(())
+            }
+        }
+    }
+
+    fn derive(&mut self, name: &str, arity: usize, offset: usize) -> Result<(), SyntaxError> {
+        if self.relations.iter().any(|(relation, _)| relation == name) {
+            return Err(error(
+                format!("{} is defined more than once.", name),
+                offset,
+            ));
+        }
+        self.derived.insert(name.to_string());
+        self.relations.push((name.to_string(), arity));
+        Ok(())
+    }
+
+    // Whether a relation is defined by rules rather than facts.
+    pub fn is_derived(&self, name: &str) -> bool {
+        self.derived.contains(name)
+    }
+
+    // Check that every relation called by a rule is defined with the same
+    // number of arguments, and that the rules are range restricted, i.e.
+    // every variable in the head of a rule is bound by its body.
+    pub fn check(&self) -> Result<(), SyntaxError> {
+        let arities: HashMap<&str, usize> = self
+            .relations
+            .iter()
+            .map(|(name, arity)| (name.as_str(), *arity))
+            .collect();
+        for rule in &self.rules {
+            let name = &rule.head.relation;
+            for goal in &rule.body {
+                if let Goal::Literal(literal) = goal {
+                    match arities.get(literal.relation.as_str()) {
+                        Some(arity) if *arity == literal.args.len() => {}
+                        Some(arity) => {
+                            return Err(error(
+                                format!(
+                                    "Expected {} argument(s) to {} in {}.",
+                                    arity, literal.relation, name
+                                ),
+                                rule.offset,
+                            ))
+                        }
+                        None => {
+                            return Err(error(
+                                format!(
+                                    "{} calls {}, which isn't defined by facts or rules.",
+                                    name, literal.relation
+                                ),
+                                rule.offset,
+                            ))
+                        }
+                    }
+                }
+            }
+            rule.check()?;
+        }
+        Ok(())
+    }
+
+    // The type of each column of each relation. Columns holding atoms and
+    // strings are symbols, and columns holding integers are numbers.
+    fn types(&self) -> Result<HashMap<String, Vec<Option<Type>>>, SyntaxError> {
+        let mut types: HashMap<String, Vec<Option<Type>>> = self
+            .relations
+            .iter()
+            .map(|(name, arity)| (name.to_string(), vec![None; *arity]))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for fact in &self.facts {
+                for (i, arg) in fact.args.iter().enumerate() {
+                    if let Some(t) = arg.constant_type() {
+                        changed |= set_type(&mut types, &fact.relation, i, t, 0)?;
+                    }
+                }
+            }
+            for rule in &self.rules {
+                let variables = rule.types(&types);
+                let literals = std::iter::once(&rule.head).chain(rule.body.iter().filter_map(
+                    |goal| match goal {
+                        Goal::Literal(literal) => Some(literal),
+                        Goal::Equals(_, _) => None,
+                    },
+                ));
+                for literal in literals {
+                    for (i, arg) in literal.args.iter().enumerate() {
+                        let t = match arg {
+                            Term::Variable(v) => variables.get(v).copied(),
+                            term => term.constant_type(),
+                        };
+                        if let Some(t) = t {
+                            changed |= set_type(&mut types, &literal.relation, i, t, rule.offset)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(types)
+    }
+
+    // Translate the program to Soufflé, returning the rules and the contents
+    // of the `.facts` file of each relation defined by facts.
+    pub fn souffle(&self, source: &str) -> Result<(String, Vec<(String, String)>), SyntaxError> {
+        self.check()?;
+        let types = self.types()?;
+        let mut dl = format!("// Exported from {} by `tern export --datalog`.\n", source);
+        let mut files = vec![];
+        for (name, _) in &self.relations {
+            let columns: Vec<String> = types[name]
+                .iter()
+                .enumerate()
+                .map(|(i, t)| match t {
+                    Some(Type::Number) => format!("a{}: number", i + 1),
+                    _ => format!("a{}: symbol", i + 1),
+                })
+                .collect();
+            dl += &format!("\n.decl {}({})\n", name, columns.join(", "));
+            if self.is_derived(name) {
+                dl += &format!(".output {}\n", name);
+                for rule in self.rules.iter().filter(|rule| rule.head.relation == *name) {
+                    dl += &format!("{}\n", rule);
+                }
+            } else {
+                dl += &format!(".input {}\n", name);
+                let mut rows = String::new();
+                for fact in self.facts.iter().filter(|fact| fact.relation == *name) {
+                    let mut fields = vec![];
+                    for arg in &fact.args {
+                        let field = match arg {
+                            Term::Atom(s) | Term::String(s) => s.to_string(),
+                            Term::Integer(n) => n.to_string(),
+                            _ => unreachable!("Facts must only include constants"),
+                        };
+                        if field.contains(['\t', '\n', '\r']) {
+                            return Err(error(
+                                format!(
+                                    "A fact for {} has a tab or a newline, which can't be written to a .facts file.",
+                                    name
+                                ),
+                                0,
+                            ));
+                        }
+                        fields.push(field);
+                    }
+                    rows += &fields.join("\t");
+                    rows += "\n";
+                }
+                files.push((format!("{}.facts", name), rows));
+            }
+        }
+        Ok((dl, files))
+    }
+}
+
+impl Rule {
+    // Check that the rule is range restricted, i.e. that every variable in
+    // its head and its equalities is bound by a call to a relation.
+    pub fn check(&self) -> Result<(), SyntaxError> {
+        let name = &self.head.relation;
+        // A head argument of `_`, e.g. from the clause `Q(x, _) :- P(x).`,
+        // is equal to a parameter of the relation.
+        let anonymous = |arg: &Term| {
+            *arg == Term::Anonymous
+                || self.body.iter().any(|goal| match goal {
+                    Goal::Equals(left, right) => {
+                        (left == arg && *right == Term::Anonymous)
+                            || (right == arg && *left == Term::Anonymous)
+                    }
+                    Goal::Literal(_) => false,
+                })
+        };
+        if self.head.args.iter().any(anonymous) {
+            return Err(error(
+                format!("`_` isn't allowed in the head of a rule for {}.", name),
+                self.offset,
+            ));
+        }
+        let bound = self.bound();
+        let equalities = self.body.iter().flat_map(|goal| match goal {
+            Goal::Equals(left, right) => vec![left, right],
+            Goal::Literal(_) => vec![],
+        });
+        for term in self.head.args.iter().chain(equalities) {
+            match term {
+                Term::Variable(variable) if !bound.contains(variable) => {
+                    return Err(error(
+                        format!(
+                            "Variable {} in a rule for {} isn't bound by a call to a relation.",
+                            variable, name
+                        ),
+                        self.offset,
+                    ))
+                }
+                Term::Anonymous => {
+                    return Err(error(
+                        format!(
+                            "`_` in a rule for {} is only allowed in calls to relations.",
+                            name
+                        ),
+                        self.offset,
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // The types of the variables of the rule, from the columns and constants
+    // they appear with.
+    fn types(&self, columns: &HashMap<String, Vec<Option<Type>>>) -> HashMap<String, Type> {
+        let mut types: HashMap<String, Type> = HashMap::new();
+        for goal in &self.body {
+            if let Goal::Literal(literal) = goal {
+                for (i, arg) in literal.args.iter().enumerate() {
+                    if let (Term::Variable(v), Some(Some(t))) =
+                        (arg, columns.get(&literal.relation).and_then(|c| c.get(i)))
+                    {
+                        types.insert(v.to_string(), *t);
+                    }
+                }
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for goal in &self.body {
+                if let Goal::Equals(left, right) = goal {
+                    for (a, b) in [(left, right), (right, left)] {
+                        let t = match b {
+                            Term::Variable(w) => types.get(w).copied(),
+                            term => term.constant_type(),
+                        };
+                        if let (Term::Variable(v), Some(t)) = (a, t) {
+                            if !types.contains_key(v) {
+                                types.insert(v.to_string(), t);
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        types
+    }
+
+    // The variables bound by the body of the rule: the variables in calls to
+    // relations, and variables equal to a constant or a bound variable.
+    pub fn bound(&self) -> HashSet<String> {
+        let mut bound = HashSet::new();
+        for goal in &self.body {
+            if let Goal::Literal(literal) = goal {
+                for arg in &literal.args {
+                    if let Term::Variable(v) = arg {
+                        bound.insert(v.to_string());
+                    }
+                }
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for goal in &self.body {
+                if let Goal::Equals(left, right) = goal {
+                    for (a, b) in [(left, right), (right, left)] {
+                        if let Term::Variable(v) = a {
+                            let known = match b {
+                                Term::Variable(w) => bound.contains(w),
+                                Term::Anonymous => false,
+                                _ => true,
+                            };
+                            if known && bound.insert(v.to_string()) {
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        bound
+    }
+}
+
+// Set the type of a column, returning whether it was unknown.
+fn set_type(
+    types: &mut HashMap<String, Vec<Option<Type>>>,
+    relation: &str,
+    i: usize,
+    t: Type,
+    offset: usize,
+) -> Result<bool, SyntaxError> {
+    match types
+        .get_mut(relation)
+        .and_then(|columns| columns.get_mut(i))
+    {
+        Some(Some(u)) if *u != t => Err(error(
+            format!(
+                "Argument {} of {} holds both integers and atoms or strings.",
+                i + 1,
+                relation
+            ),
+            offset,
+        )),
+        Some(column @ None) => {
+            *column = Some(t);
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+impl Term {
+    fn constant_type(&self) -> Option<Type> {
+        match self {
+            Term::Atom(_) | Term::String(_) => Some(Type::Symbol),
+            Term::Integer(_) => Some(Type::Number),
+            _ => None,
+        }
+    }
+}
+
+// Translate a named relation to range restricted Datalog rules.
+pub fn rules(ast: &AST) -> Result<Vec<Rule>, SyntaxError> {
+    let mut program = Program::new();
+    program.add(ast)?;
+    for rule in &program.rules {
+        rule.check()?;
+    }
+    Ok(program.rules)
+}
+
+// Translate a term of a relation body to Datalog. Errors are reported at
+// `at`, unless the term has an offset of its own.
+fn term(ast: &AST, relation: &str, at: usize) -> Result<Term, SyntaxError> {
+    let offset = match offset(ast) {
+        0 => at,
+        offset => offset,
+    };
+    match ast {
+        AST::Variable(name, _) => Ok(Term::Variable(name.to_string())),
+        AST::AnonymousVariable => Ok(Term::Anonymous),
+        AST::Atom(atom) => Ok(Term::Atom(atom.to_string())),
+        AST::Integer(n) => Ok(Term::Integer(*n)),
+        AST::String(s) => Ok(Term::String(s.to_string())),
+        AST::List(_, _) => Err(error(
+            format!("Lists in {} have no Datalog equivalent.", relation),
+            offset,
+        )),
+        AST::BindingRef(name) => Err(error(
+            format!(
+                "Passing the relation {} as an argument in {} has no Datalog equivalent.",
+                name, relation
+            ),
+            offset,
+        )),
+        _ => Err(error(
+            format!("Expected a variable or a constant in {}.", relation),
+            offset,
+        )),
+    }
+}
+
+// The disjunctive normal form of a goal: the conjunctions of calls and
+// equalities one of which must hold for the goal to hold. `scope` holds the
+// variables declared so far, as Datalog has no nested scopes.
+fn conjunctions(
+    ast: &AST,
+    relation: &str,
+    scope: &mut HashSet<String>,
+) -> Result<Vec<Vec<Goal>>, SyntaxError> {
+    match ast {
+        AST::Equals(left, right) => Ok(vec![vec![Goal::Equals(
+            term(left, relation, offset(ast))?,
+            term(right, relation, offset(ast))?,
+        )]]),
+        AST::FnCall(name, args, offset) => {
+            if !name.starts_with(char::is_uppercase) || name.contains('.') {
+                return Err(error(
+                    format!(
+                        "Calls to {} in {} have no Datalog equivalent, only calls to relations defined in the same program.",
+                        name, relation
+                    ),
+                    *offset,
+                ));
+            }
+            let args = args
+                .iter()
+                .map(|arg| term(arg, relation, *offset))
+                .collect::<Result<Vec<Term>, SyntaxError>>()?;
+            Ok(vec![vec![Goal::Literal(Literal {
+                relation: name.to_string(),
+                args,
+            })]])
+        }
+        AST::Conj(goals) => {
+            let mut result = vec![vec![]];
+            for goal in goals {
+                let mut product = vec![];
+                for right in conjunctions(goal, relation, scope)? {
+                    for left in &result {
+                        let mut conj: Vec<Goal> = Vec::clone(left);
+                        conj.extend(right.iter().cloned());
+                        product.push(conj);
+                    }
+                }
+                result = product;
+            }
+            Ok(result)
+        }
+        AST::Disj(goals) => {
+            let mut result = vec![];
+            // Each branch declares its own variables.
+            for goal in goals {
+                result.extend(conjunctions(goal, relation, &mut scope.clone())?);
+            }
+            Ok(result)
+        }
+        AST::Var(declarations, body) => {
+            for declaration in declarations {
+                if let AST::Variable(name, offset) = declaration {
+                    if !scope.insert(name.to_string()) {
+                        return Err(error(
+                            format!(
+                                "Variable {} in {} shadows another variable, which has no Datalog equivalent.",
+                                name, relation
+                            ),
+                            *offset,
+                        ));
+                    }
+                }
+            }
+            conjunctions(body, relation, scope)
+        }
+        _ => Err(error(
+            format!(
+                "{} in {} have no Datalog equivalent.",
+                describe(ast),
+                relation
+            ),
+            offset(ast),
+        )),
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Variable(name) => write!(f, "{}", name),
+            Term::Anonymous => write!(f, "_"),
+            Term::Integer(n) => write!(f, "{}", n),
+            Term::Atom(s) | Term::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        _ => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}(", self.relation)?;
+        let mut first = true;
+        for arg in &self.args {
+            if !first {
+                write!(f, ", {}", arg)?;
+            } else {
+                first = false;
+                write!(f, "{}", arg)?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.head)?;
+        let mut first = true;
+        for goal in &self.body {
+            if !first {
+                write!(f, ", ")?;
+            } else {
+                first = false;
+                write!(f, " :- ")?;
+            }
+            match goal {
+                Goal::Literal(literal) => write!(f, "{}", literal)?,
+                Goal::Equals(left, right) => write!(f, "{} = {}", left, right)?,
+            }
+        }
+        write!(f, ".")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datalog::Program;
+    use crate::{parser, tokenizer};
+
+    fn export(src: &str) -> Result<(String, Vec<(String, String)>), String> {
+        let ast = parser::parse(tokenizer::scan(src).unwrap()).unwrap();
+        let mut program = Program::new();
+        program
+            .add(&ast)
+            .and_then(|_| program.souffle("test.tern"))
+            .map_err(|err| err.msg)
+    }
+
+    #[test]
+    fn souffle() {
+        let (dl, facts) = export(
+            "Parent('abe, 'homer). Parent('homer, \"Bart\"). Age('abe, 39).
+             rel Ancestor(x, y) {
+                 disj { Parent(x, y) | var (z) { conj { Parent(x, z), Ancestor(z, y) } } }
+             }
+             Old(x) :- Age(x, n), n == 39, Parent(x, _).",
+        )
+        .unwrap();
+        assert_eq!(
+            dl,
+            "// Exported from test.tern by `tern export --datalog`.
+
+.decl Parent(a1: symbol, a2: symbol)
+.input Parent
+
+.decl Age(a1: symbol, a2: number)
+.input Age
+
+.decl Ancestor(a1: symbol, a2: symbol)
+.output Ancestor
+Ancestor(x, y) :- Parent(x, y).
+Ancestor(x, y) :- Parent(x, z), Ancestor(z, y).
+
+.decl Old(a1: symbol)
+.output Old
+Old(x) :- Age(x, n), n = 39, Parent(x, _).
+"
+        );
+        assert_eq!(
+            facts,
+            vec![
+                (
+                    "Parent.facts".to_string(),
+                    "abe\thomer\nhomer\tBart\n".to_string()
+                ),
+                ("Age.facts".to_string(), "abe\t39\n".to_string()),
+            ]
+        );
+        // Disjunctions inside conjunctions are distributed over them.
+        let (dl, _) =
+            export("P('a). rel Q(x, y) { conj { P(x), disj { y == 'b | y == 'c } } }").unwrap();
+        assert!(dl.ends_with("Q(x, y) :- P(x), y = \"b\".\nQ(x, y) :- P(x), y = \"c\".\n"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            export("Q(x) :- x == [1]."),
+            Err("Lists in Q have no Datalog equivalent.".to_string())
+        );
+        assert_eq!(
+            export("rel R(x) { var (y) { x == y } }"),
+            Err("Variable x in a rule for R isn't bound by a call to a relation.".to_string())
+        );
+        assert_eq!(
+            export("Q(x) :- P(x)."),
+            Err("Q calls P, which isn't defined by facts or rules.".to_string())
+        );
+        assert_eq!(
+            export("P('a). Q(x) :- P(x, x)."),
+            Err("Expected 1 argument(s) to P in Q.".to_string())
+        );
+        assert_eq!(
+            export("P('a). P(1)."),
+            Err("Argument 1 of P holds both integers and atoms or strings.".to_string())
+        );
+        assert_eq!(
+            export("P('a). P(x) :- x == 'b."),
+            Err("P is defined more than once.".to_string())
+        );
+        assert_eq!(
+            export("let x = 1"),
+            Err("Let bindings have no Datalog equivalent.".to_string())
+        );
+        assert_eq!(
+            export("P('a). rel Q(x) { conj { P(x), f.R(x) } }"),
+            Err("Calls to f.R in Q have no Datalog equivalent, only calls to relations defined in the same program.".to_string())
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::error::Error;
+use std::error;
 use std::fmt;
 
 #[derive(Debug)]
@@ -9,11 +9,11 @@ pub struct RuntimeError {
 
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ParserError: {}", self.msg)
+        write!(f, "RuntimeError: {}", self.msg)
     }
 }
 
-impl Error for RuntimeError {}
+impl error::Error for RuntimeError {}
 
 #[derive(Debug)]
 pub struct SyntaxError {
@@ -23,11 +23,11 @@ pub struct SyntaxError {
 
 impl fmt::Display for SyntaxError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ParserError: {}", self.msg)
+        write!(f, "SyntaxError: {}", self.msg)
     }
 }
 
-impl Error for SyntaxError {}
+impl error::Error for SyntaxError {}
 
 #[derive(Debug)]
 pub struct TokenizerError {
@@ -41,4 +41,57 @@ impl fmt::Display for TokenizerError {
     }
 }
 
-impl Error for TokenizerError {}
+impl error::Error for TokenizerError {}
+
+#[derive(Debug)]
+pub struct DataError {
+    pub msg: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DataError: {}:{}: {}", self.line, self.column, self.msg)
+    }
+}
+
+impl error::Error for DataError {}
+
+// Any error from evaluating a program.
+#[derive(Debug)]
+pub enum Error {
+    Tokenizer(TokenizerError),
+    Syntax(SyntaxError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Tokenizer(err) => err.fmt(f),
+            Error::Syntax(err) => err.fmt(f),
+            Error::Runtime(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<TokenizerError> for Error {
+    fn from(err: TokenizerError) -> Self {
+        Error::Tokenizer(err)
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(err: SyntaxError) -> Self {
+        Error::Syntax(err)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(err: RuntimeError) -> Self {
+        Error::Runtime(err)
+    }
+}
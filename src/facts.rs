@@ -0,0 +1,501 @@
+use crate::logic::Goal;
+use crate::unification::{rename, unify, walk, Substitutions, Term};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+// A fact table holds the rows of a relation defined by fact declarations,
+// e.g. `Father('terach, 'abraham).`. Every row is a ground term, and each
+// row is stored only once.
+//
+// Each argument position is indexed by value, so that a lookup with a bound
+// argument, e.g. `Father('haran, y)`, only visits the matching rows.
+//
+// Rows are only ever appended, and a retracted row is marked with the version
+// of the table it was retracted in, so an iterator sees the rows of the table
+// as of its own version without copying them: rows asserted or retracted
+// while the answers to a goal are enumerated, e.g. in the body of a `for`
+// loop, don't change those answers. Retracted rows are dropped once no
+// iterator is reading the table.
+pub struct FactTable<T> {
+    pub arity: usize,
+    rows: Vec<Entry<T>>,
+    // Maps each live row to its position in `rows`.
+    unique: HashMap<Vec<Term<T>>, usize>,
+    // For each argument position, maps a value to the rows containing it,
+    // including retracted rows not yet dropped.
+    indexes: Vec<HashMap<Term<T>, Vec<usize>>>,
+    // Incremented by every change to the table.
+    version: u64,
+    // Shared with every running iterator, so that retracted rows are only
+    // dropped when there are none.
+    readers: Rc<()>,
+}
+
+struct Entry<T> {
+    row: Vec<Term<T>>,
+    // The version in which the row was retracted, if it was.
+    retracted: Option<u64>,
+}
+
+impl<T: Clone + Eq + Hash> FactTable<T> {
+    pub fn new(arity: usize) -> Self {
+        FactTable {
+            arity,
+            rows: Vec::new(),
+            unique: HashMap::new(),
+            indexes: (0..arity).map(|_| HashMap::new()).collect(),
+            version: 0,
+            readers: Rc::new(()),
+        }
+    }
+
+    // Return the number of rows in the table.
+    pub fn len(&self) -> usize {
+        self.unique.len()
+    }
+
+    // Return the rows of the table in the order they were added.
+    pub fn rows(&self) -> impl Iterator<Item = &Vec<Term<T>>> {
+        self.rows
+            .iter()
+            .filter(|entry| entry.retracted.is_none())
+            .map(|entry| &entry.row)
+    }
+
+    // Add a row to the table, returning false if the row was already present.
+    pub fn insert(&mut self, row: Vec<Term<T>>) -> bool {
+        assert_eq!(row.len(), self.arity, "fact has the wrong arity");
+        if self.unique.contains_key(&row) {
+            return false;
+        }
+        self.compact();
+        self.version += 1;
+        let id = self.rows.len();
+        for (index, value) in self.indexes.iter_mut().zip(row.iter()) {
+            index.entry(key(value)).or_default().push(id);
+        }
+        self.unique.insert(row.clone(), id);
+        self.rows.push(Entry {
+            row,
+            retracted: None,
+        });
+        true
+    }
+
+    pub fn contains(&self, row: &[Term<T>]) -> bool {
+        self.unique.contains_key(row)
+    }
+
+    // Remove a row from the table, returning false if the row wasn't present.
+    pub fn remove(&mut self, row: &[Term<T>]) -> bool {
+        let Some(id) = self.unique.remove(row) else {
+            return false;
+        };
+        self.version += 1;
+        self.rows[id].retracted = Some(self.version);
+        self.compact();
+        true
+    }
+
+    // Drop the retracted rows once they outnumber the live ones, unless an
+    // iterator is still reading them.
+    fn compact(&mut self) {
+        if self.rows.len() < 2 * self.unique.len() || Rc::strong_count(&self.readers) > 1 {
+            return;
+        }
+        self.rows.retain(|entry| entry.retracted.is_none());
+        for index in &mut self.indexes {
+            index.clear();
+        }
+        for (id, entry) in self.rows.iter().enumerate() {
+            for (index, value) in self.indexes.iter_mut().zip(entry.row.iter()) {
+                index.entry(key(value)).or_default().push(id);
+            }
+            *self.unique.get_mut(&entry.row).expect("rows are unique") = id;
+        }
+    }
+
+    // Return the rows that have `value` at argument position `column`.
+    pub fn lookup(&self, column: usize, value: &Term<T>) -> impl Iterator<Item = &Vec<Term<T>>> {
+        self.ids(column, &key(value))
+            .iter()
+            .map(|&id| &self.rows[id])
+            .filter(|entry| entry.retracted.is_none())
+            .map(|entry| &entry.row)
+    }
+
+    // Return the positions of the rows with the key `value` at `column`,
+    // including retracted rows.
+    fn ids(&self, column: usize, value: &Term<T>) -> &[usize] {
+        match self.indexes[column].get(value) {
+            Some(ids) => ids,
+            None => &[],
+        }
+    }
+
+    // Is the row at `id` part of the table as of `version`?
+    fn visible(&self, id: usize, version: u64) -> Option<&Vec<Term<T>>> {
+        let entry = &self.rows[id];
+        match entry.retracted {
+            Some(retracted) if retracted <= version => None,
+            _ => Some(&entry.row),
+        }
+    }
+
+    // Choose the most selective index for the arguments, returning the
+    // argument position and its key, or None if no argument is ground.
+    fn select_index(
+        &self,
+        args: &[Term<T>],
+        substs: &Substitutions<T>,
+    ) -> Option<(usize, Term<T>)> {
+        let mut best: Option<(usize, Term<T>)> = None;
+        let mut best_len = usize::MAX;
+        for (column, arg) in args.iter().enumerate() {
+            if let Some(value) = ground(arg, substs) {
+                let len = self.ids(column, &value).len();
+                if len < best_len {
+                    best = Some((column, value));
+                    best_len = len;
+                }
+            }
+        }
+        best
+    }
+}
+
+// Return the value of a term if it is ground, with lists written as tuples,
+// e.g. `['a | ['b]]` as `['a, 'b]`, so that equal values have the same key in
+// an index.
+fn ground<T: Clone>(term: &Term<T>, substs: &Substitutions<T>) -> Option<Term<T>> {
+    match walk(term, substs) {
+        Term::Variable(_) => None,
+        Term::Tuple(items) => items
+            .iter()
+            .map(|item| ground(item, substs))
+            .collect::<Option<_>>()
+            .map(Term::Tuple),
+        Term::Cons(head, tail) => {
+            let head = ground(head, substs)?;
+            match ground(tail, substs)? {
+                Term::Tuple(mut items) => {
+                    items.insert(0, head);
+                    Some(Term::Tuple(items))
+                }
+                tail => Some(Term::Cons(Box::new(head), Box::new(tail))),
+            }
+        }
+        value => Some(value.clone()),
+    }
+}
+
+// Return the key of a value in a row.
+fn key<T: Clone>(value: &Term<T>) -> Term<T> {
+    ground(value, &HashMap::new()).expect("facts are ground")
+}
+
+// The Facts goal produces a stream of substitutions, one for each row of the
+// fact table that unifies with the arguments.
+pub struct Facts<T> {
+    table: Rc<RefCell<FactTable<T>>>,
+    args: Vec<Term<T>>,
+}
+
+impl<T> Facts<T> {
+    pub fn new(table: Rc<RefCell<FactTable<T>>>, args: Vec<Term<T>>) -> Self {
+        Facts { table, args }
+    }
+}
+
+pub struct FactsIterator<T> {
+    table: Rc<RefCell<FactTable<T>>>,
+    // Keeps the retracted rows of the table until the iterator is dropped.
+    _reader: Rc<()>,
+    // The version of the table when the goal was solved.
+    version: u64,
+    args: Vec<Term<T>>,
+    // The index used to find the candidate rows, or None to scan every row.
+    index: Option<(usize, Term<T>)>,
+    // The number of candidate rows when the goal was solved; later rows were
+    // asserted after it.
+    end: usize,
+    // Position of the next candidate row.
+    position: usize,
+    // substitutions to use during unification.
+    substs: Substitutions<T>,
+}
+
+impl<T: Clone + Eq + Hash + 'static> Goal<T> for Facts<T> {
+    fn solve(&self, substs: &Substitutions<T>) -> Box<dyn Iterator<Item = Substitutions<T>>> {
+        let table = self.table.borrow();
+        let index = table.select_index(&self.args, substs);
+        let end = match &index {
+            Some((column, value)) => table.ids(*column, value).len(),
+            None => table.rows.len(),
+        };
+        Box::new(FactsIterator {
+            table: self.table.clone(),
+            _reader: table.readers.clone(),
+            version: table.version,
+            args: self.args.clone(),
+            index,
+            end,
+            position: 0,
+            substs: substs.clone(),
+        })
+    }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Facts::new(
+            self.table.clone(),
+            self.args.iter().map(|arg| rename(arg, renaming)).collect(),
+        ))
+    }
+}
+
+impl<T: Clone + Eq + Hash> Iterator for FactsIterator<T> {
+    type Item = Substitutions<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let table = self.table.borrow();
+        while self.position < self.end {
+            let id = match &self.index {
+                Some((column, value)) => table.ids(*column, value)[self.position],
+                None => self.position,
+            };
+            self.position += 1;
+            let Some(row) = table.visible(id, self.version) else {
+                continue;
+            };
+            let mut substs = self.substs.clone();
+            if self
+                .args
+                .iter()
+                .zip(row.iter())
+                .all(|(arg, value)| unify(arg, value, &mut substs))
+            {
+                return Some(substs);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::facts::*;
+
+    fn father() -> Rc<RefCell<FactTable<&'static str>>> {
+        let mut table = FactTable::new(2);
+        assert!(table.insert(vec![Term::Atom("terach"), Term::Atom("abraham")]));
+        assert!(table.insert(vec![Term::Atom("terach"), Term::Atom("nachor")]));
+        assert!(table.insert(vec![Term::Atom("abraham"), Term::Atom("isaac")]));
+        assert!(!table.insert(vec![Term::Atom("terach"), Term::Atom("nachor")]));
+        assert_eq!(table.len(), 3);
+        Rc::new(RefCell::new(table))
+    }
+
+    #[test]
+    fn test_facts() {
+        let substs = HashMap::new();
+        let facts = Facts::new(father(), vec![Term::Atom("terach"), Term::Variable(1)]);
+        let mut iter = facts.solve(&substs);
+        let result = iter.next().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(*result.get(&1).unwrap(), Term::Atom("abraham"));
+        let result = iter.next().unwrap();
+        assert_eq!(*result.get(&1).unwrap(), Term::Atom("nachor"));
+        assert_eq!(iter.next(), None);
+
+        let facts = Facts::new(father(), vec![Term::Variable(1), Term::Variable(1)]);
+        let mut iter = facts.solve(&substs);
+        assert_eq!(iter.next(), None);
+
+        let facts = Facts::new(father(), vec![Term::Variable(1), Term::Atom("isaac")]);
+        let mut iter = facts.solve(&substs);
+        let result = iter.next().unwrap();
+        assert_eq!(*result.get(&1).unwrap(), Term::Atom("abraham"));
+        assert_eq!(iter.next(), None);
+
+        let table = father();
+        let facts = Facts::new(table.clone(), vec![Term::Variable(1), Term::Variable(2)]);
+        let mut iter = facts.solve(&substs);
+        assert!(iter.next().is_some());
+        // Rows asserted and retracted after the goal is solved don't change
+        // its answers.
+        table
+            .borrow_mut()
+            .insert(vec![Term::Atom("haran"), Term::Atom("lot")]);
+        table
+            .borrow_mut()
+            .remove(&[Term::Atom("abraham"), Term::Atom("isaac")]);
+        assert_eq!(iter.count(), 2);
+    }
+
+    #[test]
+    fn test_index() {
+        let table = father();
+        let table = table.borrow();
+        let sons: Vec<_> = table
+            .lookup(0, &Term::Atom("terach"))
+            .map(|row| &row[1])
+            .collect();
+        assert_eq!(sons, [&Term::Atom("abraham"), &Term::Atom("nachor")]);
+        assert_eq!(table.lookup(1, &Term::Atom("isaac")).count(), 1);
+        assert_eq!(table.lookup(0, &Term::Atom("isaac")).count(), 0);
+
+        let mut substs = HashMap::new();
+        let args = vec![Term::Variable(1), Term::Variable(2)];
+        assert_eq!(table.select_index(&args, &substs), None);
+        substs.insert(1, Term::Atom("terach"));
+        assert_eq!(
+            table.select_index(&args, &substs),
+            Some((0, Term::Atom("terach")))
+        );
+        substs.insert(2, Term::Atom("isaac"));
+        assert_eq!(
+            table.select_index(&args, &substs),
+            Some((1, Term::Atom("isaac")))
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let table = father();
+        let mut table = table.borrow_mut();
+        assert!(table.remove(&[Term::Atom("terach"), Term::Atom("abraham")]));
+        assert!(!table.remove(&[Term::Atom("terach"), Term::Atom("abraham")]));
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.rows().next(),
+            Some(&vec![Term::Atom("terach"), Term::Atom("nachor")])
+        );
+        assert_eq!(table.lookup(0, &Term::Atom("terach")).count(), 1);
+        assert_eq!(table.lookup(0, &Term::Atom("abraham")).count(), 1);
+        assert_eq!(table.lookup(1, &Term::Atom("abraham")).count(), 0);
+        assert!(table.insert(vec![Term::Atom("terach"), Term::Atom("abraham")]));
+        assert_eq!(table.lookup(0, &Term::Atom("terach")).count(), 2);
+        // Retracted rows are dropped once they outnumber the live ones.
+        assert!(table.remove(&[Term::Atom("terach"), Term::Atom("nachor")]));
+        assert!(table.remove(&[Term::Atom("terach"), Term::Atom("abraham")]));
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.ids(0, &Term::Atom("abraham")), &[0]);
+        assert!(table.ids(0, &Term::Atom("terach")).is_empty());
+    }
+
+    #[test]
+    fn test_change_while_iterating() {
+        let mut table = FactTable::new(2);
+        for i in 0..1000 {
+            table.insert(vec![Term::Atom(i), Term::Atom(i % 2)]);
+        }
+        let table = Rc::new(RefCell::new(table));
+
+        // Assert a new row and retract an old one for each answer: the
+        // stream still returns exactly the rows present when it was solved.
+        for (args, old) in [
+            (
+                vec![Term::Variable(1), Term::Variable(2)],
+                (0..1000).collect::<Vec<_>>(),
+            ),
+            (
+                vec![Term::Variable(1), Term::Atom(0)],
+                (1000..2000).step_by(2).collect(),
+            ),
+        ] {
+            let facts = Facts::new(table.clone(), args);
+            let mut answers = vec![];
+            for substs in facts.solve(&HashMap::new()) {
+                let Term::Atom(i) = substs[&1] else {
+                    panic!("expected an atom");
+                };
+                let mut table = table.borrow_mut();
+                assert!(table.insert(vec![Term::Atom(i + 1000), Term::Atom(i % 2)]));
+                assert!(table.remove(&[Term::Atom(i), Term::Atom(i % 2)]));
+                answers.push(i);
+            }
+            assert_eq!(answers, old);
+        }
+        let table = table.borrow();
+        assert_eq!(table.len(), 1000);
+        // The retracted rows were kept while the streams were running.
+        assert_eq!(table.rows.len(), 2500);
+    }
+
+    #[test]
+    fn test_indexed_facts() {
+        let mut table = FactTable::new(2);
+        for i in 0..1000 {
+            table.insert(vec![Term::Atom(i), Term::Atom(i % 10)]);
+        }
+        let table = Rc::new(RefCell::new(table));
+
+        let mut substs = HashMap::new();
+        substs.insert(2, Term::Atom(500));
+        let facts = Facts::new(table.clone(), vec![Term::Variable(2), Term::Variable(1)]);
+        let mut iter = facts.solve(&substs);
+        let result = iter.next().unwrap();
+        assert_eq!(*result.get(&1).unwrap(), Term::Atom(0));
+        assert_eq!(iter.next(), None);
+
+        let facts = Facts::new(table, vec![Term::Variable(1), Term::Atom(3)]);
+        let iter = facts.solve(&HashMap::new());
+        assert_eq!(iter.count(), 100);
+    }
+
+    #[test]
+    fn test_ground_index() {
+        let mut table = FactTable::new(3);
+        for i in 0..100 {
+            table.insert(vec![
+                Term::Integer(i),
+                Term::String("row"),
+                Term::Tuple(vec![Term::Atom("pair"), Term::Integer(i % 5)]),
+            ]);
+        }
+        let table = Rc::new(RefCell::new(table));
+
+        // Integer, string and tuple arguments use the index.
+        let mut substs = HashMap::new();
+        substs.insert(1, Term::Integer(42));
+        let args = vec![Term::Variable(1), Term::String("row"), Term::Variable(2)];
+        assert_eq!(
+            table.borrow().select_index(&args, &substs),
+            Some((0, Term::Integer(42)))
+        );
+        let facts = Facts::new(table.clone(), args);
+        let mut iter = facts.solve(&substs);
+        let result = iter.next().unwrap();
+        assert_eq!(
+            *result.get(&2).unwrap(),
+            Term::Tuple(vec![Term::Atom("pair"), Term::Integer(2)])
+        );
+        assert_eq!(iter.next(), None);
+
+        // A list written with a tail has the same key as the tuple.
+        let list = Term::Cons(
+            Box::new(Term::Atom("pair")),
+            Box::new(Term::Tuple(vec![Term::Integer(3)])),
+        );
+        let args = vec![Term::Variable(1), Term::Variable(2), list];
+        assert_eq!(
+            table.borrow().select_index(&args, &HashMap::new()),
+            Some((2, Term::Tuple(vec![Term::Atom("pair"), Term::Integer(3)])))
+        );
+        let facts = Facts::new(table.clone(), args);
+        assert_eq!(facts.solve(&HashMap::new()).count(), 20);
+
+        // A partly bound tuple isn't ground, so it doesn't use the index.
+        let partial = Term::Tuple(vec![Term::Variable(3), Term::Integer(3)]);
+        let args = vec![Term::Variable(1), Term::Variable(2), partial];
+        assert_eq!(table.borrow().select_index(&args, &HashMap::new()), None);
+        let facts = Facts::new(table, args);
+        assert_eq!(facts.solve(&HashMap::new()).count(), 20);
+    }
+}
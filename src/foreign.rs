@@ -0,0 +1,88 @@
+use crate::logic::Goal;
+use crate::unification::{rename, Substitutions, Term};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// The implementation of a foreign relation, which receives the argument terms
+// of a call together with the substitutions, and produces a stream of
+// substitutions, one for each solution.
+pub type Relation<T> =
+    dyn Fn(&[Term<T>], &Substitutions<T>) -> Box<dyn Iterator<Item = Substitutions<T>>>;
+
+// A relation defined by the host program in Rust, which can be called from
+// Tern like any other relation.
+pub struct ForeignRelation<T> {
+    pub arity: usize,
+    pub relation: Rc<Relation<T>>,
+}
+
+// The Foreign goal calls a foreign relation with the arguments of the call.
+pub struct Foreign<T> {
+    relation: Rc<Relation<T>>,
+    args: Vec<Term<T>>,
+}
+
+impl<T> Foreign<T> {
+    pub fn new(relation: Rc<Relation<T>>, args: Vec<Term<T>>) -> Self {
+        Foreign { relation, args }
+    }
+}
+
+impl<T: Clone + 'static> Goal<T> for Foreign<T> {
+    fn solve(&self, substs: &Substitutions<T>) -> Box<dyn Iterator<Item = Substitutions<T>>> {
+        (self.relation)(&self.args, substs)
+    }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Foreign::new(
+            self.relation.clone(),
+            self.args.iter().map(|arg| rename(arg, renaming)).collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::foreign::*;
+    use crate::unification::{unify, walk};
+
+    #[test]
+    fn test_foreign() {
+        // Succ(x, y) holds if y is x + 1.
+        let succ: Rc<Relation<u64>> = Rc::new(|args, substs| {
+            let mut substs = substs.clone();
+            let result = match (walk(&args[0], &substs), walk(&args[1], &substs)) {
+                (Term::Integer(x), _) => {
+                    let y = Term::Integer(x + 1);
+                    unify(&args[1], &y, &mut substs)
+                }
+                (_, Term::Integer(y)) => {
+                    let x = Term::Integer(y - 1);
+                    unify(&args[0], &x, &mut substs)
+                }
+                _ => false,
+            };
+            if result {
+                Box::new(std::iter::once(substs))
+            } else {
+                Box::new(std::iter::empty())
+            }
+        });
+        let substs = HashMap::new();
+
+        let goal = Foreign::new(succ.clone(), vec![Term::Integer(1), Term::Variable(1)]);
+        let mut iter = goal.solve(&substs);
+        assert_eq!(*iter.next().unwrap().get(&1).unwrap(), Term::Integer(2));
+        assert_eq!(iter.next(), None);
+
+        let goal = Foreign::new(succ.clone(), vec![Term::Variable(1), Term::Integer(1)]);
+        let mut iter = goal.solve(&substs);
+        assert_eq!(*iter.next().unwrap().get(&1).unwrap(), Term::Integer(0));
+
+        let goal = Foreign::new(succ, vec![Term::Variable(1), Term::Variable(2)]);
+        assert_eq!(goal.solve(&substs).count(), 0);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::errors::DataError;
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    // Any other number, e.g. `1.5` or `1e100`, as it was written.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    // Keys are kept in the order in which they appear.
+    Object(Vec<(String, Value)>),
+}
+
+// A parser for a sequence of JSON values separated by whitespace, which covers
+// both ordinary JSON documents and JSON Lines. Numbers that aren't integers
+// are kept as they were written, since Tern does not have floating point
+// numbers.
+pub struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    pub fn new(src: &str) -> Self {
+        Parser {
+            chars: src.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    // Line and column of the next character.
+    pub fn location(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, DataError> {
+        Err(DataError {
+            msg: msg.to_string(),
+            line: self.line,
+            column: self.column,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), DataError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(&format!("Expected `{}`.", expected))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, DataError> {
+        for expected in keyword.chars() {
+            if self.peek() != Some(expected) {
+                return self.error("Unexpected character.");
+            }
+            self.advance();
+        }
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, DataError> {
+        let start = self.location();
+        self.advance();
+        let mut value = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(value),
+                Some('\\') => match self.advance() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('/') => value.push('/'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('u') => {
+                        let mut code = self.hex();
+                        // Characters outside the Basic Multilingual Plane are
+                        // escaped as a pair of surrogates.
+                        if let Some(high @ 0xD800..=0xDBFF) = code {
+                            code = match (self.advance(), self.advance(), self.hex()) {
+                                (Some('\\'), Some('u'), Some(low @ 0xDC00..=0xDFFF)) => {
+                                    Some(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                                }
+                                _ => None,
+                            };
+                        }
+                        match code.and_then(char::from_u32) {
+                            Some(c) => value.push(c),
+                            None => return self.error("Invalid unicode escape in string."),
+                        }
+                    }
+                    _ => return self.error("Invalid escape sequence in string."),
+                },
+                Some(c) => value.push(c),
+                None => {
+                    return Err(DataError {
+                        msg: "Unterminated string.".to_string(),
+                        line: start.0,
+                        column: start.1,
+                    });
+                }
+            }
+        }
+    }
+
+    // Read the four hexadecimal digits of a unicode escape.
+    fn hex(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.advance()?.to_digit(16)?;
+        }
+        Some(code)
+    }
+
+    fn number(&mut self) -> Result<Value, DataError> {
+        let start = self.location();
+        let mut text = String::new();
+        let mut integer = true;
+        // A number is an optional minus sign, digits, an optional fraction and
+        // an optional exponent, e.g. `-12.5e+3`.
+        if self.peek() == Some('-') {
+            text.extend(self.advance());
+        }
+        let mut valid = self.digits(&mut text);
+        if valid && self.peek() == Some('.') {
+            integer = false;
+            text.extend(self.advance());
+            valid = self.digits(&mut text);
+        }
+        if valid && matches!(self.peek(), Some('e' | 'E')) {
+            integer = false;
+            text.extend(self.advance());
+            if matches!(self.peek(), Some('+' | '-')) {
+                text.extend(self.advance());
+            }
+            valid = self.digits(&mut text);
+        }
+        if !valid {
+            return Err(DataError {
+                msg: "Invalid number.".to_string(),
+                line: start.0,
+                column: start.1,
+            });
+        }
+        match text.parse::<i64>() {
+            Ok(n) if integer => Ok(Value::Integer(n)),
+            _ => Ok(Value::Number(text)),
+        }
+    }
+
+    // Add the digits at the current position to `text`, returning false if
+    // there are none.
+    fn digits(&mut self, text: &mut String) -> bool {
+        let len = text.len();
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            text.push(c);
+            self.advance();
+        }
+        text.len() > len
+    }
+
+    fn value(&mut self) -> Result<Value, DataError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some('[') => {
+                self.advance();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.advance();
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.advance() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return self.error("Expected `,` or `]` in array."),
+                    }
+                }
+            }
+            Some('{') => {
+                self.advance();
+                let mut fields: Vec<(String, Value)> = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.advance();
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return self.error("Expected string as object key.");
+                    }
+                    let location = self.location();
+                    let key = self.string()?;
+                    if fields.iter().any(|(k, _)| *k == key) {
+                        return Err(DataError {
+                            msg: format!("Duplicate key {:?}.", key),
+                            line: location.0,
+                            column: location.1,
+                        });
+                    }
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.advance() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Value::Object(fields)),
+                        _ => return self.error("Expected `,` or `}` in object."),
+                    }
+                }
+            }
+            Some(_) => self.error("Unexpected character."),
+            None => self.error("Unexpected end of input."),
+        }
+    }
+}
+
+impl Iterator for Parser {
+    type Item = Result<Value, DataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_whitespace();
+        self.peek()?;
+        Some(self.value())
+    }
+}
+
+// Quote and escape a string for use in JSON output.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::*;
+
+    fn parse(src: &str) -> Vec<Value> {
+        Parser::new(src).map(|value| value.unwrap()).collect()
+    }
+
+    #[test]
+    fn parsing() {
+        assert!(parse(" \n").is_empty());
+        assert_eq!(
+            parse("[1, -2, \"a\\\"b\\u0041\\uD83D\\uDE00\", true, null, []]"),
+            vec![Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(-2),
+                Value::String("a\"bA\u{1F600}".to_string()),
+                Value::Bool(true),
+                Value::Null,
+                Value::Array(Vec::new()),
+            ])]
+        );
+        assert_eq!(
+            parse("{\"b\": 1, \"a\": {}}\n{}\n"),
+            vec![
+                Value::Object(vec![
+                    ("b".to_string(), Value::Integer(1)),
+                    ("a".to_string(), Value::Object(Vec::new())),
+                ]),
+                Value::Object(Vec::new()),
+            ]
+        );
+        // Numbers that aren't integers are kept as they were written.
+        assert_eq!(
+            parse("[1.5, -2E+3, 0.0, 1e2, 99999999999999999999]"),
+            vec![Value::Array(vec![
+                Value::Number("1.5".to_string()),
+                Value::Number("-2E+3".to_string()),
+                Value::Number("0.0".to_string()),
+                Value::Number("1e2".to_string()),
+                Value::Number("99999999999999999999".to_string()),
+            ])]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = Parser::new("{\"a\": 1}\n{\"a\": 1.}")
+            .nth(1)
+            .unwrap()
+            .err()
+            .unwrap();
+        assert_eq!(err.msg, "Invalid number.");
+        assert_eq!((err.line, err.column), (2, 7));
+
+        for src in ["-", "1e", "1e+", "-.5"] {
+            let err = Parser::new(src).next().unwrap().err().unwrap();
+            assert_eq!(err.msg, "Invalid number.");
+        }
+
+        let err = Parser::new("{\"a\": 1, \"a\": 2}")
+            .next()
+            .unwrap()
+            .err()
+            .unwrap();
+        assert_eq!(err.msg, "Duplicate key \"a\".");
+        assert_eq!((err.line, err.column), (1, 10));
+
+        let err = Parser::new("[1 2]").next().unwrap().err().unwrap();
+        assert_eq!(err.msg, "Expected `,` or `]` in array.");
+
+        let err = Parser::new("  \"abc").next().unwrap().err().unwrap();
+        assert_eq!(err.msg, "Unterminated string.");
+        assert_eq!((err.line, err.column), (1, 3));
+
+        for src in [
+            "\"\\uD83D\"",
+            "\"\\uD83Dx\"",
+            "\"\\uD83D\\u0041\"",
+            "\"\\uDE00\"",
+        ] {
+            let err = Parser::new(src).next().unwrap().err().unwrap();
+            assert_eq!(err.msg, "Invalid unicode escape in string.");
+        }
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+        assert_eq!(quote("\u{1}"), "\"\\u0001\"");
+    }
+}
@@ -0,0 +1,868 @@
+// The test suites use `assert!(false)` and build instruction vectors with
+// repeated pushes, and `AST` is the conventional name for the syntax tree.
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::upper_case_acronyms,
+    clippy::vec_init_then_push
+)]
+
+pub mod answer;
+mod bottomup;
+mod builtins;
+mod codegen;
+mod csv;
+mod datalog;
+pub mod errors;
+mod facts;
+mod foreign;
+mod json;
+pub mod logic;
+mod module;
+mod parser;
+mod prolog;
+mod relation;
+pub mod report;
+mod tokenizer;
+pub mod unification;
+mod vm;
+
+pub use answer::{Answer, Outcome, Value};
+pub use errors::Error;
+
+use std::cmp::{max, min};
+use std::rc::Rc;
+use unification::{Substitutions, Term};
+
+// The relations on lists that are loaded by `load_prelude`, e.g. `Appendo`.
+pub const PRELUDE: &str = include_str!("prelude.tern");
+
+// The relations the prelude exports. Its other relations are helpers, e.g.
+// `SameLengtho`, that are private to it.
+const PRELUDE_EXPORTS: [&str; 8] = [
+    "Membero", "Appendo", "Lengtho", "Reverseo", "Nth", "Selecto", "Permuteo", "Sorto",
+];
+
+// An interpreter for Tern programs. Definitions persist between calls to
+// `eval` and `query`, e.g.
+//
+//     let mut tern = Interpreter::new();
+//     tern.eval("Father('terach, 'abraham). Father('terach, 'nachor).")?;
+//     for answer in tern.query("Father('terach, x)")? {
+//         println!("{}", answer.get("x").and_then(Value::as_atom).unwrap());
+//     }
+pub struct Interpreter {
+    ctx: codegen::Context,
+    vm: vm::VirtualMachine,
+    prelude: bool,
+    // Whether the relations used by translated Prolog have been defined.
+    prolog: bool,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            ctx: codegen::Context::new(),
+            vm: vm::VirtualMachine::new(),
+            prelude: false,
+            prolog: false,
+        }
+    }
+
+    // Define the relations of the prelude. The prelude is a module, compiled
+    // the first time it is loaded.
+    pub fn load_prelude(&mut self) -> Result<(), Error> {
+        if !self.prelude {
+            let mut instr = Vec::new();
+            let exports = module::load(
+                PRELUDE,
+                "prelude.tern",
+                Default::default(),
+                &self.ctx,
+                &mut self.vm,
+                &mut instr,
+                0,
+            )?;
+            self.vm.stack.clear();
+            self.vm.callstack.clear();
+            self.vm.run(Rc::new(instr))?;
+            for name in PRELUDE_EXPORTS {
+                self.ctx.insert(exports[name], name);
+            }
+            self.prelude = true;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, ast: &parser::AST) -> Result<(), Error> {
+        self.vm.stack.clear();
+        self.vm.callstack.clear();
+        let mut instr = Vec::new();
+        let result = codegen::generate(ast, &mut self.ctx, &mut self.vm, &mut instr)
+            .map_err(Error::from)
+            .and_then(|()| self.vm.run(Rc::new(instr)).map_err(Error::from));
+        let mut modules = self.ctx.modules.borrow_mut();
+        match result {
+            Ok(()) => modules.commit(),
+            Err(_) => modules.rollback(),
+        }
+        result
+    }
+
+    // Evaluate a program, returning the outcome of its last statement, or
+    // None if that statement has no value, e.g. a fact.
+    pub fn eval(&mut self, src: &str) -> Result<Option<Outcome>, Error> {
+        let ast = parser::parse(tokenizer::scan(src)?)?;
+        self.run(&ast)?;
+        Ok(self.vm.stack.pop().map(|value| match value {
+            // A table of terms is an answer, e.g. from `next`.
+            vm::Value::Table(ref table) => match table
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    value.as_term().map(|term| {
+                        (
+                            self.vm.term_to_string(key),
+                            Value::from_term(term, &self.vm),
+                        )
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(bindings) => Outcome::Answer(Answer::new(bindings)),
+                None => Outcome::Other(value.to_string()),
+            },
+            vm::Value::None => Outcome::No,
+            vm::Value::Term(term) => Outcome::Value(Value::from_term(&term, &self.vm)),
+            vm::Value::Boolean(b) => Outcome::Value(Value::Boolean(b)),
+            value => Outcome::Other(value.to_string()),
+        }))
+    }
+
+    // Evaluate a Prolog program, defining a relation for each of its
+    // predicates, e.g. `Parent` for `parent/2`. Only pure Prolog is
+    // supported: facts and rules, lists, `=`, `\=`, `is` and the arithmetic
+    // comparisons.
+    pub fn eval_prolog(&mut self, src: &str) -> Result<Option<Outcome>, Error> {
+        let ast = prolog::parse(src)?;
+        if !self.prolog {
+            prolog::register(&mut self.vm);
+            self.prolog = true;
+        }
+        self.run(&ast)?;
+        Ok(None)
+    }
+
+    // Solve a goal, e.g. `Father('terach, x)`, returning an iterator over its
+    // answers.
+    pub fn query(&mut self, goal: &str) -> Result<impl Iterator<Item = Answer> + '_, Error> {
+        let goal = match parser::parse(tokenizer::scan(goal)?)? {
+            parser::AST::Program(mut statements) if statements.len() == 1 => statements.remove(0),
+            _ => {
+                return Err(Error::Syntax(errors::SyntaxError {
+                    msg: "Expected a single goal.".to_string(),
+                    offset: 0,
+                }))
+            }
+        };
+        let ast = parser::AST::Program(vec![parser::AST::FnCall(
+            "solve".to_string(),
+            vec![goal],
+            0,
+        )]);
+        self.run(&ast)?;
+        let (mut stream, variables) = match self.vm.stack.pop() {
+            Some(vm::Value::Stream(stream, variables)) => (stream, variables),
+            _ => {
+                return Err(Error::Runtime(errors::RuntimeError {
+                    msg: "Expected goal.".to_string(),
+                    ip: 0,
+                }))
+            }
+        };
+        let vm = &self.vm;
+        // The answers stop at the first error, e.g. a relation applied to the
+        // wrong number of arguments.
+        Ok(std::iter::from_fn(move || {
+            let substs = stream.next();
+            vm.goal_error.take().map_or(substs, |_| None)
+        })
+        .fuse()
+        .map(move |substs| {
+            Answer::new(
+                vm.answer(&substs, &variables)
+                    .iter()
+                    .map(|(key, value)| (vm.term_to_string(key), Value::from_term(value, vm)))
+                    .collect(),
+            )
+        }))
+    }
+
+    // Return the id of an interned atom or string, e.g. to build terms for
+    // foreign relations.
+    pub fn intern(&mut self, s: &str) -> u64 {
+        self.vm.intern(&s.to_string())
+    }
+
+    // Bind `name` to a relation implemented in Rust, which can then be called
+    // from Tern like any other relation. The relation receives the argument
+    // terms and the substitutions, and returns a stream of substitutions.
+    pub fn register_relation<F>(&mut self, name: &str, arity: usize, relation: F)
+    where
+        F: Fn(&[Term<u64>], &Substitutions<u64>) -> Box<dyn Iterator<Item = Substitutions<u64>>>
+            + 'static,
+    {
+        self.vm.register_relation(name, arity, relation);
+    }
+
+    // Resolve imports in the programs passed to `eval` relative to
+    // `directory`, e.g. the directory of the file being evaluated.
+    pub fn set_directory<P: AsRef<std::path::Path>>(&mut self, directory: P) {
+        self.ctx.directory = directory.as_ref().to_path_buf();
+    }
+
+    // Send the output of `print` and `println` to `output` rather than to
+    // stdout, e.g. to capture it in a buffer.
+    pub fn set_output<W: std::io::Write + 'static>(&mut self, output: W) {
+        self.vm.output = Box::new(output);
+    }
+
+    // Describe the call stack and the stack after a runtime error.
+    pub fn backtrace(&self) -> String {
+        let mut trace = String::new();
+        if self.vm.callstack.is_empty() {
+            trace += "Empty call stack.\n";
+        } else {
+            trace += "Call stack:\n";
+            for frame in self.vm.callstack.iter().rev() {
+                let start_ip = max(0, frame.ip as i64 - 10) as usize;
+                let end_ip = min(frame.instructions.len(), frame.ip + 10);
+                for ip in start_ip..end_ip {
+                    if ip == frame.ip {
+                        trace += &format!("->  {:04}| {:?}\n", ip, frame.instructions[ip]);
+                    } else {
+                        trace += &format!("    {:04}| {:?}\n", ip, frame.instructions[ip]);
+                    }
+                }
+            }
+        }
+        if self.vm.stack.is_empty() {
+            trace += "Empty stack.\n";
+        } else {
+            trace += "Stack:\n";
+            for sp in 0..self.vm.stack.len() {
+                trace += &format!("{:04}| {}\n", sp, self.vm.stack[sp]);
+            }
+        }
+        trace
+    }
+}
+
+// Translate the relations of a program to Datalog for Soufflé, returning the
+// rules and the name and contents of the `.facts` file of each relation
+// defined by facts. `source` names the program in a comment.
+pub fn export_datalog(src: &str, source: &str) -> Result<(String, Vec<(String, String)>), Error> {
+    let ast = parser::parse(tokenizer::scan(src)?)?;
+    let mut program = datalog::Program::new();
+    program.add(&ast)?;
+    Ok(program.souffle(source)?)
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn eval() {
+        let mut tern = Interpreter::new();
+        assert_eq!(
+            tern.eval("Father('terach, 'abraham). Father('terach, 'nachor).")
+                .unwrap(),
+            None
+        );
+        match tern.eval("next(solve(Father(x, 'nachor)))").unwrap() {
+            Some(Outcome::Answer(answer)) => {
+                assert_eq!(answer.len(), 1);
+                assert_eq!(answer.get("x").and_then(Value::as_atom), Some("terach"));
+            }
+            _ => assert!(false),
+        }
+        assert_eq!(
+            tern.eval("next(solve(Father(x, 'lot)))").unwrap(),
+            Some(Outcome::No)
+        );
+        // Unbound free variables are reported too.
+        match tern.eval("next(solve(conj { x == 'a, y == y }))").unwrap() {
+            Some(Outcome::Answer(answer)) => {
+                assert_eq!(answer.len(), 2);
+                assert_eq!(answer.get("x").and_then(Value::as_atom), Some("a"));
+                assert_eq!(answer.get("y"), Some(&Value::Variable("_".to_string())));
+            }
+            _ => assert!(false),
+        }
+        assert_eq!(
+            tern.eval("\"abc\"").unwrap(),
+            Some(Outcome::Value(Value::String("abc".to_string())))
+        );
+        assert_eq!(
+            tern.eval("false").unwrap(),
+            Some(Outcome::Value(Value::Boolean(false)))
+        );
+
+        match tern.eval("let x = ") {
+            Err(Error::Syntax(err)) => assert_eq!(err.offset, 6),
+            _ => assert!(false),
+        }
+        match tern.eval("next(solve(Mother(x)))") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Undefined function: Mother"),
+            _ => assert!(false),
+        }
+        match tern.eval("\"abc") {
+            Err(Error::Tokenizer(err)) => assert_eq!(err.msg, "Unterminated string."),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn query() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('terach, 'nachor).
+            Father('terach, 'haran).
+            Age('abraham, 175).",
+        )
+        .unwrap();
+        let sons: Vec<String> = tern
+            .query("Father('terach, x)")
+            .unwrap()
+            .map(|answer| answer.get("x").unwrap().to_string())
+            .collect();
+        assert_eq!(sons, vec!["abraham", "nachor", "haran"]);
+
+        let answer = tern.query("Age(x, y)").unwrap().next().unwrap();
+        assert_eq!(answer.get("x").and_then(Value::as_atom), Some("abraham"));
+        assert_eq!(answer.get("y").and_then(Value::as_integer), Some(175));
+
+        // Definitions persist between queries.
+        tern.eval("rel Patriarch(x) { x == 'abraham }").unwrap();
+        assert_eq!(tern.query("Patriarch(x)").unwrap().count(), 1);
+
+        assert!(tern.query("Father('terach, x) Father(x, y)").is_err());
+        assert!(tern.query("'abraham").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('terach, 'nachor).
+            let y = 'global
+            fn sons(f) { let y = run(x, Father(f, x)) y }
+            fn second(a, b) { return b }
+            fn nothing() { }",
+        )
+        .unwrap();
+        match tern.eval("sons('terach)").unwrap() {
+            Some(Outcome::Value(value)) => assert_eq!(value.to_string(), "[abraham, nachor]"),
+            _ => assert!(false),
+        }
+        // The let binding in `sons` is local to the call.
+        match tern.eval("y").unwrap() {
+            Some(Outcome::Value(value)) => assert_eq!(value.as_atom(), Some("global")),
+            _ => assert!(false),
+        }
+        match tern.eval("second('abraham, 'sarah)").unwrap() {
+            Some(Outcome::Value(value)) => assert_eq!(value.as_atom(), Some("sarah")),
+            _ => assert!(false),
+        }
+        assert_eq!(tern.eval("nothing()").unwrap(), Some(Outcome::No));
+        match tern.eval("second('abraham, 'sarah, 'hagar)") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "TypeError: Expected 2 argument(s) but found 3.")
+            }
+            _ => assert!(false),
+        }
+        assert!(tern.eval("second('abraham)").is_err());
+
+        match tern.eval("return 'abraham") {
+            Err(Error::Syntax(err)) => {
+                assert_eq!(err.msg, "Unexpected `return` outside of function.")
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("fn f(x) { g(x) }") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Undefined function: g"),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn closures() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('abraham, 'isaac).
+            Father('isaac, 'jacob).
+            rel Twice(r, x, z) { var (y) { conj { r(x, y), r(y, z) } } }
+            rel Ancestor(x, y) {
+                disj { Father(x, y) | var (z) { conj { Father(x, z), Ancestor(z, y) } } }
+            }
+            let son = rel (x, y) { Father(x, y) }
+            fn twice(r) { rel (x, z) { Twice(r, x, z) } }",
+        )
+        .unwrap();
+        let grandsons = |tern: &mut Interpreter, src: &str| -> Vec<String> {
+            tern.query(src)
+                .unwrap()
+                .map(|answer| answer.get("z").unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(grandsons(&mut tern, "Twice(Father, 'terach, z)"), ["isaac"]);
+        assert_eq!(grandsons(&mut tern, "Twice(son, 'abraham, z)"), ["jacob"]);
+        assert_eq!(grandsons(&mut tern, "son('isaac, z)"), ["jacob"]);
+        // The closure returned by `twice` captures its argument.
+        tern.eval("let g = twice(Father)").unwrap();
+        assert_eq!(grandsons(&mut tern, "g('terach, z)"), ["isaac"]);
+        // Passing the same relation again reuses its term.
+        let relations = tern.vm.relations.borrow().len();
+        assert_eq!(grandsons(&mut tern, "Twice(son, 'abraham, z)"), ["jacob"]);
+        assert_eq!(tern.vm.relations.borrow().len(), relations);
+        assert_eq!(
+            grandsons(&mut tern, "Ancestor('terach, z)"),
+            ["abraham", "isaac", "jacob"]
+        );
+        // Each call to a relation has its own variables.
+        assert_eq!(
+            grandsons(
+                &mut tern,
+                "conj { Ancestor('terach, z), Ancestor('isaac, z) }"
+            ),
+            ["jacob"]
+        );
+        match tern.eval("next(solve(son('isaac, z, 'y)))") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "TypeError: Expected 2 argument(s) but found 3.")
+            }
+            _ => assert!(false),
+        }
+        assert!(tern.eval("next(solve(Ancestor(z)))").is_err());
+        // So is applying a relation passed as an argument to the wrong number
+        // of arguments, rather than finding no answers.
+        tern.eval("rel Female(x) { x == 'sarah }").unwrap();
+        match tern.eval("next(solve(Twice(Female, 'sarah, z)))") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "TypeError: Expected 1 argument(s) but found 2.")
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("for a in solve(Twice(Female, 'sarah, z)) { a }") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "TypeError: Expected 1 argument(s) but found 2.")
+            }
+            _ => assert!(false),
+        }
+        // A query stops at the error, which isn't reported by a later program.
+        assert!(grandsons(&mut tern, "Twice(Female, 'sarah, z)").is_empty());
+        assert_eq!(grandsons(&mut tern, "Twice(Father, 'terach, z)"), ["isaac"]);
+    }
+
+    #[test]
+    fn matching() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "fn describe(x) {
+                match x {
+                    [] => 'empty,
+                    [h] => h,
+                    [a, a | _] => 'pair,
+                    [_ | t] => t,
+                    'nil => 'nil,
+                    _ => 'other
+                }
+            }",
+        )
+        .unwrap();
+        let describe = |tern: &mut Interpreter, src: &str| match tern.eval(src).unwrap() {
+            Some(Outcome::Value(value)) => value.to_string(),
+            Some(Outcome::No) => "No.".to_string(),
+            _ => panic!("expected a value from {}", src),
+        };
+        assert_eq!(describe(&mut tern, "describe([])"), "empty");
+        assert_eq!(describe(&mut tern, "describe(['a])"), "a");
+        assert_eq!(describe(&mut tern, "describe(['a, 'a, 'b])"), "pair");
+        assert_eq!(describe(&mut tern, "describe(['a, 'b, 'c])"), "[b, c]");
+        assert_eq!(describe(&mut tern, "describe('nil)"), "nil");
+        assert_eq!(describe(&mut tern, "describe(42)"), "other");
+        assert_eq!(describe(&mut tern, "match 'a { 'b => 'b }"), "No.");
+        // Pattern variables are only bound in their arm.
+        match tern.eval("match 'a { x => x } x") {
+            Ok(Some(Outcome::Value(value))) => assert_eq!(value.to_string(), "x"),
+            _ => assert!(false),
+        }
+        // Lists unify with tuples in goals too.
+        let answers: Vec<Answer> = tern.query("['a, 'b] == [h | t]").unwrap().collect();
+        assert_eq!(answers[0].get("t").unwrap().to_string(), "[b]");
+        let answers: Vec<Answer> = tern.query("x == ['a | t]").unwrap().collect();
+        assert_eq!(answers[0].get("x").unwrap().to_string(), "[a | t]");
+
+        match tern.eval("match {'a: 'b} { _ => 'a }") {
+            Err(Error::Runtime(err)) => assert_eq!(err.msg, "TypeError: Expected term to match."),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn tables() {
+        let mut tern = Interpreter::new();
+        tern.eval("Father('terach, 'abraham).").unwrap();
+        let value = |tern: &mut Interpreter, src: &str| match tern.eval(src).unwrap() {
+            Some(Outcome::Value(value)) => value.to_string(),
+            Some(Outcome::No) => "No.".to_string(),
+            Some(Outcome::Other(value)) => value,
+            _ => panic!("expected a value from {}", src),
+        };
+        // Answer tables can be queried by variable name.
+        assert_eq!(
+            value(&mut tern, "next(solve(Father('terach, x))).x"),
+            "abraham"
+        );
+        tern.eval("let t = {'name: 'isaac, 'inner: {'k: 'v}}")
+            .unwrap();
+        assert_eq!(value(&mut tern, "t.name"), "isaac");
+        assert_eq!(value(&mut tern, "t['name]"), "isaac");
+        assert_eq!(value(&mut tern, "t.inner.k"), "v");
+        assert_eq!(value(&mut tern, "t.missing"), "No.");
+        // Tables can hold goals, which can be solved later.
+        tern.eval("let g = {'goal: Father(x, 'abraham)}").unwrap();
+        assert!(value(&mut tern, "g").contains("goal"));
+        assert_eq!(value(&mut tern, "next(solve(g.goal)).x"), "terach");
+        // Tables are shared, so changes are seen through every reference.
+        tern.eval(
+            "let u = t
+            u.name = 'jacob
+            fn mark(table, k) { table[k] = 'marked }
+            mark(t, 'seen)",
+        )
+        .unwrap();
+        assert_eq!(value(&mut tern, "t.name"), "jacob");
+        assert_eq!(value(&mut tern, "u.seen"), "marked");
+    }
+
+    #[test]
+    fn control_flow() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Father('terach, 'abraham).
+            Father('terach, 'nachor).
+            Father('abraham, 'isaac).
+            let sons = {}
+            for answer in solve(Father('terach, x)) {
+                sons[answer.x] = true
+            }
+            fn son(x) {
+                if sons[x] { return 'yes } else if false { return 'never }
+                'no
+            }
+            fn first(g) {
+                for answer in solve(g) { return answer.y }
+                'none
+            }
+            let found = {}
+            for answer in solve(Father(x, y)) {
+                found[answer.x] = answer.y
+                break
+            }
+            let t = {'go: true, 'count: 0}
+            while t.go {
+                t.go = false
+                t.count = 1
+            }",
+        )
+        .unwrap();
+        let value = |tern: &mut Interpreter, src: &str| match tern.eval(src).unwrap() {
+            Some(Outcome::Value(value)) => value.to_string(),
+            Some(Outcome::No) => "No.".to_string(),
+            Some(Outcome::Other(value)) => value,
+            _ => panic!("expected a value from {}", src),
+        };
+        assert_eq!(value(&mut tern, "keys(sons)"), "[abraham, nachor]");
+        assert_eq!(value(&mut tern, "sons['nachor]"), "true");
+        assert_eq!(value(&mut tern, "son('nachor)"), "yes");
+        assert_eq!(value(&mut tern, "son('isaac)"), "no");
+        assert_eq!(value(&mut tern, "first(Father('abraham, y))"), "isaac");
+        assert_eq!(value(&mut tern, "first(Father('lot, y))"), "none");
+        assert_eq!(value(&mut tern, "keys(found)"), "[terach]");
+        assert_eq!(value(&mut tern, "t.count"), "1");
+        assert_eq!(value(&mut tern, "t.go"), "false");
+    }
+
+    #[test]
+    fn modules() {
+        let dir = std::env::temp_dir().join(format!("tern-modules-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("lib").join("family.tern"),
+            "Father('terach, 'abraham).
+            Father('abraham, 'isaac).
+            let helper = 'private
+            rel Grandfather(x, z) { var (y) { conj { Father(x, y), Father(y, z) } } }",
+        )
+        .unwrap();
+        std::fs::write(dir.join("a.tern"), "import b").unwrap();
+        std::fs::write(dir.join("b.tern"), "import a").unwrap();
+        std::fs::write(dir.join("bad.tern"), "\n let = 'a").unwrap();
+
+        let mut tern = Interpreter::new();
+        tern.set_directory(&dir);
+        tern.eval("import \"lib/family.tern\" as f").unwrap();
+        let answers: Vec<Answer> = tern.query("f.Grandfather('terach, z)").unwrap().collect();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].get("z").and_then(Value::as_atom), Some("isaac"));
+        // Only relations are exported, and only with the module's name.
+        match tern.eval("Grandfather('terach, z)") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Undefined function: Grandfather"),
+            _ => assert!(false),
+        }
+        match tern.eval("helper") {
+            Ok(Some(Outcome::Value(value))) => assert_eq!(value.to_string(), "helper"),
+            _ => assert!(false),
+        }
+        // A module is only loaded once, so importing it again doesn't add
+        // its facts twice.
+        tern.eval("import \"lib/family.tern\"").unwrap();
+        let answers: Vec<Answer> = tern.query("Father(x, y)").unwrap().collect();
+        assert_eq!(answers.len(), 2);
+
+        // A module imported by a program that fails is loaded again by the
+        // next program that imports it.
+        std::fs::write(dir.join("fam.tern"), "Father('terach, 'nachor).").unwrap();
+        match tern.eval("import fam undefined_fn()") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Undefined function: undefined_fn"),
+            _ => assert!(false),
+        }
+        match tern.eval("import fam next(solve(Father(x, 'nachor)))") {
+            Ok(Some(Outcome::Answer(answer))) => {
+                assert_eq!(answer.get("x").and_then(Value::as_atom), Some("terach"))
+            }
+            _ => assert!(false),
+        }
+
+        match tern.eval("import a") {
+            Err(Error::Syntax(err)) => {
+                assert_eq!(
+                    err.msg,
+                    "a.tern:1:6: b.tern:1:6: Import cycle: a.tern -> b.tern -> a.tern."
+                );
+                assert_eq!(err.offset, 5);
+            }
+            _ => assert!(false),
+        }
+        match tern.eval("import bad") {
+            Err(Error::Syntax(err)) => assert_eq!(
+                err.msg,
+                "bad.tern:2:4: Expected literal while parsing variable."
+            ),
+            _ => assert!(false),
+        }
+        match tern.eval("import missing") {
+            Err(Error::Syntax(err)) => assert!(err.msg.starts_with("Can't import")),
+            _ => assert!(false),
+        }
+        match tern.eval("fn f() { import a }") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Unexpected `import` inside function."),
+            _ => assert!(false),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prelude() {
+        let mut tern = Interpreter::new();
+        tern.load_prelude().unwrap();
+        // Loading the prelude again doesn't redefine its relations.
+        tern.load_prelude().unwrap();
+        // The helpers of the prelude are private to it.
+        match tern.eval("SameLengtho(['a], ['b])") {
+            Err(Error::Syntax(err)) => assert_eq!(err.msg, "Undefined function: SameLengtho"),
+            _ => assert!(false),
+        }
+        let mut run = |src: &str| match tern.eval(src).unwrap() {
+            Some(Outcome::Value(value)) => value.to_string(),
+            _ => panic!("expected a value from {}", src),
+        };
+        assert_eq!(run("run(x, Membero(x, ['a, 'b, 'c]))"), "[a, b, c]");
+        assert_eq!(run("run(x, Membero('b, ['a, 'b, 'c]))"), "[x]");
+        assert_eq!(run("run(x, Membero('d, ['a, 'b, 'c]))"), "[]");
+        assert_eq!(run("run(x, Appendo(['a], ['b, 'c], x))"), "[[a, b, c]]");
+        assert_eq!(
+            run("run(x, var (y) { Appendo(x, y, ['a, 'b]) })"),
+            "[[], [a], [a, b]]"
+        );
+        assert_eq!(run("run(x, Appendo(['a], x, ['a, 'b]))"), "[[b]]");
+        assert_eq!(run("run(n, Lengtho(['a, 'b], n))"), "[[s, [s, z]]]");
+        assert_eq!(run("run(n, Lengtho([], n))"), "[z]");
+        assert_eq!(run("next(solve(Lengtho(l, ['s, 'z]))).l"), "[_]");
+        assert_eq!(run("run(r, Reverseo(['a, 'b, 'c], r))"), "[[c, b, a]]");
+        assert_eq!(run("run(l, Reverseo(l, ['a, 'b, 'c]))"), "[[c, b, a]]");
+        assert_eq!(run("run(x, Nth(['a, 'b, 'c], ['s, 'z], x))"), "[b]");
+        assert_eq!(run("run(n, Nth(['a, 'b, 'c], n, 'c))"), "[[s, [s, z]]]");
+        assert_eq!(run("run(r, Selecto('b, ['a, 'b, 'c], r))"), "[[a, c]]");
+        assert_eq!(run("run(x, Selecto(x, ['a, 'b], ['b]))"), "[a]");
+        assert_eq!(
+            run("run(p, Permuteo(['a, 'b, 'c], p))"),
+            "[[a, b, c], [a, c, b], [b, a, c], [b, c, a], [c, a, b], [c, b, a]]"
+        );
+        assert_eq!(run("run(l, Permuteo(l, ['a, 'b]))"), "[[a, b], [b, a]]");
+        assert_eq!(
+            run("run(s, Sorto([['s, ['s, 'z]], 'z, ['s, 'z]], s))"),
+            "[[z, [s, z], [s, [s, z]]]]"
+        );
+        assert_eq!(run("run(s, Sorto(['z, 'z], s))"), "[[z, z]]");
+        assert_eq!(run("run(x, Sorto([['s, 'z], 'z], ['z, ['s, 'z]]))"), "[x]");
+        assert_eq!(run("run(x, Sorto([['s, 'z], 'z], [['s, 'z], 'z]))"), "[]");
+    }
+
+    #[test]
+    fn clauses() {
+        let mut tern = Interpreter::new();
+        tern.eval(
+            "Parent('abe, 'homer).
+             Parent('homer, 'bart).
+             Parent('homer, 'lisa).
+             Grandparent(x, z) :- Parent(x, y), Parent(y, z).
+             Sibling(x, y) :- Parent(p, x), Parent(p, y), disj { x == 'bart | y == 'bart }.
+             Last(l, x) :- l == [x].
+             Last([_ | t], x) :- Last(t, x).",
+        )
+        .unwrap();
+        let mut run = |src: &str| match tern.eval(src).unwrap() {
+            Some(Outcome::Value(value)) => value.to_string(),
+            _ => panic!("expected a value from {}", src),
+        };
+        assert_eq!(run("run(x, Grandparent('abe, x))"), "[bart, lisa]");
+        assert_eq!(run("run(x, Grandparent(x, 'lisa))"), "[abe]");
+        assert_eq!(run("run(x, Sibling(x, 'lisa))"), "[bart]");
+        assert_eq!(run("run(x, Last(['a, 'b, 'c], x))"), "[c]");
+        assert_eq!(run("run(x, Last([], x))"), "[]");
+        // Clauses in later programs are added to the earlier ones.
+        run("Color(x) :- x == 'red. 'a");
+        run("Color(x) :- x == 'blue. 'a");
+        assert_eq!(run("run(x, Color(x))"), "[red, blue]");
+
+        // A relation can't be defined by both clauses and facts, in either
+        // order, in the same program or not.
+        let mut mixed = |src: &str| match tern.eval(src) {
+            Err(Error::Syntax(err)) => (err.msg, err.offset),
+            _ => panic!("expected a syntax error from {}", src),
+        };
+        assert_eq!(
+            mixed("Anc('a, 'b). Anc(x, y) :- x == 'c, y == 'd."),
+            ("Anc is defined by both clauses and facts.".to_string(), 15)
+        );
+        assert_eq!(
+            mixed("Edge('a, 'b). Edge(x, z) :- Edge(x, y), Edge(y, z)."),
+            ("Edge is defined by both clauses and facts.".to_string(), 17)
+        );
+        assert_eq!(
+            mixed("Up(x) :- x == 'a. println('lost) Up('b)."),
+            ("Up is defined by both clauses and facts.".to_string(), 34)
+        );
+        assert_eq!(
+            mixed("Color('green)."),
+            ("Color is defined by both clauses and facts.".to_string(), 4)
+        );
+        assert_eq!(
+            mixed("Color(x, y) :- x == y."),
+            ("Expected 1 argument(s) in clause for Color.".to_string(), 4)
+        );
+    }
+
+    #[test]
+    fn prolog() {
+        let mut tern = Interpreter::new();
+        tern.eval_prolog(
+            "parent(abe, homer).
+             parent(homer, bart).
+             parent(homer, lisa).
+             sibling(X, Y) :- parent(P, X), parent(P, Y), X \\= Y.
+             len([], 0).
+             len([_|T], N) :- len(T, M), N is M + 1.
+             fact(0, 1).
+             fact(N, F) :- N > 0, M is N - 1, fact(M, G), F is N * G.
+             mod3(X, Y) :- Y is X mod 3.
+             shape(circle(3)).
+             list(X) :- X = [circle, 3].
+             plus(X) :- X = 1 + 2.
+             nolist(X) :- f(X) \\= [f, X].",
+        )
+        .unwrap();
+        let mut run = |src: &str| match tern.eval(src).unwrap() {
+            Some(Outcome::Value(value)) => value.to_string(),
+            _ => panic!("expected a value from {}", src),
+        };
+        assert_eq!(run("run(x, Parent('homer, x))"), "[bart, lisa]");
+        assert_eq!(run("run(x, Sibling('bart, x))"), "[lisa]");
+        assert_eq!(run("run(n, Len(['a, 'b, 'c], n))"), "[3]");
+        assert_eq!(run("run(f, Fact(5, f))"), "[120]");
+        assert_eq!(run("run(y, Mod3(-7, y))"), "[2]");
+        // Arithmetic on unbound variables has no answers.
+        assert_eq!(run("run(y, Mod3(x, y))"), "[]");
+        // Compound terms don't unify with lists, and lists aren't arithmetic.
+        assert_eq!(run("run(x, conj { Shape(x), List(x) })"), "[]");
+        assert_eq!(run("run(x, Nolist('a))"), "[x]");
+        assert_eq!(run("run(x, var (y) { conj { List(y), $is(x, y) } })"), "[]");
+        assert_eq!(
+            run("run(x, var (y) { conj { Plus(y), $is(x, y) } })"),
+            "[3]"
+        );
+    }
+
+    #[test]
+    fn foreign_relations() {
+        let mut tern = Interpreter::new();
+        // Ages(x, y) holds for the people and ages in a host table.
+        let ages: Vec<(u64, i64)> =
+            vec![(tern.intern("abraham"), 175), (tern.intern("isaac"), 180)];
+        tern.register_relation("Ages", 2, move |args, substs| {
+            let args = args.to_vec();
+            let substs = substs.clone();
+            Box::new(ages.clone().into_iter().filter_map(move |(name, age)| {
+                let mut substs = substs.clone();
+                if unification::unify(&args[0], &Term::Atom(name), &mut substs)
+                    && unification::unify(&args[1], &Term::Integer(age), &mut substs)
+                {
+                    Some(substs)
+                } else {
+                    None
+                }
+            }))
+        });
+
+        let answers: Vec<Answer> = tern.query("Ages(x, y)").unwrap().collect();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[1].get("x").and_then(Value::as_atom), Some("isaac"));
+        assert_eq!(answers[1].get("y").and_then(Value::as_integer), Some(180));
+
+        match tern.eval("next(solve(Ages(x, 175)))").unwrap() {
+            Some(Outcome::Answer(answer)) => {
+                assert_eq!(answer.get("x").and_then(Value::as_atom), Some("abraham"))
+            }
+            _ => assert!(false),
+        }
+        assert_eq!(tern.query("Ages('lot, y)").unwrap().count(), 0);
+        match tern.eval("next(solve(Ages(x)))") {
+            Err(Error::Runtime(err)) => {
+                assert_eq!(err.msg, "TypeError: Expected 2 argument(s) but found 1.")
+            }
+            _ => assert!(false),
+        }
+        assert!(tern.eval("next(solve(Ages(x, y, 'z)))").is_err());
+    }
+}
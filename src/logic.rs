@@ -1,4 +1,5 @@
-use crate::unification::{unify, Substitutions, Term};
+use crate::unification::{rename, unify, Substitutions, Term};
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::rc::Rc;
 
@@ -6,6 +7,10 @@ use std::rc::Rc;
 // argument, and produces a stream of substitutions as a result.
 pub trait Goal<T> {
     fn solve(&self, substs: &Substitutions<T>) -> Box<dyn Iterator<Item = Substitutions<T>>>;
+
+    // Return a copy of the goal with its variables renamed, as described by
+    // `unification::rename`.
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>>;
 }
 
 // The EqualsExpr goal produces either a singleton stream, if left and
@@ -44,6 +49,13 @@ impl<T: std::cmp::PartialEq + Clone + 'static> Goal<T> for Unify<T> {
             substs: substs.clone(),
         })
     }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Unify::new(
+            rename(&self.left, renaming),
+            rename(&self.right, renaming),
+        ))
+    }
 }
 
 impl<T: std::cmp::PartialEq + Clone> Iterator for UnifyIterator<T> {
@@ -107,6 +119,13 @@ impl<T: Clone + 'static> Goal<T> for Disj2<T> {
             phantom: PhantomData,
         })
     }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Disj2::new(
+            self.left.rename(renaming),
+            self.right.rename(renaming),
+        ))
+    }
 }
 
 impl<T: Clone> Iterator for Disj2Iterator<T> {
@@ -165,6 +184,13 @@ impl<T: Clone + 'static> Goal<T> for Conj2<T> {
             phantom: PhantomData,
         })
     }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Conj2::new(
+            self.left.rename(renaming),
+            self.right.rename(renaming),
+        ))
+    }
 }
 
 impl<T: Clone> Iterator for Conj2Iterator<T> {
@@ -217,6 +243,10 @@ mod tests {
                 substs: Some(substs.clone()),
             })
         }
+
+        fn rename(&self, _: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+            Rc::new(Succeed {})
+        }
     }
 
     impl<T: Clone> Iterator for SucceedIterator<T> {
@@ -246,6 +276,10 @@ mod tests {
                 phantom: PhantomData,
             })
         }
+
+        fn rename(&self, _: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+            Rc::new(Fail {})
+        }
     }
 
     impl<T> Iterator for FailureIterator<T> {
@@ -1,191 +1,348 @@
-mod codegen;
-mod errors;
-mod logic;
-mod parser;
-mod tokenizer;
-mod unification;
-mod vm;
-
-use std::cmp::{max, min};
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{self, BufRead, Write};
-use std::rc::Rc;
+use std::process;
+use tern::report::{self, Format, Report};
+use tern::Interpreter;
+
+// Evaluate a program and print its result, returning false if it failed.
+fn eval(filename: &str, src: &str, format: Format, tern: &mut Interpreter) -> bool {
+    let result = tern.eval(src);
+    report(filename, src, &result, format, tern)
+}
 
-fn display_error(filename: &str, src: &str, err_type: &str, err_msg: &str, err_offset: usize) {
-    let lines: Vec<&str> = src.split('\n').collect();
-    let mut line = 0;
-    let mut col = 0;
-    let mut count = 0;
-    for ch in src.chars() {
-        col += 1;
-        count += 1;
-        if count == err_offset {
-            break;
+fn report(
+    filename: &str,
+    src: &str,
+    result: &Result<Option<tern::Outcome>, tern::Error>,
+    format: Format,
+    tern: &Interpreter,
+) -> bool {
+    let report = Report {
+        result,
+        filename,
+        src,
+    };
+    report::print(&report, format, tern);
+    result.is_ok()
+}
+
+// Print the header for the output format, if any, before the first result.
+fn print_header(format: Format, printed: &mut bool) {
+    if !*printed {
+        if let Some(header) = format.header() {
+            println!("{}", header);
         }
-        if ch == '\n' {
-            line += 1;
-            col = 0;
+        *printed = true;
+    }
+}
+
+// Return the program that loads a CSV file for `--load-csv Relation=filename`,
+// skipping the first row if `header` is true.
+fn load_csv_program(spec: &str, header: bool) -> Result<String, String> {
+    let (relation, filename) = match spec.split_once('=') {
+        Some((relation, filename)) if !relation.is_empty() && !filename.is_empty() => {
+            (relation, filename)
+        }
+        _ => return Err("Expected Relation=filename after --load-csv.".to_string()),
+    };
+    // The relation is part of the program, so it must be a relation name.
+    if !relation.starts_with(char::is_uppercase)
+        || !relation.chars().all(|c| c.is_alphanumeric() || c == '_')
+    {
+        return Err(format!(
+            "Expected a relation name before = after --load-csv, not {}.",
+            relation
+        ));
+    }
+    let filename = filename.replace('\\', "\\\\").replace('"', "\\\"");
+    Ok(if header {
+        format!("load_csv(\"{}\", {}, 'header)", filename, relation)
+    } else {
+        format!("load_csv(\"{}\", {})", filename, relation)
+    })
+}
+
+// Return the name a file is exported under, its name without an extension.
+fn export_stem(filename: &str) -> String {
+    std::path::Path::new(filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "program".to_string())
+}
+
+// Return the files and output directory for
+// `tern export --datalog [--output-dir dir] file.tern...`.
+fn export_options(args: Vec<String>) -> Result<(Vec<String>, std::path::PathBuf), String> {
+    let mut datalog = false;
+    let mut directory = std::path::PathBuf::from(".");
+    let mut files = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--datalog" {
+            datalog = true;
+        } else if arg == "--output-dir" {
+            match args.next() {
+                Some(dir) => directory = dir.into(),
+                None => return Err("Expected a directory after --output-dir.".to_string()),
+            }
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown option for export: {}.", arg));
+        } else {
+            files.push(arg);
         }
     }
-    let width = line.to_string().len() + 2;
-    println!("{}: {}", err_type, err_msg);
-    println!("{s:>width$}|", s = " ", width = width);
-    if line > 0 {
-        println!(" {} | {}", line, lines[line - 1]);
+    if !datalog {
+        return Err("Expected --datalog after export, the only supported format.".to_string());
     }
-    print!("{s:>width$}|", s = " ", width = width);
-    println!("{s:>width$}^", s = " ", width = col);
-    println!("--> {}:{}", filename, line);
+    if files.is_empty() {
+        return Err("Expected a file to export.".to_string());
+    }
+    // Each file's .facts files are named after its relations, so when
+    // several files are exported, each is written to a directory of its own.
+    let mut stems = std::collections::HashSet::new();
+    for filename in &files {
+        if !stems.insert(export_stem(filename)) {
+            return Err(format!(
+                "Can't export two files named {}.",
+                export_stem(filename)
+            ));
+        }
+    }
+    Ok((files, directory))
 }
 
-fn eval(filename: &str, src: &str, ctx: &mut codegen::Context, vm: &mut vm::VirtualMachine) {
-    vm.stack.clear();
-    vm.callstack.clear();
-    match tokenizer::scan(src) {
-        Ok(tokens) => match parser::parse(tokens) {
-            Ok(ast) => {
-                let mut instr = Vec::new();
-                match codegen::generate(&ast, ctx, vm, &mut instr) {
-                    Ok(()) => match vm.run(Rc::new(instr)) {
-                        Ok(()) => {
-                            match vm.stack.pop() {
-                                Some(vm::Value::Table(substs)) => {
-                                    if substs.is_empty() {
-                                        println!("Ok.");
-                                    } else {
-                                        for subst in substs {
-                                            match subst.0 {
-                                                unification::Term::Variable(a) => {
-                                                    if let Some(name) = vm.lookup_variable(&a) {
-                                                        print!("{}: ", name);
-                                                    } else {
-                                                        print!("{}: ", a);
-                                                    }
-                                                }
-                                                _ => unreachable!(
-                                                    "expected variable as substitution key"
-                                                ),
-                                            }
-                                            match subst.1 {
-                                            unification::Term::Atom(a) => {
-                                                if let Some(interned) = vm.lookup_interned(&a) {
-                                                    println!("{}", interned);
-                                                } else {
-                                                    println!("{}", a);
-                                                }
-                                            },
-                                            unification::Term::Variable(a) => {
-                                                if let Some(name) = vm.lookup_variable(&a) {
-                                                    println!("{}", name);
-                                                } else {
-                                                    println!("{}", a);
-                                                }
-                                            }
-                                            _ => unreachable!("expected atom or variable as substitution value")
-                                        }
-                                        }
-                                    }
-                                }
-                                Some(vm::Value::None) => {
-                                    println!("No.");
-                                }
-                                Some(value) => {
-                                    println!("{}", value);
-                                }
-                                _ => {}
-                            }
-                        }
-                        Err(err) => {
-                            println!("RuntimeError: {}", err.msg);
-                            if vm.callstack.is_empty() {
-                                println!("Empty call stack.");
-                            } else {
-                                println!("Call stack:");
-                                for callable in vm.callstack.iter().rev() {
-                                    if let vm::Value::Callable {
-                                        kind,
-                                        parameters,
-                                        instructions,
-                                        ip: callable_ip,
-                                    } = callable
-                                    {
-                                        let start_ip = max(0, *callable_ip as i64 - 10) as usize;
-                                        let end_ip = min(instructions.len(), *callable_ip + 10);
-                                        for ip in start_ip..end_ip {
-                                            if ip == *callable_ip {
-                                                println!("->  {:04}| {:?}", ip, instructions[ip]);
-                                            } else {
-                                                println!("    {:04}| {:?}", ip, instructions[ip]);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            if vm.stack.is_empty() {
-                                println!("Empty stack.");
-                            } else {
-                                println!("Stack:");
-                                for sp in 0..vm.stack.len() {
-                                    println!("{:04}| {}", sp, vm.stack[sp]);
-                                }
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        display_error(filename, src, "SyntaxError", &err.msg, err.offset);
+// tern export --datalog [--output-dir dir] file.tern...
+fn export(args: Vec<String>, tern: &Interpreter) -> io::Result<()> {
+    let (files, directory) = match export_options(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    let fail = |path: &std::path::Path, err: io::Error| -> ! {
+        eprintln!("Failed to export {}: {}.", path.display(), err);
+        process::exit(1);
+    };
+    for filename in &files {
+        let src = match std::fs::read_to_string(filename) {
+            Ok(src) => src,
+            Err(err) => fail(filename.as_ref(), err),
+        };
+        match tern::export_datalog(&src, filename) {
+            Ok((rules, facts)) => {
+                let directory = if files.len() > 1 {
+                    directory.join(export_stem(filename))
+                } else {
+                    directory.clone()
+                };
+                if let Err(err) = std::fs::create_dir_all(&directory) {
+                    fail(&directory, err);
+                }
+                let path = directory.join(format!("{}.dl", export_stem(filename)));
+                if let Err(err) = std::fs::write(&path, rules) {
+                    fail(&path, err);
+                }
+                println!("{}", path.display());
+                for (name, rows) in facts {
+                    let path = directory.join(name);
+                    if let Err(err) = std::fs::write(&path, rows) {
+                        fail(&path, err);
                     }
+                    println!("{}", path.display());
                 }
             }
             Err(err) => {
-                display_error(filename, src, "SyntaxError", &err.msg, err.offset);
+                report(filename, &src, &Err(err), Format::Text, tern);
+                process::exit(1);
             }
-        },
-        Err(err) => {
-            display_error(filename, src, "TokenizerError", &err.msg, err.offset);
         }
     }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
-    let mut ctx = codegen::Context::new();
-    let mut vm = vm::VirtualMachine::new();
-    let args: Vec<String> = env::args().collect();
-    let mut run_interactive = args.len() == 1;
-    for i in 1..args.len() {
-        if args[i] == "--interactive" {
+    let mut tern = Interpreter::new();
+    // The prelude is loaded before any file, unless --no-prelude is passed.
+    if !env::args().skip(1).any(|arg| arg == "--no-prelude") {
+        if let Err(err) = tern.load_prelude() {
+            eprintln!("Failed to load the prelude: {}", err);
+            process::exit(1);
+        }
+    }
+    let mut args = env::args()
+        .skip(1)
+        .filter(|arg| arg != "--no-prelude")
+        .peekable();
+    if args.peek().map(String::as_str) == Some("export") {
+        args.next();
+        return export(args.collect(), &tern);
+    }
+    let mut run_interactive = args.peek().is_none();
+    let mut format = Format::Text;
+    let mut printed_header = false;
+    let mut failed = false;
+    while let Some(arg) = args.next() {
+        if arg == "--interactive" {
             run_interactive = true;
             continue;
         }
-        let filename = &args[i];
+        // --output is an alias for --format.
+        if arg == "--format" || arg == "--output" {
+            match args.next().as_deref().and_then(Format::parse) {
+                Some(f) => format = f,
+                None => {
+                    eprintln!("Expected text, json, csv or sexpr after {}.", arg);
+                    process::exit(1);
+                }
+            }
+            continue;
+        }
+        if arg == "--load-csv" {
+            // --load-csv Relation=filename [--header]
+            let spec = args.next().unwrap_or_default();
+            let header = args.next_if(|arg| arg == "--header").is_some();
+            match load_csv_program(&spec, header) {
+                Ok(src) => {
+                    print_header(format, &mut printed_header);
+                    failed |= !eval("<load-csv>", &src, format, &mut tern);
+                }
+                Err(msg) => {
+                    eprintln!("{}", msg);
+                    process::exit(1);
+                }
+            }
+            continue;
+        }
+        if arg == "--from-prolog" {
+            // --from-prolog filename.pl
+            let filename = match args.next() {
+                Some(filename) => filename,
+                None => {
+                    eprintln!("Expected a filename after --from-prolog.");
+                    process::exit(1);
+                }
+            };
+            let program = std::fs::read_to_string(&filename)?;
+            print_header(format, &mut printed_header);
+            let result = tern.eval_prolog(&program);
+            failed |= !report(&filename, &program, &result, format, &tern);
+            continue;
+        }
+        let filename = &arg;
         let mut file = File::open(filename)?;
         let mut program = String::new();
         file.read_to_string(&mut program)?;
-        eval(&filename, &program, &mut ctx, &mut vm);
-    }
-
-    // Not running interactively.
-    if !run_interactive {
-        return Ok(());
+        print_header(format, &mut printed_header);
+        // Imports are relative to the file being evaluated.
+        if let Some(directory) = std::path::Path::new(filename).parent() {
+            tern.set_directory(directory);
+        }
+        failed |= !eval(filename, &program, format, &mut tern);
     }
 
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-    println!("Welcome to Tern!");
-    print!("> ");
-    stdout.flush()?;
+    if run_interactive {
+        // The banner and prompts go to stderr, so that the results on stdout
+        // can be piped, e.g. as JSON.
+        let stdin = io::stdin();
+        let mut stderr = io::stderr();
+        eprintln!("Welcome to Tern!");
+        eprint!("> ");
+        stderr.flush()?;
 
-    for line in stdin.lock().lines() {
-        match line {
-            Ok(src) => {
-                eval("<stdin>", &src, &mut ctx, &mut vm);
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(src) => {
+                    print_header(format, &mut printed_header);
+                    failed |= !eval("<stdin>", &src, format, &mut tern);
+                }
+                _ => break,
             }
-            _ => break,
+            io::stdout().flush()?;
+            eprint!("> ");
+            stderr.flush()?;
         }
-        print!("> ");
-        stdout.flush()?;
     }
 
+    // Exit with an error if any program failed.
+    if failed {
+        io::stdout().flush()?;
+        process::exit(1);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn load_csv_programs() {
+        assert_eq!(
+            load_csv_program("Father=family.csv", false),
+            Ok("load_csv(\"family.csv\", Father)".to_string())
+        );
+        assert_eq!(
+            load_csv_program("Father=dir\\\"a\".csv", true),
+            Ok("load_csv(\"dir\\\\\\\"a\\\".csv\", Father, 'header)".to_string())
+        );
+        for spec in ["Father", "=family.csv", "Father="] {
+            assert_eq!(
+                load_csv_program(spec, false),
+                Err("Expected Relation=filename after --load-csv.".to_string())
+            );
+        }
+        for relation in ["father", "Father)\nprint(1", "Fa ther", "A.B"] {
+            assert_eq!(
+                load_csv_program(&format!("{}=family.csv", relation), false),
+                Err(format!(
+                    "Expected a relation name before = after --load-csv, not {}.",
+                    relation
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn export_options() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(
+            crate::export_options(args(&["--datalog", "a.tern", "b/c.tern"])),
+            Ok((
+                vec!["a.tern".to_string(), "b/c.tern".to_string()],
+                std::path::PathBuf::from(".")
+            ))
+        );
+        assert_eq!(
+            crate::export_options(args(&["--output-dir", "out", "--datalog", "a.tern"])),
+            Ok((vec!["a.tern".to_string()], std::path::PathBuf::from("out")))
+        );
+        assert_eq!(
+            crate::export_options(args(&["--datalog", "--souffle", "a.tern"])),
+            Err("Unknown option for export: --souffle.".to_string())
+        );
+        assert_eq!(
+            crate::export_options(args(&["--datalog", "a.tern", "--output-dir"])),
+            Err("Expected a directory after --output-dir.".to_string())
+        );
+        assert_eq!(
+            crate::export_options(args(&["a.tern"])),
+            Err("Expected --datalog after export, the only supported format.".to_string())
+        );
+        assert_eq!(
+            crate::export_options(args(&["--datalog"])),
+            Err("Expected a file to export.".to_string())
+        );
+        // Files with the same name would be written to the same directory.
+        assert_eq!(
+            crate::export_options(args(&["--datalog", "a/family.tern", "b/family.tern"])),
+            Err("Can't export two files named family.".to_string())
+        );
+        assert_eq!(
+            crate::export_options(args(&["--datalog", "family.tern", "family.dl"])),
+            Err("Can't export two files named family.".to_string())
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::codegen::{self, Context};
+use crate::errors::SyntaxError;
+use crate::parser;
+use crate::report;
+use crate::tokenizer;
+use crate::vm::{Opcode, VirtualMachine};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// The names of the relations a module exports, and the variables they are
+// bound to.
+pub type Exports = Rc<HashMap<String, u64>>;
+
+// The modules imported so far, shared by the contexts of every module.
+#[derive(Default)]
+pub struct Modules {
+    loaded: HashMap<PathBuf, Exports>,
+    // The modules being imported, outermost first, to detect import cycles.
+    loading: Vec<PathBuf>,
+    // The modules loaded by the program being compiled. Their code only runs
+    // as part of the program, so they are forgotten if it fails.
+    pending: Vec<PathBuf>,
+}
+
+impl Modules {
+    // Keep the modules loaded by a program that ran successfully.
+    pub fn commit(&mut self) {
+        self.pending.clear();
+    }
+
+    // Forget the modules loaded by a program that failed to compile or run,
+    // so that they are loaded again when they are next imported.
+    pub fn rollback(&mut self) {
+        for path in self.pending.drain(..) {
+            self.loaded.remove(&path);
+        }
+    }
+}
+
+// Import the module at `path`, relative to the directory of the importing
+// module, generating its code the first time it is imported. The module is
+// generated in a context of its own, so only the relations it defines at the
+// top level are visible to the importing module.
+pub fn import(
+    path: &str,
+    ctx: &Context,
+    vm: &mut VirtualMachine,
+    instr: &mut Vec<Opcode>,
+    offset: usize,
+) -> Result<Exports, SyntaxError> {
+    let error = |msg: String| SyntaxError { msg, offset };
+    let path = ctx.directory.join(path);
+    let canonical = fs::canonicalize(&path)
+        .map_err(|err| error(format!("Can't import {}: {}.", path.display(), err)))?;
+    if let Some(exports) = ctx.modules.borrow().loaded.get(&canonical) {
+        return Ok(exports.clone());
+    }
+    if let Some(start) = ctx
+        .modules
+        .borrow()
+        .loading
+        .iter()
+        .position(|p| *p == canonical)
+    {
+        let modules = ctx.modules.borrow();
+        let cycle: Vec<String> = modules.loading[start..]
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|p| name(p))
+            .collect();
+        return Err(error(format!("Import cycle: {}.", cycle.join(" -> "))));
+    }
+    let src = fs::read_to_string(&canonical)
+        .map_err(|err| error(format!("Can't import {}: {}.", path.display(), err)))?;
+    let directory = canonical
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    ctx.modules.borrow_mut().loading.push(canonical.clone());
+    let result = load(&src, &name(&canonical), directory, ctx, vm, instr, offset);
+    ctx.modules.borrow_mut().loading.pop();
+    let exports = result?;
+    let mut modules = ctx.modules.borrow_mut();
+    modules.pending.push(canonical.clone());
+    modules.loaded.insert(canonical, exports.clone());
+    Ok(exports)
+}
+
+// Generate the code of a module from its source, returning the relations it
+// defines at the top level. Errors in the module are reported at `offset`,
+// with their location in the module called `name`.
+pub fn load(
+    src: &str,
+    name: &str,
+    directory: PathBuf,
+    ctx: &Context,
+    vm: &mut VirtualMachine,
+    instr: &mut Vec<Opcode>,
+    offset: usize,
+) -> Result<Exports, SyntaxError> {
+    let located = |msg: &str, at: usize| {
+        let (line, column) = report::location(src, at);
+        SyntaxError {
+            msg: format!("{}:{}:{}: {}", name, line, column, msg),
+            offset,
+        }
+    };
+    let ast = tokenizer::scan(src)
+        .map_err(|err| located(&err.msg, err.offset))
+        .and_then(|tokens| parser::parse(tokens).map_err(|err| located(&err.msg, err.offset)))?;
+
+    let mut module_ctx = Context::new();
+    module_ctx.modules = ctx.modules.clone();
+    module_ctx.directory = directory;
+    generate(&ast, &mut module_ctx, vm, instr).map_err(|err| located(&err.msg, err.offset))?;
+    Ok(Rc::new(
+        module_ctx.bindings[0]
+            .iter()
+            .filter(|(name, _)| name.starts_with(char::is_uppercase))
+            .map(|(name, id)| (name.clone(), *id))
+            .collect(),
+    ))
+}
+
+// Generate the statements of a module, discarding their values.
+fn generate(
+    ast: &parser::AST,
+    ctx: &mut Context,
+    vm: &mut VirtualMachine,
+    instr: &mut Vec<Opcode>,
+) -> Result<(), SyntaxError> {
+    if let parser::AST::Program(statements) = ast {
+        for statement in statements {
+            ctx.clear_free_variables();
+            codegen::generate(statement, ctx, vm, instr)?;
+            if codegen::has_value(statement) {
+                instr.push(Opcode::Pop);
+            }
+        }
+        ctx.clear_free_variables();
+    }
+    Ok(())
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::Context;
+    use crate::module::*;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn loading() {
+        let ctx = Context::new();
+        let mut vm = VirtualMachine::new();
+        let mut instr = Vec::new();
+        // Only the relations at the top level are exported, and nothing is
+        // defined in the importing context.
+        let exports = load(
+            "Father('terach, 'abraham).
+            rel Parent(x, y) { Father(x, y) }
+            let helper = 'h
+            fn f(x) { return x }",
+            "family.tern",
+            PathBuf::new(),
+            &ctx,
+            &mut vm,
+            &mut instr,
+            7,
+        )
+        .unwrap();
+        let mut names: Vec<&String> = exports.keys().collect();
+        names.sort();
+        assert_eq!(names, ["Father", "Parent"]);
+        assert!(ctx.lookup("Parent").is_none());
+
+        // Errors are reported at the offset of the import, with their
+        // location in the module.
+        let err = load(
+            "Male('abraham).\nrel R(x) {\n  Female(x)\n}",
+            "bad.tern",
+            PathBuf::new(),
+            &ctx,
+            &mut vm,
+            &mut instr,
+            7,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.offset, 7);
+        assert_eq!(err.msg, "bad.tern:3:8: Undefined function: Female");
+    }
+}
@@ -1,21 +1,55 @@
 use crate::errors::SyntaxError;
 use crate::tokenizer::{Token, TokenKind};
+use std::collections::HashMap;
 use std::fmt;
 use std::iter::Peekable;
 
+// The arguments of the head of a clause and the goals of its body.
+pub type Clause = (Vec<AST>, Vec<AST>);
+
+#[derive(Clone)]
 pub enum AST {
     Conj(Vec<AST>),
     Disj(Vec<AST>),
     Equals(Box<AST>, Box<AST>),
     Var(Vec<AST>, Box<AST>),
     Atom(String),
-    Variable(String),
+    Variable(String, usize),
+    AnonymousVariable,
+    Integer(i64),
+    String(String),
     FnCall(String, Vec<AST>, usize),
     Program(Vec<AST>),
     Table(Vec<AST>),
     LetBinding(String, Box<AST>),
     BindingRef(String),
-    Relation(String, Vec<AST>, Box<AST>),
+    // A relation definition, or an anonymous relation if it has no name.
+    Relation(Option<String>, Vec<AST>, Box<AST>),
+    Fact(String, Vec<AST>, usize),
+    Function(String, Vec<AST>, Vec<AST>),
+    Return(Box<AST>, usize),
+    // A list of terms, with the tail of the list if it has one.
+    List(Vec<AST>, Option<Box<AST>>),
+    // A value and the pattern and expression of each arm.
+    Match(Box<AST>, Vec<(AST, AST)>),
+    // A table and the key of one of its fields.
+    Field(Box<AST>, Box<AST>),
+    // An assignment to a field and the value assigned, e.g. `t.x = 'a`.
+    Assign(Box<AST>, Box<AST>),
+    Boolean(bool),
+    // A condition, and the statements run if it is true and if it is false.
+    If(Box<AST>, Vec<AST>, Vec<AST>),
+    While(Box<AST>, Vec<AST>),
+    // A loop over the answers in a stream, binding each to a variable.
+    For(String, Box<AST>, Vec<AST>),
+    Break(usize),
+    // The path of a module, and the name its relations are qualified with,
+    // if any.
+    Import(String, Option<String>, usize),
+    // The clauses defining a relation, each with the arguments of its head
+    // and the goals of its body, e.g. `Grandparent(x, z) :- Parent(x, y),
+    // Parent(y, z).` Only clauses translated from Prolog have empty bodies.
+    Clauses(String, Vec<Clause>, usize),
 }
 
 impl fmt::Display for AST {
@@ -62,7 +96,22 @@ impl fmt::Display for AST {
                 write!(f, ") {{ {} }}", body)
             }
             AST::Atom(atom) => write!(f, "'{}", atom),
-            AST::Variable(name) => write!(f, "{}", name),
+            AST::Variable(name, _) => write!(f, "{}", name),
+            AST::AnonymousVariable => write!(f, "_"),
+            AST::Integer(n) => write!(f, "{}", n),
+            AST::String(value) => {
+                write!(f, "\"")?;
+                for c in value.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        _ => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
             AST::FnCall(name, arguments, _) => {
                 write!(f, "{}(", name)?;
                 let mut first = true;
@@ -104,7 +153,10 @@ impl fmt::Display for AST {
                 write!(f, "{}", name)
             }
             AST::Relation(name, parameters, body) => {
-                write!(f, "rel {}(", name)?;
+                match name {
+                    Some(name) => write!(f, "rel {}(", name)?,
+                    None => write!(f, "rel (")?,
+                }
                 let mut first = true;
                 for parameter in parameters {
                     if !first {
@@ -116,6 +168,144 @@ impl fmt::Display for AST {
                 }
                 write!(f, ") {{ {} }}", body)
             }
+            AST::Fact(name, arguments, _) => {
+                write!(f, "{}(", name)?;
+                let mut first = true;
+                for argument in arguments {
+                    if !first {
+                        write!(f, ", {}", argument)?;
+                    } else {
+                        first = false;
+                        write!(f, "{}", argument)?;
+                    }
+                }
+                write!(f, ").")
+            }
+            AST::Function(name, parameters, body) => {
+                write!(f, "fn {}(", name)?;
+                let mut first = true;
+                for parameter in parameters {
+                    if !first {
+                        write!(f, ", {}", parameter)?;
+                    } else {
+                        first = false;
+                        write!(f, "{}", parameter)?;
+                    }
+                }
+                write!(f, ") {{")?;
+                for statement in body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, " }}")
+            }
+            AST::Return(value, _) => write!(f, "return {}", value),
+            AST::List(elements, tail) => {
+                write!(f, "[")?;
+                let mut first = true;
+                for element in elements {
+                    if !first {
+                        write!(f, ", {}", element)?;
+                    } else {
+                        first = false;
+                        write!(f, "{}", element)?;
+                    }
+                }
+                if let Some(tail) = tail {
+                    write!(f, " | {}", tail)?;
+                }
+                write!(f, "]")
+            }
+            AST::Match(value, arms) => {
+                write!(f, "match {} {{ ", value)?;
+                let mut first = true;
+                for (pattern, expr) in arms {
+                    if !first {
+                        write!(f, ", {} => {}", pattern, expr)?;
+                    } else {
+                        first = false;
+                        write!(f, "{} => {}", pattern, expr)?;
+                    }
+                }
+                write!(f, " }}")
+            }
+            AST::Field(table, key) => match &**key {
+                AST::Atom(name) => write!(f, "{}.{}", table, name),
+                key => write!(f, "{}[{}]", table, key),
+            },
+            AST::Assign(field, value) => write!(f, "{} = {}", field, value),
+            AST::Boolean(value) => write!(f, "{}", value),
+            AST::If(condition, then, otherwise) => {
+                write!(f, "if {} {{", condition)?;
+                for statement in then {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, " }}")?;
+                if !otherwise.is_empty() {
+                    write!(f, " else {{")?;
+                    for statement in otherwise {
+                        write!(f, " {}", statement)?;
+                    }
+                    write!(f, " }}")?;
+                }
+                Ok(())
+            }
+            AST::While(condition, body) => {
+                write!(f, "while {} {{", condition)?;
+                for statement in body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, " }}")
+            }
+            AST::For(name, stream, body) => {
+                write!(f, "for {} in {} {{", name, stream)?;
+                for statement in body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, " }}")
+            }
+            AST::Break(_) => write!(f, "break"),
+            AST::Import(path, alias, _) => {
+                write!(f, "import {:?}", path)?;
+                if let Some(alias) = alias {
+                    write!(f, " as {}", alias)?;
+                }
+                Ok(())
+            }
+            AST::Clauses(name, clauses, _) => {
+                let mut first_clause = true;
+                for (arguments, goals) in clauses {
+                    if !first_clause {
+                        write!(f, " ")?;
+                    } else {
+                        first_clause = false;
+                    }
+                    write!(f, "{}(", name)?;
+                    let mut first = true;
+                    for argument in arguments {
+                        if !first {
+                            write!(f, ", {}", argument)?;
+                        } else {
+                            first = false;
+                            write!(f, "{}", argument)?;
+                        }
+                    }
+                    write!(f, ")")?;
+                    if !goals.is_empty() {
+                        write!(f, " :- ")?;
+                    }
+                    let mut first = true;
+                    for goal in goals {
+                        if !first {
+                            write!(f, ", {}", goal)?;
+                        } else {
+                            first = false;
+                            write!(f, "{}", goal)?;
+                        }
+                    }
+                    write!(f, ".")?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -129,8 +319,36 @@ fn program(
     tokens: &mut Peekable<std::vec::IntoIter<Token>>,
 ) -> Result<AST, SyntaxError> {
     let mut statements: Vec<AST> = Vec::new();
+    // The clauses of a relation are grouped into the statement of its first
+    // clause, so they can be defined as one relation.
+    let mut relations: HashMap<String, usize> = HashMap::new();
     while tokens.peek().is_some() {
-        statements.push(statement(state, tokens)?);
+        match statement(state, tokens)? {
+            AST::Clauses(name, clauses, offset) => {
+                if let Some(&i) = relations.get(&name) {
+                    if let AST::Clauses(_, group, _) = &mut statements[i] {
+                        let arity = group[0].0.len();
+                        if clauses
+                            .iter()
+                            .any(|(arguments, _)| arguments.len() != arity)
+                        {
+                            return Err(SyntaxError {
+                                msg: format!(
+                                    "Expected {} argument(s) in clause for {}.",
+                                    arity, name
+                                ),
+                                offset: state.offset,
+                            });
+                        }
+                        group.extend(clauses);
+                    }
+                } else {
+                    relations.insert(name.clone(), statements.len());
+                    statements.push(AST::Clauses(name, clauses, offset));
+                }
+            }
+            statement => statements.push(statement),
+        }
     }
     Ok(AST::Program(statements))
 }
@@ -142,8 +360,41 @@ fn statement(
     if let Some(token) = tokens.peek() {
         if token.kind == TokenKind::Let {
             letbinding(state, tokens)
+        } else if token.kind == TokenKind::Return {
+            let offset = token.offset;
+            state.offset = token.offset;
+            tokens.next();
+            let value = expression(state, tokens)?;
+            Ok(AST::Return(Box::new(value), offset))
+        } else if token.kind == TokenKind::If {
+            conditional(state, tokens)
+        } else if token.kind == TokenKind::While {
+            state.offset = token.offset;
+            tokens.next();
+            let condition = expression(state, tokens)?;
+            let body = block(state, tokens, "while")?;
+            Ok(AST::While(Box::new(condition), body))
+        } else if token.kind == TokenKind::For {
+            forloop(state, tokens)
+        } else if token.kind == TokenKind::Import {
+            import(state, tokens)
+        } else if token.kind == TokenKind::Break {
+            let offset = token.offset;
+            state.offset = token.offset;
+            tokens.next();
+            Ok(AST::Break(offset))
         } else {
-            expression(state, tokens)
+            let expr = expression(state, tokens)?;
+            if let Some(token) = tokens.peek() {
+                if token.kind == TokenKind::Dot {
+                    state.offset = token.offset;
+                    tokens.next();
+                    return fact(state, expr);
+                } else if token.kind == TokenKind::Colon {
+                    return clause(state, tokens, expr);
+                }
+            }
+            Ok(expr)
         }
     } else {
         Err(SyntaxError {
@@ -165,7 +416,7 @@ fn letbinding(
             });
         }
         state.offset = token.offset;
-        if let AST::Variable(name) = variable(state, tokens)? {
+        if let AST::Variable(name, _) = variable(state, tokens)? {
             if let Some(token) = tokens.next() {
                 if token.kind != TokenKind::Equals {
                     return Err(SyntaxError {
@@ -196,6 +447,124 @@ fn letbinding(
     }
 }
 
+fn fact(state: &mut ParseState, expr: AST) -> Result<AST, SyntaxError> {
+    if let AST::FnCall(name, arguments, offset) = expr {
+        if !name.starts_with(char::is_uppercase) {
+            return Err(SyntaxError {
+                msg: "Relation name must start with an uppercase character.".to_string(),
+                offset,
+            });
+        }
+        if arguments.is_empty() {
+            return Err(SyntaxError {
+                msg: "Empty argument list while parsing fact.".to_string(),
+                offset,
+            });
+        }
+        for argument in &arguments {
+            if !matches!(argument, AST::Atom(_) | AST::Integer(_) | AST::String(_)) {
+                return Err(SyntaxError {
+                    msg: "Expected only constants as arguments while parsing fact.".to_string(),
+                    offset,
+                });
+            }
+        }
+        Ok(AST::Fact(name, arguments, offset))
+    } else {
+        Err(SyntaxError {
+            msg: "Expected relation call before `.` while parsing fact.".to_string(),
+            offset: state.offset,
+        })
+    }
+}
+
+fn clause(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+    head: AST,
+) -> Result<AST, SyntaxError> {
+    // `:-` is scanned as a colon followed by a `-` literal.
+    if let Some(token) = tokens.next() {
+        state.offset = token.offset;
+    }
+    match tokens.next() {
+        Some(Token {
+            kind: TokenKind::Literal(dash),
+            offset,
+        }) if dash == "-" && offset == state.offset + 1 => state.offset = offset,
+        _ => {
+            return Err(SyntaxError {
+                msg: "Expected `:-` while parsing clause.".to_string(),
+                offset: state.offset,
+            })
+        }
+    }
+    let (name, arguments, offset) = match head {
+        AST::FnCall(name, arguments, offset) if name.starts_with(char::is_uppercase) => {
+            (name, arguments, offset)
+        }
+        _ => {
+            return Err(SyntaxError {
+                msg: "Expected relation call before `:-` while parsing clause.".to_string(),
+                offset: state.offset,
+            })
+        }
+    };
+    if arguments.is_empty() {
+        return Err(SyntaxError {
+            msg: "Empty argument list while parsing clause.".to_string(),
+            offset,
+        });
+    }
+    // The arguments of the head were parsed as expressions, where a variable
+    // is a reference to a binding.
+    let mut head = vec![];
+    for argument in arguments {
+        head.push(match argument {
+            AST::BindingRef(name) if !name.starts_with(char::is_uppercase) => {
+                AST::Variable(name, offset)
+            }
+            AST::Atom(_)
+            | AST::Integer(_)
+            | AST::String(_)
+            | AST::AnonymousVariable
+            | AST::List(_, _) => argument,
+            _ => {
+                return Err(SyntaxError {
+                    msg: "Expected only terms as arguments while parsing clause.".to_string(),
+                    offset,
+                })
+            }
+        });
+    }
+    let mut goals = vec![goal(state, tokens)?];
+    loop {
+        match tokens.next() {
+            Some(token) if token.kind == TokenKind::Comma => {
+                state.offset = token.offset;
+                goals.push(goal(state, tokens)?);
+            }
+            Some(token) if token.kind == TokenKind::Dot => {
+                state.offset = token.offset;
+                break;
+            }
+            Some(_) => {
+                return Err(SyntaxError {
+                    msg: "Expected `,` or `.` while parsing clause.".to_string(),
+                    offset: state.offset,
+                })
+            }
+            None => {
+                return Err(SyntaxError {
+                    msg: "Unexpected end of input while parsing clause.".to_string(),
+                    offset: state.offset,
+                })
+            }
+        }
+    }
+    Ok(AST::Clauses(name, vec![(head, goals)], offset))
+}
+
 fn expression(
     state: &mut ParseState,
     tokens: &mut Peekable<std::vec::IntoIter<Token>>,
@@ -204,23 +573,51 @@ fn expression(
         match &token.kind {
             TokenKind::LeftBrace => table(state, tokens),
             TokenKind::Rel => relation(state, tokens),
+            TokenKind::Fn => function(state, tokens),
+            TokenKind::Match => matches(state, tokens),
+            TokenKind::True | TokenKind::False => {
+                let value = token.kind == TokenKind::True;
+                state.offset = token.offset;
+                tokens.next();
+                Ok(AST::Boolean(value))
+            }
+            TokenKind::Literal(name) if is_integer(name) => {
+                let left = term(state, tokens)?;
+                if let Some(token) = tokens.peek() {
+                    if token.kind == TokenKind::DoubleEquals {
+                        return equals(state, tokens, Some(left));
+                    }
+                }
+                Ok(left)
+            }
             TokenKind::Literal(name) => {
                 let name = name.to_string();
                 let offset = token.offset;
                 state.offset = token.offset;
                 tokens.next();
-                if let Some(token) = tokens.peek() {
+                let lowercase = name.starts_with(char::is_lowercase);
+                let expr = if let Some(token) = tokens.peek() {
                     if token.kind == TokenKind::LeftParen {
                         let arglist = arglist(state, tokens)?;
-                        Ok(AST::FnCall(name, arglist, offset))
+                        AST::FnCall(name, arglist, offset)
+                    } else if token.kind == TokenKind::DoubleEquals {
+                        return equals(state, tokens, Some(AST::Variable(name, offset)));
                     } else {
-                        Ok(AST::BindingRef(name))
+                        AST::BindingRef(name)
                     }
                 } else {
-                    Ok(AST::BindingRef(name))
+                    AST::BindingRef(name)
+                };
+                if lowercase {
+                    fields(state, tokens, expr)
+                } else {
+                    Ok(expr)
                 }
             }
-            TokenKind::Tick => {
+            TokenKind::Tick
+            | TokenKind::Underscore
+            | TokenKind::String(_)
+            | TokenKind::LeftBracket => {
                 let left = term(state, tokens)?;
                 if let Some(token) = tokens.peek() {
                     if token.kind == TokenKind::DoubleEquals {
@@ -273,7 +670,7 @@ fn table(
                 }
                 state.offset = token.offset;
             }
-            members.push(term(state, tokens)?);
+            members.push(expression(state, tokens)?);
             if let Some(token) = tokens.next() {
                 if token.kind == TokenKind::Comma {
                     state.offset = token.offset;
@@ -314,13 +711,16 @@ fn relation(
             });
         }
         state.offset = token.offset;
-        let name;
-        if let Some(token) = tokens.next() {
-            state.offset = token.offset;
-            if let TokenKind::Literal(s) = &token.kind {
+        let mut name = None;
+        if let Some(token) = tokens.peek() {
+            if token.kind == TokenKind::LeftParen {
+                // An anonymous relation.
+            } else if let TokenKind::Literal(s) = &token.kind {
+                state.offset = token.offset;
                 if let Some(ch) = s.chars().next() {
                     if ch.is_uppercase() {
-                        name = s.to_string();
+                        name = Some(s.to_string());
+                        tokens.next();
                     } else {
                         return Err(SyntaxError {
                             msg: "Relation name must start with an uppercase character."
@@ -332,6 +732,7 @@ fn relation(
                     unreachable!("relation name is empty string")
                 }
             } else {
+                state.offset = token.offset;
                 return Err(SyntaxError {
                     msg: "Expected literal while parsing relation name.".to_string(),
                     offset: state.offset,
@@ -343,7 +744,7 @@ fn relation(
                 offset: state.offset,
             });
         }
-        let parameters = varlist(state, tokens)?;
+        let parameters = varlist(state, tokens, false)?;
         if let Some(token) = tokens.next() {
             if token.kind != TokenKind::LeftBrace {
                 return Err(SyntaxError {
@@ -367,14 +768,268 @@ fn relation(
             }
         } else {
             return Err(SyntaxError {
-                msg: "Unexpected end of input while parsing `rel`.".to_string(),
+                msg: "Unexpected end of input while parsing `rel`.".to_string(),
+                offset: state.offset,
+            });
+        }
+        Ok(AST::Relation(name, parameters, Box::new(body)))
+    } else {
+        Err(SyntaxError {
+            msg: "Unexpected end of input while parsing `rel`.".to_string(),
+            offset: state.offset,
+        })
+    }
+}
+
+// Parse the statements of a block, e.g. the body of a loop, reporting
+// errors as part of parsing `construct`.
+fn block(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+    construct: &str,
+) -> Result<Vec<AST>, SyntaxError> {
+    match tokens.next() {
+        Some(token) if token.kind == TokenKind::LeftBrace => {
+            state.offset = token.offset;
+        }
+        Some(_) => {
+            return Err(SyntaxError {
+                msg: format!("Expected `{{` while parsing `{}`.", construct),
+                offset: state.offset,
+            });
+        }
+        None => {
+            return Err(SyntaxError {
+                msg: format!("Unexpected end of input while parsing `{}`.", construct),
+                offset: state.offset,
+            });
+        }
+    }
+    let mut statements = Vec::new();
+    loop {
+        match tokens.peek() {
+            Some(token) if token.kind == TokenKind::RightBrace => {
+                state.offset = token.offset;
+                tokens.next();
+                return Ok(statements);
+            }
+            Some(_) => statements.push(statement(state, tokens)?),
+            None => {
+                return Err(SyntaxError {
+                    msg: format!("Unexpected end of input while parsing `{}`.", construct),
+                    offset: state.offset,
+                });
+            }
+        }
+    }
+}
+
+// Parse an `if` statement, with an optional `else` block or `else if`.
+fn conditional(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+) -> Result<AST, SyntaxError> {
+    if let Some(token) = tokens.next() {
+        state.offset = token.offset;
+    }
+    let condition = expression(state, tokens)?;
+    let then = block(state, tokens, "if")?;
+    let mut otherwise = Vec::new();
+    if let Some(token) = tokens.peek() {
+        if token.kind == TokenKind::Else {
+            state.offset = token.offset;
+            tokens.next();
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::If => {
+                    otherwise.push(conditional(state, tokens)?);
+                }
+                _ => otherwise = block(state, tokens, "else")?,
+            }
+        }
+    }
+    Ok(AST::If(Box::new(condition), then, otherwise))
+}
+
+// Parse an import of a module by its path, e.g. `import "family.tern"`, or
+// by its name, e.g. `import family as f`, which imports `family.tern`.
+fn import(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+) -> Result<AST, SyntaxError> {
+    let offset = match tokens.next() {
+        Some(token) => {
+            state.offset = token.offset;
+            token.offset
+        }
+        None => state.offset,
+    };
+    let path = match tokens.next() {
+        Some(Token {
+            kind: TokenKind::String(path),
+            offset,
+        }) => {
+            state.offset = offset;
+            path
+        }
+        Some(Token {
+            kind: TokenKind::Literal(name),
+            offset,
+        }) if name.starts_with(char::is_lowercase) => {
+            state.offset = offset;
+            name + ".tern"
+        }
+        Some(_) => {
+            return Err(SyntaxError {
+                msg: "Expected module name or path while parsing `import`.".to_string(),
+                offset: state.offset,
+            });
+        }
+        None => {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input while parsing `import`.".to_string(),
+                offset: state.offset,
+            });
+        }
+    };
+    let mut alias = None;
+    if let Some(token) = tokens.peek() {
+        if token.kind == TokenKind::As {
+            state.offset = token.offset;
+            tokens.next();
+            match tokens.next() {
+                Some(Token {
+                    kind: TokenKind::Literal(name),
+                    offset,
+                }) if name.starts_with(char::is_lowercase) && !is_integer(&name) => {
+                    state.offset = offset;
+                    alias = Some(name);
+                }
+                _ => {
+                    return Err(SyntaxError {
+                        msg: "Expected lowercase name after `as` while parsing `import`."
+                            .to_string(),
+                        offset: state.offset,
+                    });
+                }
+            }
+        }
+    }
+    Ok(AST::Import(path, alias, offset))
+}
+
+// Parse a `for` loop, e.g. `for answer in solve(Father(x, y)) { ... }`.
+fn forloop(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+) -> Result<AST, SyntaxError> {
+    if let Some(token) = tokens.next() {
+        state.offset = token.offset;
+    }
+    let name = match variable(state, tokens)? {
+        AST::Variable(name, _) => name,
+        _ => {
+            return Err(SyntaxError {
+                msg: "Expected variable name while parsing `for`.".to_string(),
+                offset: state.offset,
+            })
+        }
+    };
+    match tokens.next() {
+        Some(token) if token.kind == TokenKind::In => {
+            state.offset = token.offset;
+        }
+        Some(_) => {
+            return Err(SyntaxError {
+                msg: "Expected `in` while parsing `for`.".to_string(),
+                offset: state.offset,
+            });
+        }
+        None => {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input while parsing `for`.".to_string(),
+                offset: state.offset,
+            });
+        }
+    }
+    let stream = expression(state, tokens)?;
+    let body = block(state, tokens, "for")?;
+    Ok(AST::For(name, Box::new(stream), body))
+}
+
+fn function(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+) -> Result<AST, SyntaxError> {
+    if let Some(token) = tokens.next() {
+        if token.kind != TokenKind::Fn {
+            return Err(SyntaxError {
+                msg: "Expected `fn`.".to_string(),
+                offset: state.offset,
+            });
+        }
+        state.offset = token.offset;
+        let name;
+        if let Some(token) = tokens.next() {
+            state.offset = token.offset;
+            match &token.kind {
+                TokenKind::Literal(s) if s.starts_with(char::is_lowercase) => {
+                    name = s.to_string();
+                }
+                TokenKind::Literal(_) => {
+                    return Err(SyntaxError {
+                        msg: "Function name must start with a lowercase character.".to_string(),
+                        offset: state.offset,
+                    });
+                }
+                _ => {
+                    return Err(SyntaxError {
+                        msg: "Expected literal while parsing function name.".to_string(),
+                        offset: state.offset,
+                    });
+                }
+            }
+        } else {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input while parsing `fn`.".to_string(),
+                offset: state.offset,
+            });
+        }
+        let parameters = varlist(state, tokens, true)?;
+        if let Some(token) = tokens.next() {
+            if token.kind != TokenKind::LeftBrace {
+                return Err(SyntaxError {
+                    msg: "Expected `{`.".to_string(),
+                    offset: state.offset,
+                });
+            }
+            state.offset = token.offset;
+        } else {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input while parsing `fn`.".to_string(),
                 offset: state.offset,
             });
         }
-        Ok(AST::Relation(name, parameters, Box::new(body)))
+        let mut body = Vec::new();
+        loop {
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::RightBrace => {
+                    state.offset = token.offset;
+                    tokens.next();
+                    break;
+                }
+                Some(_) => body.push(statement(state, tokens)?),
+                None => {
+                    return Err(SyntaxError {
+                        msg: "Unexpected end of input while parsing `fn`.".to_string(),
+                        offset: state.offset,
+                    });
+                }
+            }
+        }
+        Ok(AST::Function(name, parameters, body))
     } else {
         Err(SyntaxError {
-            msg: "Unexpected end of input while parsing `rel`.".to_string(),
+            msg: "Unexpected end of input while parsing `fn`.".to_string(),
             offset: state.offset,
         })
     }
@@ -426,7 +1081,26 @@ fn goal(
                     })
                 }
             }
-            TokenKind::Tick | TokenKind::Literal(_) => equals(state, tokens, None),
+            TokenKind::Literal(ref name) if !is_integer(name) => {
+                let name = name.to_string();
+                let offset = token.offset;
+                state.offset = token.offset;
+                tokens.next();
+                if let Some(token) = tokens.peek() {
+                    if token.kind == TokenKind::LeftParen {
+                        return relcall(state, tokens, name, offset);
+                    } else if token.kind == TokenKind::Dot {
+                        state.offset = token.offset;
+                        tokens.next();
+                        return qualified(state, tokens, name, offset);
+                    }
+                }
+                equals(state, tokens, Some(AST::Variable(name, offset)))
+            }
+            TokenKind::Tick
+            | TokenKind::Underscore
+            | TokenKind::String(_)
+            | TokenKind::Literal(_) => equals(state, tokens, None),
             TokenKind::Var => {
                 state.offset = token.offset;
                 tokens.next();
@@ -564,10 +1238,34 @@ fn term(
     tokens: &mut Peekable<std::vec::IntoIter<Token>>,
 ) -> Result<AST, SyntaxError> {
     if let Some(token) = tokens.peek() {
-        if token.kind == TokenKind::Tick {
-            atom(state, tokens)
-        } else {
-            variable(state, tokens)
+        match &token.kind {
+            TokenKind::Tick => atom(state, tokens),
+            TokenKind::LeftBracket => list(state, tokens),
+            TokenKind::Underscore => {
+                state.offset = token.offset;
+                tokens.next();
+                Ok(AST::AnonymousVariable)
+            }
+            TokenKind::String(value) => {
+                let value = value.to_string();
+                state.offset = token.offset;
+                tokens.next();
+                Ok(AST::String(value))
+            }
+            TokenKind::Literal(value) if is_integer(value) => {
+                state.offset = token.offset;
+                match value.parse::<i64>() {
+                    Ok(n) => {
+                        tokens.next();
+                        Ok(AST::Integer(n))
+                    }
+                    Err(_) => Err(SyntaxError {
+                        msg: "Integer literal out of range.".to_string(),
+                        offset: state.offset,
+                    }),
+                }
+            }
+            _ => variable(state, tokens),
         }
     } else {
         Err(SyntaxError {
@@ -577,6 +1275,288 @@ fn term(
     }
 }
 
+// Parse the fields accessed on a value, e.g. `t.key` or `t['key]`. Only the
+// values of lowercase names and function calls have fields, so the `.` that
+// ends a fact is never mistaken for a field access.
+fn fields(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+    mut expr: AST,
+) -> Result<AST, SyntaxError> {
+    loop {
+        match tokens.peek() {
+            Some(token) if token.kind == TokenKind::Dot => {
+                state.offset = token.offset;
+                tokens.next();
+                match tokens.peek() {
+                    Some(Token {
+                        kind: TokenKind::Literal(name),
+                        offset,
+                    }) if name.starts_with(char::is_lowercase) => {
+                        let key = AST::Atom(name.to_string());
+                        state.offset = *offset;
+                        tokens.next();
+                        expr = AST::Field(Box::new(expr), Box::new(key));
+                    }
+                    // A relation of an imported module, e.g. `f.Father(x, y)`.
+                    Some(Token {
+                        kind: TokenKind::Literal(name),
+                        ..
+                    }) if name.starts_with(char::is_uppercase) => {
+                        if let AST::BindingRef(module) = expr {
+                            return qualified(state, tokens, module, state.offset);
+                        }
+                        return fact(state, expr);
+                    }
+                    // Otherwise, the `.` ends a fact.
+                    _ => return fact(state, expr),
+                }
+            }
+            Some(token) if token.kind == TokenKind::LeftBracket => {
+                state.offset = token.offset;
+                tokens.next();
+                let key = expression(state, tokens)?;
+                match tokens.next() {
+                    Some(token) if token.kind == TokenKind::RightBracket => {
+                        state.offset = token.offset;
+                    }
+                    Some(_) => {
+                        return Err(SyntaxError {
+                            msg: "Expected `]` while parsing field.".to_string(),
+                            offset: state.offset,
+                        });
+                    }
+                    None => {
+                        return Err(SyntaxError {
+                            msg: "Unexpected end of input while parsing field.".to_string(),
+                            offset: state.offset,
+                        });
+                    }
+                }
+                expr = AST::Field(Box::new(expr), Box::new(key));
+            }
+            // Fields can be assigned to, e.g. `t.x = 'a`.
+            Some(token) if token.kind == TokenKind::Equals && matches!(expr, AST::Field(_, _)) => {
+                state.offset = token.offset;
+                tokens.next();
+                let value = expression(state, tokens)?;
+                return Ok(AST::Assign(Box::new(expr), Box::new(value)));
+            }
+            _ => return Ok(expr),
+        }
+    }
+}
+
+// Parse a list of terms, e.g. `['a, 'b]`, or a list with a tail, e.g.
+// `[h | t]`.
+fn list(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+) -> Result<AST, SyntaxError> {
+    if let Some(token) = tokens.next() {
+        state.offset = token.offset;
+    }
+    let mut elements: Vec<AST> = Vec::new();
+    if let Some(token) = tokens.peek() {
+        if token.kind == TokenKind::RightBracket {
+            state.offset = token.offset;
+            tokens.next();
+            return Ok(AST::List(elements, None));
+        }
+    }
+    loop {
+        elements.push(term(state, tokens)?);
+        match tokens.next() {
+            Some(token) if token.kind == TokenKind::Comma => state.offset = token.offset,
+            Some(token) if token.kind == TokenKind::RightBracket => {
+                state.offset = token.offset;
+                return Ok(AST::List(elements, None));
+            }
+            Some(token) if token.kind == TokenKind::Pipe => {
+                state.offset = token.offset;
+                let tail = term(state, tokens)?;
+                return match tokens.next() {
+                    Some(token) if token.kind == TokenKind::RightBracket => {
+                        state.offset = token.offset;
+                        Ok(AST::List(elements, Some(Box::new(tail))))
+                    }
+                    Some(_) => Err(SyntaxError {
+                        msg: "Expected `]` while parsing list.".to_string(),
+                        offset: state.offset,
+                    }),
+                    None => Err(SyntaxError {
+                        msg: "Unexpected end of input while parsing list.".to_string(),
+                        offset: state.offset,
+                    }),
+                };
+            }
+            Some(_) => {
+                return Err(SyntaxError {
+                    msg: "Expected `,`, `|` or `]` while parsing list.".to_string(),
+                    offset: state.offset,
+                });
+            }
+            None => {
+                return Err(SyntaxError {
+                    msg: "Unexpected end of input while parsing list.".to_string(),
+                    offset: state.offset,
+                });
+            }
+        }
+    }
+}
+
+// Parse a match expression, e.g. `match x { [h | _] => h, _ => 'nil }`. Each
+// arm is a pattern, which is a term, and the expression for the arm.
+fn matches(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+) -> Result<AST, SyntaxError> {
+    if let Some(token) = tokens.next() {
+        state.offset = token.offset;
+    }
+    let value = expression(state, tokens)?;
+    match tokens.next() {
+        Some(token) if token.kind == TokenKind::LeftBrace => state.offset = token.offset,
+        Some(_) => {
+            return Err(SyntaxError {
+                msg: "Expected `{` while parsing match.".to_string(),
+                offset: state.offset,
+            });
+        }
+        None => {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input while parsing match.".to_string(),
+                offset: state.offset,
+            });
+        }
+    }
+    let mut arms = Vec::new();
+    loop {
+        if let Some(token) = tokens.peek() {
+            if token.kind == TokenKind::RightBrace {
+                state.offset = token.offset;
+                tokens.next();
+                return Ok(AST::Match(Box::new(value), arms));
+            }
+        }
+        let pattern = term(state, tokens)?;
+        match tokens.next() {
+            Some(token) if token.kind == TokenKind::FatArrow => state.offset = token.offset,
+            Some(_) => {
+                return Err(SyntaxError {
+                    msg: "Expected `=>` while parsing match.".to_string(),
+                    offset: state.offset,
+                });
+            }
+            None => break,
+        }
+        arms.push((pattern, expression(state, tokens)?));
+        match tokens.next() {
+            Some(token) if token.kind == TokenKind::Comma => state.offset = token.offset,
+            Some(token) if token.kind == TokenKind::RightBrace => {
+                state.offset = token.offset;
+                return Ok(AST::Match(Box::new(value), arms));
+            }
+            Some(_) => {
+                return Err(SyntaxError {
+                    msg: "Expected `,` or `}` while parsing match.".to_string(),
+                    offset: state.offset,
+                });
+            }
+            None => break,
+        }
+    }
+    Err(SyntaxError {
+        msg: "Unexpected end of input while parsing match.".to_string(),
+        offset: state.offset,
+    })
+}
+
+// Parse the arguments of a relation call inside of a goal. The arguments are
+// terms, except that a name starting with an uppercase character refers to a
+// relation, e.g. the `Father` in `Twice(Father, x, z)`.
+fn relcall(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+    name: String,
+    offset: usize,
+) -> Result<AST, SyntaxError> {
+    let mut arguments: Vec<AST> = Vec::new();
+    if let Some(token) = tokens.next() {
+        state.offset = token.offset;
+    }
+    loop {
+        match tokens.peek() {
+            Some(Token {
+                kind: TokenKind::Literal(s),
+                offset: _,
+            }) if s.starts_with(char::is_uppercase) => {
+                arguments.push(AST::BindingRef(s.to_string()));
+                tokens.next();
+            }
+            Some(_) => arguments.push(term(state, tokens)?),
+            None => break,
+        }
+        match tokens.next() {
+            Some(token) if token.kind == TokenKind::Comma => state.offset = token.offset,
+            Some(token) if token.kind == TokenKind::RightParen => {
+                state.offset = token.offset;
+                return Ok(AST::FnCall(name, arguments, offset));
+            }
+            Some(_) => {
+                return Err(SyntaxError {
+                    msg: "Expected `,` or `)` while parsing argument list.".to_string(),
+                    offset: state.offset,
+                });
+            }
+            None => break,
+        }
+    }
+    Err(SyntaxError {
+        msg: "Unexpected end of input while parsing argument list.".to_string(),
+        offset: state.offset,
+    })
+}
+
+// Parse a call to a relation of an imported module, after the name of the
+// module and the `.`, e.g. `Father(x, y)` in `f.Father(x, y)`.
+fn qualified(
+    state: &mut ParseState,
+    tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+    module: String,
+    offset: usize,
+) -> Result<AST, SyntaxError> {
+    match tokens.next() {
+        Some(Token {
+            kind: TokenKind::Literal(name),
+            offset: name_offset,
+        }) if name.starts_with(char::is_uppercase) => {
+            state.offset = name_offset;
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::LeftParen => {
+                    relcall(state, tokens, format!("{}.{}", module, name), offset)
+                }
+                _ => Err(SyntaxError {
+                    msg: "Expected `(` after relation of module.".to_string(),
+                    offset: state.offset,
+                }),
+            }
+        }
+        _ => Err(SyntaxError {
+            msg: "Expected relation name after `.`.".to_string(),
+            offset: state.offset,
+        }),
+    }
+}
+
+// Integer literals are scanned as literals made up of digits, optionally
+// preceded by a minus sign.
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
 fn atom(
     state: &mut ParseState,
     tokens: &mut Peekable<std::vec::IntoIter<Token>>,
@@ -620,7 +1600,7 @@ fn var(
     state: &mut ParseState,
     tokens: &mut Peekable<std::vec::IntoIter<Token>>,
 ) -> Result<AST, SyntaxError> {
-    let declarations = varlist(state, tokens)?;
+    let declarations = varlist(state, tokens, false)?;
     if let Some(token) = tokens.next() {
         if token.kind == TokenKind::LeftBrace {
             state.offset = token.offset;
@@ -719,9 +1699,12 @@ fn arglist(
     Ok(arguments)
 }
 
+// Parse a parenthesized list of variables. Only function parameter lists may
+// be empty.
 fn varlist(
     state: &mut ParseState,
     tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+    allow_empty: bool,
 ) -> Result<Vec<AST>, SyntaxError> {
     let mut declarations: Vec<AST> = Vec::new();
     if let Some(token) = tokens.next() {
@@ -731,6 +1714,15 @@ fn varlist(
                 offset: state.offset,
             });
         }
+        if allow_empty {
+            if let Some(token) = tokens.peek() {
+                if token.kind == TokenKind::RightParen {
+                    state.offset = token.offset;
+                    tokens.next();
+                    return Ok(declarations);
+                }
+            }
+        }
     } else {
         return Err(SyntaxError {
             msg: "Unexpected end of input while parsing variable list.".to_string(),
@@ -782,7 +1774,7 @@ fn variable(
     if let Some(token) = tokens.next() {
         if let TokenKind::Literal(name) = token.kind {
             state.offset = token.offset;
-            Ok(AST::Variable(name))
+            Ok(AST::Variable(name, token.offset))
         } else {
             Err(SyntaxError {
                 msg: "Expected literal while parsing variable.".to_string(),
@@ -851,6 +1843,29 @@ mod tests {
         parse!("'0live == '0live", "'0live == '0live");
         parsefails!("'", "Unexpected end of input while parsing atom.", 0);
         parse!("olive", "olive");
+        parse!("olive == 'olive", "olive == 'olive");
+        parse!("f(q == 'olive)", "f(q == 'olive)");
+        parse!("_ == 'olive", "_ == 'olive");
+        parse!("42 == x", "42 == x");
+        parse!("x == -42", "x == -42");
+        parse!("'42 == x", "'42 == x");
+        parse!("\"olive oil\" == x", "\"olive oil\" == x");
+        parse!("x == \"say \\\"hi\\\"\"", "x == \"say \\\"hi\\\"\"");
+        parse!("Age(\"sarah\", 127)", "Age(\"sarah\", 127)");
+        parsefails!(
+            "x == 99999999999999999999",
+            "Integer literal out of range.",
+            24
+        );
+        parse!("'olive == _", "'olive == _");
+        parse!("var (q) { q == _ }", "var (q) { q == _ }");
+        parse!("Female(_)", "Female(_)");
+        parse!("{_: 'olive}", "{_: 'olive}");
+        parsefails!(
+            "var (_) { 'olive == 'oil }",
+            "Expected literal while parsing variable.",
+            2
+        );
         parsefails!(
             "'olive ==",
             "Unexpected end of input while parsing term.",
@@ -889,13 +1904,13 @@ mod tests {
         parse!("var (q) { 'olive == q }", "var (q) { 'olive == q }");
         parse!("var (q) { q == 'olive }", "var (q) { q == 'olive }");
         parse!("var (p, q) { p == q }", "var (p, q) { p == q }");
-        parse!("fn()", "fn()");
-        parse!("fn('olive == 'olive)", "fn('olive == 'olive)");
-        parse!("fn(var (p, q) { p == q })", "fn(var (p, q) { p == q })");
+        parse!("f()", "f()");
+        parse!("f('olive == 'olive)", "f('olive == 'olive)");
+        parse!("f(var (p, q) { p == q })", "f(var (p, q) { p == q })");
         parsefails!(
-            "fn(var (p, q) { p == q }",
+            "f(var (p, q) { p == q }",
             "Unexpected end of input while parsing argument list.",
-            23
+            22
         );
         parse!("{}", "{}");
         parse!("{x: 'olive}", "{x: 'olive}");
@@ -911,6 +1926,15 @@ mod tests {
             "Unexpected end of input while parsing table.",
             23
         );
+        parse!("{'a: f(x)}", "{'a: f(x)}");
+        parse!("t.key", "t.key");
+        parse!("t['key]", "t.key");
+        parse!("t[x]", "t[x]");
+        parse!("f(x).a.b", "f(x).a.b");
+        parse!("t.x = 'a", "t.x = 'a");
+        parse!("t['k] = f(x)", "t.k = f(x)");
+        parsefails!("t['key", "Unexpected end of input while parsing field.", 5);
+        parsefails!("t['key 'x]", "Expected `]` while parsing field.", 5);
         parse!("let x = {}", "let x = {}");
         parse!("let x = 'olive == 'olive", "let x = 'olive == 'olive");
         parse!(
@@ -931,11 +1955,42 @@ mod tests {
         parsefails!("let 'x = {}", "Expected literal while parsing variable.", 2);
         parsefails!("let {} = {}", "Expected literal while parsing variable.", 2);
         parse!("x", "x");
+        parse!("Father('terach, 'abraham).", "Father('terach, 'abraham).");
+        parse!("Age(\"sarah\", 127).", "Age(\"sarah\", 127).");
+        parse!(
+            "Male('terach). Male('abraham).",
+            "Male('terach).Male('abraham)."
+        );
+        parsefails!(
+            "father('terach, 'abraham).",
+            "Relation name must start with an uppercase character.",
+            5
+        );
+        parsefails!(
+            "Father('terach, y).",
+            "Expected only constants as arguments while parsing fact.",
+            5
+        );
+        parsefails!("Father().", "Empty argument list while parsing fact.", 5);
+        parsefails!(
+            "'terach.",
+            "Expected relation call before `.` while parsing fact.",
+            7
+        );
         parse!("rel R(x) { x == 'olive }", "rel R(x) { x == 'olive }");
         parse!(
             "let y = rel R(x) { disj { x == 'red | x == 'bean } }",
             "let y = rel R(x) { disj { x == 'red | x == 'bean } }"
         );
+        parse!("rel (x) { x == 'olive }", "rel (x) { x == 'olive }");
+        parse!(
+            "rel R(r, x) { conj { r(x), Father(x, 'terach) } }",
+            "rel R(r, x) { conj { r(x) , Father(x, 'terach) } }"
+        );
+        parse!(
+            "rel R(x) { Twice(Father, x, _) }",
+            "rel R(x) { Twice(Father, x, _) }"
+        );
         parsefails!(
             "rel R(x) { x == 'olive ",
             "Unexpected end of input while parsing `rel`.",
@@ -956,5 +2011,131 @@ mod tests {
             "Relation name must start with an uppercase character.",
             4
         );
+        parse!("x == ['a, 1]", "x == ['a, 1]");
+        parse!("x == []", "x == []");
+        parse!("x == ['a, _ | t]", "x == ['a, _ | t]");
+        parsefails!("x == ['a | t, u]", "Expected `]` while parsing list.", 11);
+        parse!(
+            "match x { [h | _] => h, 'nil => 'nil, _ => 'other }",
+            "match x { [h | _] => h, 'nil => 'nil, _ => 'other }"
+        );
+        parse!("match f(x) { }", "match f(x) {  }");
+        parsefails!(
+            "match x { 'a 'b }",
+            "Expected `=>` while parsing match.",
+            11
+        );
+        parsefails!(
+            "match x { 'a => 'b",
+            "Unexpected end of input while parsing match.",
+            17
+        );
+        parse!("fn f(x) { return x }", "fn f(x) { return x }");
+        parse!(
+            "fn sons(f) { let s = run(x, Father(f, x)) s }",
+            "fn sons(f) { let s = run(x, Father(f, x)) s }"
+        );
+        parse!("fn f() { }", "fn f() { }");
+        parse!("return 'olive", "return 'olive");
+        parsefails!(
+            "fn F(x) { return x }",
+            "Function name must start with a lowercase character.",
+            3
+        );
+        parsefails!(
+            "fn f(x) { return x ",
+            "Unexpected end of input while parsing `fn`.",
+            17
+        );
+        parsefails!("fn f(x) return x", "Expected `{`.", 6);
+        parse!("if true { 'a }", "if true { 'a }");
+        parse!(
+            "if t.x { 'a } else { let y = 'b y }",
+            "if t.x { 'a } else { let y = 'b y }"
+        );
+        parse!(
+            "if a { } else if b { 'b } else { 'c }",
+            "if a { } else { if b { 'b } else { 'c } }"
+        );
+        parse!("while t.go { t.go = false }", "while t.go { t.go = false }");
+        parse!(
+            "for a in solve(Father(x, y)) { print(a.x) }",
+            "for a in solve(Father(x, y)) { print(a.x) }"
+        );
+        parse!(
+            "for a in solve(G(x)) { if a.x { break } }",
+            "for a in solve(G(x)) { if a.x { break } }"
+        );
+        parse!("import \"family.tern\"", "import \"family.tern\"");
+        parse!("import family as f", "import \"family.tern\" as f");
+        parse!("f.Father(x, 'isaac)", "f.Father(x, 'isaac)");
+        parse!(
+            "run(x, conj { f.Father(x, y), Father(y, 'isaac) })",
+            "run(x, conj { f.Father(x, y) , Father(y, 'isaac) })"
+        );
+        parsefails!(
+            "import 'family",
+            "Expected module name or path while parsing `import`.",
+            5
+        );
+        parsefails!(
+            "import family as F",
+            "Expected lowercase name after `as` while parsing `import`.",
+            15
+        );
+        parsefails!(
+            "run(x, conj { f.father(x) })",
+            "Expected relation name after `.`.",
+            15
+        );
+        parsefails!("if true 'a", "Expected `{` while parsing `if`.", 6);
+        parsefails!(
+            "while true { 'a ",
+            "Unexpected end of input while parsing `while`.",
+            14
+        );
+        parsefails!(
+            "for a solve(G(x)) { }",
+            "Expected `in` while parsing `for`.",
+            4
+        );
+        parse!(
+            "Grandparent(x, z) :- Parent(x, y), Parent(y, z).",
+            "Grandparent(x, z) :- Parent(x, y), Parent(y, z)."
+        );
+        parse!(
+            "Last([x], y) :- x == y. Fact('a). Last([_ | t], x) :- Last(t, x).",
+            "Last([x], y) :- x == y. Last([_ | t], x) :- Last(t, x).Fact('a)."
+        );
+        parsefails!(
+            "Foo(x, y) :- Bar(x). Foo(x) :- Bar(x).",
+            "Expected 2 argument(s) in clause for Foo.",
+            37
+        );
+        parsefails!(
+            "foo(x) :- Bar(x).",
+            "Expected relation call before `:-` while parsing clause.",
+            8
+        );
+        parsefails!(
+            "Foo(x) : - Bar(x).",
+            "Expected `:-` while parsing clause.",
+            7
+        );
+        parsefails!(
+            "Foo(x) :- Bar(x) Baz(x).",
+            "Expected `,` or `.` while parsing clause.",
+            15
+        );
+        parsefails!(
+            "Foo(f(x)) :- Bar(x).",
+            "Expected only terms as arguments while parsing clause.",
+            2
+        );
+        parsefails!(
+            "Foo(x) :- Bar(x)",
+            "Unexpected end of input while parsing clause.",
+            15
+        );
     }
 }
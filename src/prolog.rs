@@ -0,0 +1,858 @@
+use crate::errors::{SyntaxError, TokenizerError};
+use crate::parser::AST;
+use crate::unification::{unify, walk, Substitutions, Term};
+use crate::vm::{AtomType, VirtualMachine};
+use std::collections::HashMap;
+use std::iter::Peekable;
+
+// A front-end for pure Prolog, which translates facts and rules into the
+// facts and clauses of Tern. A predicate `parent/2` becomes the relation
+// `Parent`, a variable `X` becomes `x`, and a compound term `f(a, b)` becomes
+// the tuple `['$f, 'f, 'a, 'b]`. `\=`, `is` and the arithmetic comparisons are
+// calls to the foreign relations defined by `register`.
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    Atom(String),
+    Variable(String),
+    Integer(i64),
+    String(String),
+    // A sequence of symbol characters, e.g. `:-` or `=<`.
+    Symbol(String),
+    // A solo character, e.g. `(`, `,` or `!`.
+    Punct(char),
+    // The `.` that ends a clause.
+    End,
+}
+
+struct Token {
+    kind: TokenKind,
+    offset: usize,
+}
+
+// The atom that compound terms start with. Like the relations `$dif` and
+// `$is`, it's reserved for translated Prolog.
+const FUNCTOR: &str = "$f";
+
+const SYMBOL_CHARS: &str = "+-*/\\^<>=~:.?@#&$";
+
+fn scan(src: &str) -> Result<Vec<Token>, TokenizerError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '%' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(TokenizerError {
+                    msg: "Unterminated comment.".to_string(),
+                    offset: start,
+                });
+            }
+            i += 2;
+            continue;
+        }
+        let kind = if c.is_alphabetic() || c == '_' {
+            while i + 1 < chars.len() && (chars[i + 1].is_alphanumeric() || chars[i + 1] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..=i].iter().collect();
+            if c.is_uppercase() || c == '_' {
+                TokenKind::Variable(name)
+            } else {
+                TokenKind::Atom(name)
+            }
+        } else if c.is_ascii_digit() {
+            while i + 1 < chars.len() && chars[i + 1].is_ascii_digit() {
+                i += 1;
+            }
+            if chars.get(i + 1) == Some(&'.') && chars.get(i + 2).is_some_and(char::is_ascii_digit)
+            {
+                return Err(TokenizerError {
+                    msg: "Floating point numbers are not supported.".to_string(),
+                    offset: i + 1,
+                });
+            }
+            let digits: String = chars[start..=i].iter().collect();
+            match digits.parse() {
+                Ok(n) => TokenKind::Integer(n),
+                Err(_) => {
+                    return Err(TokenizerError {
+                        msg: "Integer out of range.".to_string(),
+                        offset: i,
+                    })
+                }
+            }
+        } else if c == '\'' || c == '"' {
+            let mut value = String::new();
+            let mut terminated = false;
+            while i + 1 < chars.len() {
+                i += 1;
+                match chars[i] {
+                    // A doubled quote stands for the quote itself.
+                    q if q == c && chars.get(i + 1) == Some(&c) => {
+                        value.push(c);
+                        i += 1;
+                    }
+                    q if q == c => {
+                        terminated = true;
+                        break;
+                    }
+                    '\\' => {
+                        i += 1;
+                        match chars.get(i) {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(&e) if e == '\\' || e == '\'' || e == '"' => value.push(e),
+                            _ => {
+                                return Err(TokenizerError {
+                                    msg: "Invalid escape sequence in quoted atom or string."
+                                        .to_string(),
+                                    offset: i,
+                                })
+                            }
+                        }
+                    }
+                    ch => value.push(ch),
+                }
+            }
+            if !terminated {
+                return Err(TokenizerError {
+                    msg: "Unterminated quoted atom or string.".to_string(),
+                    offset: start,
+                });
+            }
+            if c == '\'' {
+                TokenKind::Atom(value)
+            } else {
+                TokenKind::String(value)
+            }
+        } else if "()[]{},|!;".contains(c) {
+            TokenKind::Punct(c)
+        } else if SYMBOL_CHARS.contains(c) {
+            while i + 1 < chars.len() && SYMBOL_CHARS.contains(chars[i + 1]) {
+                i += 1;
+            }
+            let symbol: String = chars[start..=i].iter().collect();
+            // A `.` followed by whitespace, a comment or the end of input ends
+            // a clause.
+            if symbol == "."
+                && chars
+                    .get(i + 1)
+                    .is_none_or(|next| next.is_whitespace() || *next == '%')
+            {
+                TokenKind::End
+            } else {
+                TokenKind::Symbol(symbol)
+            }
+        } else {
+            return Err(TokenizerError {
+                msg: format!("Unexpected character `{}`.", c),
+                offset: i,
+            });
+        };
+        // Like Tern tokens, a token reports the offset of its last character.
+        tokens.push(Token { kind, offset: i });
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+struct ParseState {
+    offset: usize,
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+// A clause of a predicate, with the offset of its head.
+struct Clause {
+    name: String,
+    arguments: Vec<AST>,
+    goals: Vec<AST>,
+    offset: usize,
+}
+
+// Parse a Prolog program into a Tern program. The clauses of a predicate are
+// grouped into one definition, which is a list of facts if every clause is a
+// fact with constant arguments.
+pub fn parse(src: &str) -> Result<AST, crate::errors::Error> {
+    let tokens = scan(src)?;
+    let mut state = ParseState { offset: 0 };
+    let mut tokens = tokens.into_iter().peekable();
+    let mut predicates: Vec<(String, usize, Vec<Clause>)> = Vec::new();
+    while tokens.peek().is_some() {
+        let clause = clause(&mut state, &mut tokens)?;
+        let arity = clause.arguments.len();
+        match predicates.iter_mut().find(|(name, _, _)| *name == clause.name) {
+            Some((_, n, clauses)) if *n == arity => clauses.push(clause),
+            Some((_, n, clauses)) => {
+                return Err(SyntaxError {
+                    msg: format!(
+                        "Predicates with the same name and different arities are not supported: {}/{} and {}/{}.",
+                        clauses[0].name,
+                        n,
+                        clause.name,
+                        arity
+                    ),
+                    offset: clause.offset,
+                }
+                .into())
+            }
+            None => predicates.push((clause.name.clone(), arity, vec![clause])),
+        }
+    }
+    let mut statements = Vec::new();
+    for (name, _, clauses) in predicates {
+        let facts = clauses.iter().all(|clause| {
+            clause.goals.is_empty()
+                && clause.arguments.iter().all(|argument| {
+                    matches!(argument, AST::Atom(_) | AST::Integer(_) | AST::String(_))
+                })
+        });
+        if facts {
+            for clause in clauses {
+                statements.push(AST::Fact(
+                    relation_name(&name),
+                    clause.arguments,
+                    clause.offset,
+                ));
+            }
+        } else {
+            let offset = clauses[0].offset;
+            let clauses = clauses
+                .into_iter()
+                .map(|clause| (clause.arguments, clause.goals))
+                .collect();
+            statements.push(AST::Clauses(relation_name(&name), clauses, offset));
+        }
+    }
+    Ok(AST::Program(statements))
+}
+
+// The name of the relation a predicate is translated to, e.g. `Parent`.
+fn relation_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// The name of the Tern variable a Prolog variable is translated to, e.g. `x`.
+fn variable_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn unsupported(what: &str, offset: usize) -> SyntaxError {
+    SyntaxError {
+        msg: format!("{} not supported.", what),
+        offset,
+    }
+}
+
+fn expect(
+    state: &mut ParseState,
+    tokens: &mut Tokens,
+    kind: TokenKind,
+    what: &str,
+) -> Result<(), SyntaxError> {
+    match tokens.next() {
+        Some(token) if token.kind == kind => {
+            state.offset = token.offset;
+            Ok(())
+        }
+        Some(token) => Err(SyntaxError {
+            msg: format!("Expected {}.", what),
+            offset: token.offset,
+        }),
+        None => Err(SyntaxError {
+            msg: format!("Unexpected end of input, expected {}.", what),
+            offset: state.offset,
+        }),
+    }
+}
+
+fn clause(state: &mut ParseState, tokens: &mut Tokens) -> Result<Clause, SyntaxError> {
+    if let Some(Token {
+        kind: TokenKind::Symbol(symbol),
+        offset,
+    }) = tokens.peek()
+    {
+        if symbol == ":-" || symbol == "?-" {
+            return Err(unsupported("Directives are", *offset));
+        }
+    }
+    let offset = tokens.peek().map_or(state.offset, |token| token.offset);
+    let (name, arguments) = match term(state, tokens)? {
+        AST::FnCall(name, arguments, _) => (name, arguments),
+        AST::Atom(_) => return Err(unsupported("Predicates without arguments are", offset)),
+        _ => {
+            return Err(SyntaxError {
+                msg: "Expected the head of a clause.".to_string(),
+                offset,
+            })
+        }
+    };
+    let mut goals = Vec::new();
+    match tokens.next() {
+        Some(Token {
+            kind: TokenKind::End,
+            offset,
+        }) => state.offset = offset,
+        Some(Token {
+            kind: TokenKind::Symbol(symbol),
+            offset,
+        }) if symbol == ":-" => {
+            state.offset = offset;
+            match body(state, tokens)? {
+                AST::Conj(conj) => goals = conj,
+                goal => goals.push(goal),
+            }
+            expect(
+                state,
+                tokens,
+                TokenKind::End,
+                "`.` at the end of the clause",
+            )?;
+        }
+        Some(token) => {
+            return Err(SyntaxError {
+                msg: "Expected `:-` or `.` after the head of a clause.".to_string(),
+                offset: token.offset,
+            })
+        }
+        None => {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input, expected `.` at the end of the clause.".to_string(),
+                offset: state.offset,
+            })
+        }
+    }
+    Ok(Clause {
+        name,
+        arguments,
+        goals,
+        offset,
+    })
+}
+
+// A disjunction of conjunctions of goals.
+fn body(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    let mut branches = vec![conjunction(state, tokens)?];
+    while let Some(Token {
+        kind: TokenKind::Punct(';'),
+        offset,
+    }) = tokens.peek()
+    {
+        state.offset = *offset;
+        tokens.next();
+        branches.push(conjunction(state, tokens)?);
+    }
+    if branches.len() == 1 {
+        Ok(branches.remove(0))
+    } else {
+        Ok(AST::Disj(branches))
+    }
+}
+
+fn conjunction(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    let mut goals = vec![goal(state, tokens)?];
+    while let Some(Token {
+        kind: TokenKind::Punct(','),
+        offset,
+    }) = tokens.peek()
+    {
+        state.offset = *offset;
+        tokens.next();
+        goals.push(goal(state, tokens)?);
+    }
+    if goals.len() == 1 {
+        Ok(goals.remove(0))
+    } else {
+        Ok(AST::Conj(goals))
+    }
+}
+
+fn goal(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    match tokens.peek() {
+        Some(Token {
+            kind: TokenKind::Punct('('),
+            offset,
+        }) => {
+            state.offset = *offset;
+            tokens.next();
+            let goal = body(state, tokens)?;
+            expect(state, tokens, TokenKind::Punct(')'), "`)`")?;
+            return Ok(goal);
+        }
+        Some(Token {
+            kind: TokenKind::Punct('!'),
+            offset,
+        }) => return Err(unsupported("Cut `!` is", *offset)),
+        Some(Token {
+            kind: TokenKind::Symbol(symbol),
+            offset,
+        }) if symbol == "\\+" => return Err(unsupported("Negation `\\+` is", *offset)),
+        _ => {}
+    }
+    let offset = tokens.peek().map_or(state.offset, |token| token.offset);
+    let left = term(state, tokens)?;
+    let operator = match tokens.peek() {
+        Some(Token {
+            kind: TokenKind::Symbol(symbol),
+            ..
+        }) => Some(symbol.to_string()),
+        Some(Token {
+            kind: TokenKind::Atom(name),
+            ..
+        }) if name == "is" => Some(name.to_string()),
+        _ => None,
+    };
+    if let Some(operator) = operator {
+        let offset = tokens.next().map_or(state.offset, |token| token.offset);
+        state.offset = offset;
+        let name = match &operator[..] {
+            "=" => {
+                let right = argument(term(state, tokens)?);
+                return Ok(AST::Equals(Box::new(argument(left)), Box::new(right)));
+            }
+            "\\=" => "$dif",
+            "is" => "$is",
+            "<" => "$<",
+            ">" => "$>",
+            "=<" => "$=<",
+            ">=" => "$>=",
+            "=:=" => "$=:=",
+            "=\\=" => "$=\\=",
+            "->" => return Err(unsupported("If-then-else `->` is", offset)),
+            _ => {
+                return Err(unsupported(
+                    &format!("The operator `{}` is", operator),
+                    offset,
+                ))
+            }
+        };
+        let right = argument(term(state, tokens)?);
+        return Ok(AST::FnCall(
+            name.to_string(),
+            vec![argument(left), right],
+            offset,
+        ));
+    }
+    match left {
+        // A call to a predicate, e.g. `parent(X, Y)`.
+        AST::FnCall(name, arguments, offset) => {
+            Ok(AST::FnCall(relation_name(&name), arguments, offset))
+        }
+        AST::Atom(_) => Err(unsupported("Predicates without arguments are", offset)),
+        _ => Err(SyntaxError {
+            msg: "Expected a goal.".to_string(),
+            offset,
+        }),
+    }
+}
+
+// A term, including arithmetic expressions, which become compound terms of the
+// operator and its operands, e.g. `X + 1` becomes `['$f, '+, x, 1]`. A compound
+// term is returned as a call with the name of its functor, which `argument`
+// turns into a tuple.
+fn term(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    let mut left = product(state, tokens)?;
+    while let Some(operator) = operator(tokens, &["+", "-"]) {
+        state.offset = tokens.next().map_or(state.offset, |token| token.offset);
+        let right = product(state, tokens)?;
+        left = compound(operator, vec![argument(left), argument(right)]);
+    }
+    Ok(left)
+}
+
+fn product(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    let mut left = primary(state, tokens)?;
+    while let Some(operator) = operator(tokens, &["*", "/", "//", "mod"]) {
+        state.offset = tokens.next().map_or(state.offset, |token| token.offset);
+        let right = primary(state, tokens)?;
+        left = compound(operator, vec![argument(left), argument(right)]);
+    }
+    Ok(left)
+}
+
+fn operator(tokens: &mut Tokens, operators: &[&str]) -> Option<String> {
+    match tokens.peek() {
+        Some(Token {
+            kind: TokenKind::Symbol(symbol) | TokenKind::Atom(symbol),
+            ..
+        }) if operators.contains(&&symbol[..]) => Some(symbol.to_string()),
+        _ => None,
+    }
+}
+
+// Turn a compound term into a tuple of its functor and arguments.
+fn argument(ast: AST) -> AST {
+    match ast {
+        AST::FnCall(name, arguments, _) => compound(name, arguments),
+        ast => ast,
+    }
+}
+
+// A compound term is a tuple that starts with the atom `FUNCTOR`, followed by
+// its functor and arguments, so that `f(a)` doesn't unify with the list
+// `[f, a]`.
+fn compound(functor: String, arguments: Vec<AST>) -> AST {
+    let mut elements = vec![AST::Atom(FUNCTOR.to_string()), AST::Atom(functor)];
+    elements.extend(arguments);
+    AST::List(elements, None)
+}
+
+fn primary(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    let token = match tokens.next() {
+        Some(token) => token,
+        None => {
+            return Err(SyntaxError {
+                msg: "Unexpected end of input while parsing term.".to_string(),
+                offset: state.offset,
+            })
+        }
+    };
+    state.offset = token.offset;
+    match token.kind {
+        TokenKind::Integer(n) => Ok(AST::Integer(n)),
+        TokenKind::String(s) => Ok(AST::String(s)),
+        TokenKind::Variable(name) if name == "_" => Ok(AST::AnonymousVariable),
+        TokenKind::Variable(name) => Ok(AST::Variable(variable_name(&name), token.offset)),
+        TokenKind::Symbol(symbol) if symbol == "-" => match tokens.peek() {
+            Some(Token {
+                kind: TokenKind::Integer(n),
+                offset,
+            }) if *offset == token.offset + 1 => {
+                let n = -*n;
+                state.offset = *offset;
+                tokens.next();
+                Ok(AST::Integer(n))
+            }
+            _ => {
+                let operand = primary(state, tokens)?;
+                Ok(compound(symbol, vec![argument(operand)]))
+            }
+        },
+        TokenKind::Atom(name) => match tokens.peek() {
+            Some(Token {
+                kind: TokenKind::Punct('('),
+                offset,
+            }) if *offset == token.offset + 1 => {
+                state.offset = *offset;
+                tokens.next();
+                let mut arguments = vec![argument(term(state, tokens)?)];
+                while let Some(Token {
+                    kind: TokenKind::Punct(','),
+                    offset,
+                }) = tokens.peek()
+                {
+                    state.offset = *offset;
+                    tokens.next();
+                    arguments.push(argument(term(state, tokens)?));
+                }
+                expect(
+                    state,
+                    tokens,
+                    TokenKind::Punct(')'),
+                    "`,` or `)` in arguments",
+                )?;
+                Ok(AST::FnCall(name, arguments, token.offset))
+            }
+            _ => Ok(AST::Atom(name)),
+        },
+        TokenKind::Punct('(') => {
+            let inner = term(state, tokens)?;
+            expect(state, tokens, TokenKind::Punct(')'), "`)`")?;
+            Ok(inner)
+        }
+        TokenKind::Punct('[') => list(state, tokens),
+        TokenKind::Punct('{') => Err(unsupported("Curly bracketed terms are", token.offset)),
+        TokenKind::Symbol(symbol) => Err(SyntaxError {
+            msg: format!("Unexpected `{}` while parsing term.", symbol),
+            offset: token.offset,
+        }),
+        TokenKind::Punct(c) => Err(SyntaxError {
+            msg: format!("Unexpected `{}` while parsing term.", c),
+            offset: token.offset,
+        }),
+        TokenKind::End => Err(SyntaxError {
+            msg: "Unexpected `.` while parsing term.".to_string(),
+            offset: token.offset,
+        }),
+    }
+}
+
+// A list, after its `[`.
+fn list(state: &mut ParseState, tokens: &mut Tokens) -> Result<AST, SyntaxError> {
+    if let Some(Token {
+        kind: TokenKind::Punct(']'),
+        offset,
+    }) = tokens.peek()
+    {
+        state.offset = *offset;
+        tokens.next();
+        return Ok(AST::List(vec![], None));
+    }
+    let mut elements = vec![argument(term(state, tokens)?)];
+    let mut tail = None;
+    loop {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Punct(','),
+                offset,
+            }) => {
+                state.offset = offset;
+                elements.push(argument(term(state, tokens)?));
+            }
+            Some(Token {
+                kind: TokenKind::Punct('|'),
+                offset,
+            }) => {
+                state.offset = offset;
+                tail = Some(Box::new(argument(term(state, tokens)?)));
+                expect(
+                    state,
+                    tokens,
+                    TokenKind::Punct(']'),
+                    "`]` after the tail of a list",
+                )?;
+                break;
+            }
+            Some(Token {
+                kind: TokenKind::Punct(']'),
+                offset,
+            }) => {
+                state.offset = offset;
+                break;
+            }
+            Some(token) => {
+                return Err(SyntaxError {
+                    msg: "Expected `,`, `|` or `]` in list.".to_string(),
+                    offset: token.offset,
+                })
+            }
+            None => {
+                return Err(SyntaxError {
+                    msg: "Unexpected end of input while parsing list.".to_string(),
+                    offset: state.offset,
+                })
+            }
+        }
+    }
+    Ok(AST::List(elements, tail))
+}
+
+type Comparison = fn(&i64, &i64) -> bool;
+
+// Define the foreign relations that `\=`, `is` and the arithmetic comparisons
+// are translated to.
+pub fn register(vm: &mut VirtualMachine) {
+    let operators: HashMap<AtomType, &'static str> = ["+", "-", "*", "/", "//", "mod"]
+        .iter()
+        .map(|op| (vm.intern(&op.to_string()), *op))
+        .collect();
+    vm.register_relation("$dif", 2, |args, substs| {
+        let mut unified = substs.clone();
+        if unify(&args[0], &args[1], &mut unified) {
+            Box::new(std::iter::empty())
+        } else {
+            Box::new(std::iter::once(substs.clone()))
+        }
+    });
+    let functor = vm.intern(&FUNCTOR.to_string());
+    let ops = operators.clone();
+    vm.register_relation("$is", 2, move |args, substs| {
+        let mut substs = substs.clone();
+        match evaluate(&args[1], &substs, functor, &ops) {
+            Some(n) if unify(&args[0], &Term::Integer(n), &mut substs) => {
+                Box::new(std::iter::once(substs))
+            }
+            _ => Box::new(std::iter::empty()),
+        }
+    });
+    let comparisons: [(&str, Comparison); 6] = [
+        ("$<", i64::lt),
+        ("$>", i64::gt),
+        ("$=<", i64::le),
+        ("$>=", i64::ge),
+        ("$=:=", i64::eq),
+        ("$=\\=", i64::ne),
+    ];
+    for (name, compare) in comparisons {
+        let ops = operators.clone();
+        vm.register_relation(name, 2, move |args, substs| {
+            match (
+                evaluate(&args[0], substs, functor, &ops),
+                evaluate(&args[1], substs, functor, &ops),
+            ) {
+                (Some(a), Some(b)) if compare(&a, &b) => Box::new(std::iter::once(substs.clone())),
+                _ => Box::new(std::iter::empty()),
+            }
+        });
+    }
+}
+
+// Evaluate an arithmetic expression, or return None if it isn't a bound
+// integer expression, or divides by zero or overflows.
+fn evaluate(
+    term: &Term<AtomType>,
+    substs: &Substitutions<AtomType>,
+    functor: AtomType,
+    operators: &HashMap<AtomType, &'static str>,
+) -> Option<i64> {
+    match walk(term, substs) {
+        Term::Integer(n) => Some(*n),
+        Term::Tuple(elements) => match &elements[..] {
+            [Term::Atom(tag), Term::Atom(op), operand]
+                if *tag == functor && operators.get(op) == Some(&"-") =>
+            {
+                evaluate(operand, substs, functor, operators)?.checked_neg()
+            }
+            [Term::Atom(tag), Term::Atom(op), left, right] if *tag == functor => {
+                let a = evaluate(left, substs, functor, operators)?;
+                let b = evaluate(right, substs, functor, operators)?;
+                match *operators.get(op)? {
+                    "+" => a.checked_add(b),
+                    "-" => a.checked_sub(b),
+                    "*" => a.checked_mul(b),
+                    "/" | "//" => a.checked_div(b),
+                    "mod" => a
+                        .checked_rem_euclid(b)
+                        .map(|r| if b < 0 && r != 0 { r + b } else { r }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prolog;
+
+    macro_rules! translate {
+        ($input:expr, $value:expr) => {{
+            match prolog::parse($input) {
+                Ok(ast) => assert_eq!(ast.to_string(), $value),
+                Err(err) => assert_eq!("translation failed", err.to_string()),
+            }
+        }};
+    }
+
+    macro_rules! unsupported {
+        ($input:expr, $msg:expr, $offset:expr) => {{
+            match prolog::parse($input) {
+                Ok(_) => assert!(false),
+                Err(crate::errors::Error::Syntax(err)) => {
+                    assert_eq!(err.msg, $msg);
+                    assert_eq!(err.offset, $offset);
+                }
+                Err(crate::errors::Error::Tokenizer(err)) => {
+                    assert_eq!(err.msg, $msg);
+                    assert_eq!(err.offset, $offset);
+                }
+                Err(_) => assert!(false),
+            }
+        }};
+    }
+
+    #[test]
+    fn translation() {
+        translate!(
+            "parent(abe, homer). % A comment.\nparent(homer, 'Bart').",
+            "Parent('abe, 'homer).Parent('homer, 'Bart)."
+        );
+        translate!(
+            "grandparent(X, Z) :- parent(X, Y), parent(Y, Z).",
+            "Grandparent(x, z) :- Parent(x, y), Parent(y, z)."
+        );
+        translate!(
+            "app([], L, L). app([H|T], L, [H|R]) :- app(T, L, R).",
+            "App([], l, l). App([h | t], l, [h | r]) :- App(t, l, r)."
+        );
+        translate!(
+            "len([_|T], N) :- len(T, M), N is M + 1 * 2.",
+            "Len([_ | t], n) :- Len(t, m), $is(n, ['$f, '+, m, ['$f, '*, 1, 2]])."
+        );
+        translate!(
+            "p(X, Y) :- X \\= Y, X =< -1 ; f(X) = Y.",
+            "P(x, y) :- disj { conj { $dif(x, y) , $=<(x, -1) } | ['$f, 'f, x] == y }."
+        );
+        translate!("shape(circle(3)).", "Shape(['$f, 'circle, 3]).");
+        unsupported!("p(X) :- !, q(X).", "Cut `!` is not supported.", 8);
+        unsupported!("p(X) :- \\+ q(X).", "Negation `\\+` is not supported.", 9);
+        unsupported!(
+            "p(X) :- (q(X) -> r(X) ; s(X)).",
+            "If-then-else `->` is not supported.",
+            15
+        );
+        unsupported!(":- dynamic(p/1).", "Directives are not supported.", 1);
+        unsupported!(
+            "p :- q(a).",
+            "Predicates without arguments are not supported.",
+            0
+        );
+        unsupported!("p(X) :- X == a.", "The operator `==` is not supported.", 11);
+        unsupported!(
+            "p(a). p(a, b).",
+            "Predicates with the same name and different arities are not supported: p/1 and p/2.",
+            6
+        );
+        unsupported!("p(1.5).", "Floating point numbers are not supported.", 3);
+        unsupported!(
+            "p(a) :- q(a)",
+            "Unexpected end of input, expected `.` at the end of the clause.",
+            11
+        );
+    }
+
+    #[test]
+    fn compound_terms() {
+        // Compound terms are tagged, so `f(a)` doesn't unify with the list
+        // `[f, a]`, and a list isn't evaluated as an arithmetic expression.
+        let mut tern = crate::Interpreter::new();
+        tern.eval_prolog(
+            "shape(circle(3)).
+             list([circle, 3]).
+             same(X, X).
+             value(X, Y) :- Y is X.",
+        )
+        .unwrap();
+        let count = |tern: &mut crate::Interpreter, goal: &str| tern.query(goal).unwrap().count();
+        assert_eq!(count(&mut tern, "conj { Shape(x), List(x) }"), 0);
+        assert_eq!(
+            count(&mut tern, "conj { Shape(x), Same(x, ['circle | _]) }"),
+            0
+        );
+        assert_eq!(count(&mut tern, "conj { Shape(x), Shape(x) }"), 1);
+        assert_eq!(count(&mut tern, "conj { List(x), Value(x, y) }"), 0);
+        let answer = tern
+            .query("var (e) { conj { e == ['$f, '+, 1, 2], Value(e, y) } }")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(answer.get("y").and_then(|y| y.as_integer()), Some(3));
+    }
+}
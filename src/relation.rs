@@ -0,0 +1,298 @@
+use crate::facts::{FactTable, Facts};
+use crate::foreign::{Foreign, ForeignRelation};
+use crate::logic::{Conj2, Goal, Unify};
+use crate::unification::{rename, walk, Substitutions, Term};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+// The goal built from the body of a relation defined in Tern. The body is
+// built the first time the relation is called, and is shared by every call.
+pub enum Body<T> {
+    Unbuilt,
+    // The body is being built, e.g. by a recursive call.
+    Building,
+    Built(Rc<dyn Goal<T>>),
+}
+
+// A relation that can be called from a goal.
+pub enum Relation<T> {
+    // A relation defined in Tern, with its parameters and body.
+    Defined(Rc<Vec<u64>>, Rc<RefCell<Body<T>>>),
+    Facts(Rc<RefCell<FactTable<T>>>),
+    Foreign(Rc<ForeignRelation<T>>),
+}
+
+// The error raised while solving a goal, if any, e.g. by calling a relation
+// with the wrong number of arguments. Answers are produced by iterators, which
+// can't return errors, so the error is kept here until the virtual machine
+// takes the next answer from the stream.
+pub type Error = Rc<RefCell<Option<String>>>;
+
+fn check_arity(arity: usize, args: &[Term<impl Sized>]) -> Result<(), String> {
+    if arity != args.len() {
+        return Err(format!(
+            "TypeError: Expected {} argument(s) but found {}.",
+            arity,
+            args.len()
+        ));
+    }
+    Ok(())
+}
+
+impl<T: Clone + Eq + Hash + 'static> Relation<T> {
+    // Return the goal for a call with the arguments, or an error if the call
+    // has the wrong number of arguments. The parameters and variables of a
+    // relation defined in Tern are renamed apart from those of every other
+    // call, which is what allows relations to be recursive.
+    pub fn call(&self, args: &[Term<T>]) -> Result<Rc<dyn Goal<T>>, String> {
+        match self {
+            Relation::Defined(parameters, body) => {
+                check_arity(parameters.len(), args)?;
+                let mut renaming = HashMap::new();
+                let mut goal = match &*body.borrow() {
+                    Body::Built(goal) => goal.rename(&mut renaming),
+                    _ => return Err("Relation called before its body was built.".to_string()),
+                };
+                for (parameter, arg) in parameters.iter().zip(args.iter()).rev() {
+                    let parameter = rename(&Term::Variable(*parameter), &mut renaming);
+                    goal = Rc::new(Conj2::new(
+                        Rc::new(Unify::new(parameter, arg.clone())),
+                        goal,
+                    ));
+                }
+                Ok(goal)
+            }
+            Relation::Facts(table) => {
+                check_arity(table.borrow().arity, args)?;
+                Ok(Rc::new(Facts::new(table.clone(), args.to_vec())))
+            }
+            Relation::Foreign(foreign) => {
+                check_arity(foreign.arity, args)?;
+                Ok(Rc::new(Foreign::new(
+                    foreign.relation.clone(),
+                    args.to_vec(),
+                )))
+            }
+        }
+    }
+}
+
+// The Call goal calls a relation with the arguments of the call. The goal
+// for the call is only built once the call is solved, so that a recursive
+// relation only recurses as far as its answers require. If the call fails,
+// the goal has no answers and the reason is kept in `error`.
+pub struct Call<T> {
+    relation: Rc<Relation<T>>,
+    args: Vec<Term<T>>,
+    error: Error,
+}
+
+impl<T> Call<T> {
+    pub fn new(relation: Rc<Relation<T>>, args: Vec<Term<T>>, error: Error) -> Self {
+        Call {
+            relation,
+            args,
+            error,
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash + 'static> Goal<T> for Call<T> {
+    fn solve(&self, substs: &Substitutions<T>) -> Box<dyn Iterator<Item = Substitutions<T>>> {
+        let relation = self.relation.clone();
+        let args = self.args.clone();
+        let substs = substs.clone();
+        let error = self.error.clone();
+        Box::new(
+            std::iter::once(()).flat_map(move |_| match relation.call(&args) {
+                Ok(goal) => goal.solve(&substs),
+                Err(msg) => {
+                    error.borrow_mut().get_or_insert(msg);
+                    Box::new(std::iter::empty())
+                }
+            }),
+        )
+    }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Call::new(
+            self.relation.clone(),
+            self.args.iter().map(|arg| rename(arg, renaming)).collect(),
+            self.error.clone(),
+        ))
+    }
+}
+
+// Relations that have been passed as terms, e.g. as arguments to other
+// relations, by the atom that stands for them.
+pub type Relations<T> = Rc<RefCell<HashMap<T, Rc<Relation<T>>>>>;
+
+// The Apply goal calls the relation that a term is bound to, e.g. the
+// parameter `r` in `rel Twice(r, x, z) { var (y) { conj { r(x, y), r(y, z) } } }`.
+// The goal fails if the term is not bound to a relation.
+pub struct Apply<T> {
+    relations: Relations<T>,
+    relation: Term<T>,
+    args: Vec<Term<T>>,
+    error: Error,
+}
+
+impl<T> Apply<T> {
+    pub fn new(
+        relations: Relations<T>,
+        relation: Term<T>,
+        args: Vec<Term<T>>,
+        error: Error,
+    ) -> Self {
+        Apply {
+            relations,
+            relation,
+            args,
+            error,
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash + 'static> Goal<T> for Apply<T> {
+    fn solve(&self, substs: &Substitutions<T>) -> Box<dyn Iterator<Item = Substitutions<T>>> {
+        let relation = match walk(&self.relation, substs) {
+            Term::Atom(a) => self.relations.borrow().get(a).cloned(),
+            _ => None,
+        };
+        match relation {
+            Some(relation) => {
+                Call::new(relation, self.args.clone(), self.error.clone()).solve(substs)
+            }
+            None => Box::new(std::iter::empty()),
+        }
+    }
+
+    fn rename(&self, renaming: &mut HashMap<u64, u64>) -> Rc<dyn Goal<T>> {
+        Rc::new(Apply::new(
+            self.relations.clone(),
+            rename(&self.relation, renaming),
+            self.args.iter().map(|arg| rename(arg, renaming)).collect(),
+            self.error.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::facts::FactTable;
+    use crate::logic::{Disj2, Unify};
+    use crate::relation::*;
+    use crate::unification::reify;
+
+    #[test]
+    fn test_call() {
+        // R(x) holds if x is 1 or 2.
+        let body: Rc<dyn Goal<u64>> = Rc::new(Disj2::new(
+            Rc::new(Unify::new(Term::Variable(1), Term::Atom(1))),
+            Rc::new(Unify::new(Term::Variable(1), Term::Atom(2))),
+        ));
+        let r = Rc::new(Relation::Defined(
+            Rc::new(vec![1]),
+            Rc::new(RefCell::new(Body::Built(body))),
+        ));
+        let substs = HashMap::new();
+        let error: Error = Rc::new(RefCell::new(None));
+        let goal = Conj2::new(
+            Rc::new(Call::new(r.clone(), vec![Term::Variable(2)], error.clone())),
+            Rc::new(Call::new(r.clone(), vec![Term::Variable(3)], error.clone())),
+        );
+        // The two calls have their own copies of the parameter.
+        let answers: Vec<(Term<u64>, Term<u64>)> = goal
+            .solve(&substs)
+            .map(|substs| {
+                (
+                    reify(&Term::Variable(2), &substs),
+                    reify(&Term::Variable(3), &substs),
+                )
+            })
+            .collect();
+        assert_eq!(answers.len(), 4);
+        assert!(answers.contains(&(Term::Atom(1), Term::Atom(2))));
+        assert!(error.borrow().is_none());
+
+        // A call with the wrong number of arguments has no answers, and
+        // records the error.
+        assert_eq!(
+            Call::new(r, vec![], error.clone()).solve(&substs).count(),
+            0
+        );
+        assert_eq!(
+            error.take().as_deref(),
+            Some("TypeError: Expected 1 argument(s) but found 0.")
+        );
+
+        // So does a call to a relation whose body is still being built.
+        let r = Rc::new(Relation::Defined(
+            Rc::new(vec![1]),
+            Rc::new(RefCell::new(Body::Building)),
+        ));
+        let call = Call::new(r, vec![Term::Variable(2)], error.clone());
+        assert_eq!(call.solve(&substs).count(), 0);
+        assert_eq!(
+            error.take().as_deref(),
+            Some("Relation called before its body was built.")
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut table = FactTable::new(1);
+        table.insert(vec![Term::Atom(1)]);
+        let relations: Relations<u64> = Rc::new(RefCell::new(HashMap::new()));
+        relations
+            .borrow_mut()
+            .insert(10, Rc::new(Relation::Facts(Rc::new(RefCell::new(table)))));
+        let error: Error = Rc::new(RefCell::new(None));
+        let goal = Conj2::new(
+            Rc::new(Unify::new(Term::Variable(1), Term::Atom(10))),
+            Rc::new(Apply::new(
+                relations.clone(),
+                Term::Variable(1),
+                vec![Term::Variable(2)],
+                error.clone(),
+            )),
+        );
+        let substs = HashMap::new();
+        let mut iter = goal.solve(&substs);
+        assert_eq!(*iter.next().unwrap().get(&2).unwrap(), Term::Atom(1));
+        assert!(iter.next().is_none());
+
+        // Unbound relations have no answers.
+        let goal = Apply::new(
+            relations.clone(),
+            Term::Variable(1),
+            vec![Term::Variable(2)],
+            error.clone(),
+        );
+        assert_eq!(goal.solve(&substs).count(), 0);
+        assert!(error.borrow().is_none());
+
+        // A relation applied to the wrong number of arguments records the
+        // error.
+        let goal = Conj2::new(
+            Rc::new(Unify::new(Term::Variable(1), Term::Atom(10))),
+            Rc::new(Apply::new(
+                relations,
+                Term::Variable(1),
+                vec![Term::Variable(2), Term::Variable(3)],
+                error.clone(),
+            )),
+        );
+        assert_eq!(goal.solve(&substs).count(), 0);
+        assert_eq!(
+            error.take().as_deref(),
+            Some("TypeError: Expected 1 argument(s) but found 2.")
+        );
+    }
+}
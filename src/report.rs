@@ -0,0 +1,402 @@
+use crate::answer::{Outcome, Value};
+use crate::errors::Error;
+use crate::{csv, json, Interpreter};
+
+// The format used to report the result of each statement.
+//
+// Apart from text, every format reports each result on its own line (after
+// a header row for CSV) using one of the statuses `answer`, `no`, `value` or
+// `error`:
+//
+//   json:  {"status":"answer","bindings":{"x":"abraham"}}
+//          {"status":"no"}
+//          {"status":"value","value":3}
+//          {"status":"error","kind":"RuntimeError","message":"..."}
+//          {"status":"error","kind":"SyntaxError","message":"...","file":"family.tern",
+//           "offset":12,"line":2,"column":5}
+//   csv:   status,name,value,kind,message,file,offset,line,column
+//          answer,x,abraham,,,,,,
+//          error,,,SyntaxError,...,family.tern,12,2,5
+//   sexpr: (answer (x abraham))
+//          (no)
+//          (value 3)
+//          (error RuntimeError "...")
+//          (error SyntaxError "..." (file "family.tern") (offset 12) (line 2) (column 5))
+//
+// An answer has one CSV row per binding, or a single row without a name if
+// it has no bindings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+    Sexpr,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "sexpr" => Some(Format::Sexpr),
+            _ => None,
+        }
+    }
+
+    // A line to print before any results.
+    pub fn header(&self) -> Option<&'static str> {
+        match self {
+            Format::Csv => Some("status,name,value,kind,message,file,offset,line,column"),
+            _ => None,
+        }
+    }
+}
+
+// The result of evaluating a statement, with enough context to locate any
+// syntax errors.
+pub struct Report<'a> {
+    pub result: &'a Result<Option<Outcome>, Error>,
+    pub filename: &'a str,
+    pub src: &'a str,
+}
+
+// A syntax or tokenizer error, with its location in the source.
+struct Location<'a> {
+    kind: &'a str,
+    msg: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Report<'a> {
+    fn location(&self) -> Option<Location<'a>> {
+        let (kind, msg, offset) = match self.result {
+            Err(Error::Tokenizer(err)) => ("TokenizerError", &err.msg, err.offset),
+            Err(Error::Syntax(err)) => ("SyntaxError", &err.msg, err.offset),
+            _ => return None,
+        };
+        let (line, column) = location(self.src, offset);
+        Some(Location {
+            kind,
+            msg,
+            offset,
+            line,
+            column,
+        })
+    }
+}
+
+// Return the 1-based line and column of the character at `offset`.
+pub fn location(src: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+    for ch in src.chars().take(offset) {
+        if ch == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+pub fn print(report: &Report, format: Format, interpreter: &Interpreter) {
+    if let Ok(None) = report.result {
+        return;
+    }
+    match format {
+        Format::Text => print!("{}", to_text(report, interpreter)),
+        Format::Json => println!("{}", to_json(report)),
+        Format::Csv => {
+            for row in to_csv(report) {
+                let row: Vec<String> = row.iter().map(|field| csv::quote(field)).collect();
+                println!("{}", row.join(","));
+            }
+        }
+        Format::Sexpr => println!("{}", to_sexpr(report)),
+    }
+}
+
+fn to_text(report: &Report, interpreter: &Interpreter) -> String {
+    let mut text = String::new();
+    match report.result {
+        Ok(None) => {}
+        Ok(Some(Outcome::Answer(answer))) => {
+            if answer.is_empty() {
+                text += "Ok.\n";
+            } else {
+                for (name, value) in answer.iter() {
+                    text += &format!("{}: {}\n", name, value);
+                }
+            }
+        }
+        Ok(Some(Outcome::No)) => text += "No.\n",
+        Ok(Some(Outcome::Value(value))) => text += &format!("{}\n", value),
+        Ok(Some(Outcome::Other(value))) => text += &format!("{}\n", value),
+        Err(Error::Runtime(err)) => {
+            text += &format!("RuntimeError: {}\n", err.msg);
+            text += &interpreter.backtrace();
+        }
+        Err(_) => {
+            if let Some(location) = report.location() {
+                let line = report.src.split('\n').nth(location.line - 1).unwrap_or("");
+                let width = location.line.to_string().len() + 2;
+                text += &format!("{}: {}\n", location.kind, location.msg);
+                text += &format!("{s:>width$}|\n", s = " ", width = width);
+                text += &format!(" {} | {}\n", location.line, line);
+                text += &format!("{s:>width$}|", s = " ", width = width);
+                text += &format!("{s:>width$}^\n", s = " ", width = location.column);
+                text += &format!(
+                    "--> {}:{}:{}\n",
+                    report.filename, location.line, location.column
+                );
+            }
+        }
+    }
+    text
+}
+
+fn value_to_json(value: &Value) -> String {
+    match value {
+        Value::Integer(n) => n.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Atom(s) | Value::String(s) | Value::Variable(s) => json::quote(s),
+        Value::Tuple(values) => {
+            let values: Vec<String> = values.iter().map(value_to_json).collect();
+            format!("[{}]", values.join(","))
+        }
+        // A list with an unbound tail has no JSON equivalent.
+        Value::List(_, _) => json::quote(&value.to_string()),
+    }
+}
+
+fn to_json(report: &Report) -> String {
+    match report.result {
+        Ok(None) => String::new(),
+        Ok(Some(Outcome::Answer(answer))) => {
+            let bindings: Vec<String> = answer
+                .iter()
+                .map(|(name, value)| format!("{}:{}", json::quote(name), value_to_json(value)))
+                .collect();
+            format!(
+                "{{\"status\":\"answer\",\"bindings\":{{{}}}}}",
+                bindings.join(",")
+            )
+        }
+        Ok(Some(Outcome::No)) => "{\"status\":\"no\"}".to_string(),
+        Ok(Some(Outcome::Value(value))) => format!(
+            "{{\"status\":\"value\",\"value\":{}}}",
+            value_to_json(value)
+        ),
+        Ok(Some(Outcome::Other(value))) => format!(
+            "{{\"status\":\"value\",\"value\":{}}}",
+            json::quote(value)
+        ),
+        Err(Error::Runtime(err)) => format!(
+            "{{\"status\":\"error\",\"kind\":\"RuntimeError\",\"message\":{}}}",
+            json::quote(&err.msg)
+        ),
+        Err(_) => match report.location() {
+            Some(location) => format!(
+                "{{\"status\":\"error\",\"kind\":{},\"message\":{},\"file\":{},\"offset\":{},\"line\":{},\"column\":{}}}",
+                json::quote(location.kind),
+                json::quote(location.msg),
+                json::quote(report.filename),
+                location.offset,
+                location.line,
+                location.column
+            ),
+            None => String::new(),
+        },
+    }
+}
+
+// Return the rows for a report, with the fields in the order of the header.
+fn to_csv(report: &Report) -> Vec<Vec<String>> {
+    let row = |status: &str, name: &str, value: &str| {
+        let mut row = vec![status.to_string(), name.to_string(), value.to_string()];
+        row.resize(9, String::new());
+        row
+    };
+    match report.result {
+        Ok(None) => Vec::new(),
+        Ok(Some(Outcome::Answer(answer))) if answer.is_empty() => vec![row("answer", "", "")],
+        Ok(Some(Outcome::Answer(answer))) => answer
+            .iter()
+            .map(|(name, value)| row("answer", name, &value.to_string()))
+            .collect(),
+        Ok(Some(Outcome::No)) => vec![row("no", "", "")],
+        Ok(Some(Outcome::Value(value))) => vec![row("value", "", &value.to_string())],
+        Ok(Some(Outcome::Other(value))) => vec![row("value", "", value)],
+        Err(Error::Runtime(err)) => {
+            let mut row = row("error", "", "");
+            row[3] = "RuntimeError".to_string();
+            row[4] = err.msg.clone();
+            vec![row]
+        }
+        Err(_) => match report.location() {
+            Some(location) => vec![vec![
+                "error".to_string(),
+                String::new(),
+                String::new(),
+                location.kind.to_string(),
+                location.msg.to_string(),
+                report.filename.to_string(),
+                location.offset.to_string(),
+                location.line.to_string(),
+                location.column.to_string(),
+            ]],
+            None => Vec::new(),
+        },
+    }
+}
+
+fn value_to_sexpr(value: &Value) -> String {
+    match value {
+        Value::String(s) => json::quote(s),
+        Value::Tuple(values) => {
+            let values: Vec<String> = values.iter().map(value_to_sexpr).collect();
+            format!("({})", values.join(" "))
+        }
+        Value::List(values, tail) => {
+            let values: Vec<String> = values.iter().map(value_to_sexpr).collect();
+            format!("({} . {})", values.join(" "), value_to_sexpr(tail))
+        }
+        value => value.to_string(),
+    }
+}
+
+fn to_sexpr(report: &Report) -> String {
+    match report.result {
+        Ok(None) => String::new(),
+        Ok(Some(Outcome::Answer(answer))) => {
+            let mut sexpr = String::from("(answer");
+            for (name, value) in answer.iter() {
+                sexpr += &format!(" ({} {})", name, value_to_sexpr(value));
+            }
+            sexpr + ")"
+        }
+        Ok(Some(Outcome::No)) => "(no)".to_string(),
+        Ok(Some(Outcome::Value(value))) => format!("(value {})", value_to_sexpr(value)),
+        Ok(Some(Outcome::Other(value))) => format!("(value {})", json::quote(value)),
+        Err(Error::Runtime(err)) => format!("(error RuntimeError {})", json::quote(&err.msg)),
+        Err(_) => match report.location() {
+            Some(location) => format!(
+                "(error {} {} (file {}) (offset {}) (line {}) (column {}))",
+                location.kind,
+                json::quote(location.msg),
+                json::quote(report.filename),
+                location.offset,
+                location.line,
+                location.column
+            ),
+            None => String::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::answer::{Answer, Outcome, Value};
+    use crate::errors::{Error, RuntimeError, SyntaxError};
+    use crate::report::*;
+
+    #[test]
+    fn locating() {
+        let src = "let x = 1\nnext(solve(Father('haran, y)))";
+        assert_eq!(location(src, 0), (1, 1));
+        assert_eq!(location(src, 4), (1, 5));
+        assert_eq!(location(src, 10), (2, 1));
+        assert_eq!(location(src, 15), (2, 6));
+    }
+
+    #[test]
+    fn formats() {
+        let report = |result| Report {
+            result,
+            filename: "family.tern",
+            src: "let x = 1\nlet = 2",
+        };
+        let answer = Ok(Some(Outcome::Answer(Answer::new(vec![(
+            "x".to_string(),
+            Value::Tuple(vec![
+                Value::Atom("abraham".to_string()),
+                Value::String("Abraham, son of Terach".to_string()),
+            ]),
+        )]))));
+        assert_eq!(
+            to_json(&report(&answer)),
+            "{\"status\":\"answer\",\"bindings\":{\"x\":[\"abraham\",\"Abraham, son of Terach\"]}}"
+        );
+        assert_eq!(
+            to_sexpr(&report(&answer)),
+            "(answer (x (abraham \"Abraham, son of Terach\")))"
+        );
+        assert_eq!(
+            to_csv(&report(&answer))[0][..3],
+            ["answer", "x", "[abraham, \"Abraham, son of Terach\"]"]
+        );
+
+        // Booleans are JSON literals, but atoms are always strings.
+        let literals = Ok(Some(Outcome::Value(Value::Tuple(vec![
+            Value::Boolean(true),
+            Value::Atom("true".to_string()),
+            Value::Atom("null".to_string()),
+            Value::String("false".to_string()),
+        ]))));
+        assert_eq!(
+            to_json(&report(&literals)),
+            "{\"status\":\"value\",\"value\":[true,\"true\",\"null\",\"false\"]}"
+        );
+
+        let no = Ok(Some(Outcome::No));
+        assert_eq!(to_json(&report(&no)), "{\"status\":\"no\"}");
+        assert_eq!(to_sexpr(&report(&no)), "(no)");
+
+        let value = Ok(Some(Outcome::Value(Value::Integer(3))));
+        assert_eq!(
+            to_json(&report(&value)),
+            "{\"status\":\"value\",\"value\":3}"
+        );
+        assert_eq!(to_sexpr(&report(&value)), "(value 3)");
+
+        let err = Err(Error::Runtime(RuntimeError {
+            msg: "Stack underflow.".to_string(),
+            ip: 0,
+        }));
+        assert_eq!(
+            to_json(&report(&err)),
+            "{\"status\":\"error\",\"kind\":\"RuntimeError\",\"message\":\"Stack underflow.\"}"
+        );
+
+        let err = Err(Error::Syntax(SyntaxError {
+            msg: "Unexpected token.".to_string(),
+            offset: 14,
+        }));
+        assert_eq!(
+            to_json(&report(&err)),
+            "{\"status\":\"error\",\"kind\":\"SyntaxError\",\"message\":\"Unexpected token.\",\"file\":\"family.tern\",\"offset\":14,\"line\":2,\"column\":5}"
+        );
+        assert_eq!(
+            to_csv(&report(&err)),
+            vec![vec![
+                "error",
+                "",
+                "",
+                "SyntaxError",
+                "Unexpected token.",
+                "family.tern",
+                "14",
+                "2",
+                "5"
+            ]]
+        );
+        assert_eq!(
+            to_sexpr(&report(&err)),
+            "(error SyntaxError \"Unexpected token.\" (file \"family.tern\") (offset 14) (line 2) (column 5))"
+        );
+    }
+}
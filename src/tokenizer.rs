@@ -9,6 +9,7 @@ pub enum TokenKind {
     Dot,
     DoubleEquals,
     Equals,
+    FatArrow,
     LeftBrace,
     LeftBracket,
     LeftParen,
@@ -17,16 +18,31 @@ pub enum TokenKind {
     RightBracket,
     RightParen,
     Tick,
+    Underscore,
 
     // Keywords
+    As,
+    Break,
     Conj,
     Disj,
+    Else,
+    False,
+    Fn,
+    For,
+    If,
+    Import,
+    In,
     Let,
+    Match,
     Rel,
+    Return,
+    True,
     Var,
+    While,
 
     // Literals
     Literal(String),
+    String(String),
 }
 
 impl fmt::Display for TokenKind {
@@ -37,6 +53,7 @@ impl fmt::Display for TokenKind {
             TokenKind::DoubleEquals => write!(f, "=="),
             TokenKind::Dot => write!(f, "."),
             TokenKind::Equals => write!(f, "="),
+            TokenKind::FatArrow => write!(f, "=>"),
             TokenKind::LeftBrace => write!(f, "{{"),
             TokenKind::LeftBracket => write!(f, "["),
             TokenKind::LeftParen => write!(f, "("),
@@ -45,12 +62,27 @@ impl fmt::Display for TokenKind {
             TokenKind::RightBracket => write!(f, "]"),
             TokenKind::RightParen => write!(f, ")"),
             TokenKind::Tick => write!(f, "'"),
+            TokenKind::Underscore => write!(f, "_"),
+            TokenKind::As => write!(f, "as"),
+            TokenKind::Break => write!(f, "break"),
             TokenKind::Conj => write!(f, "conj"),
             TokenKind::Disj => write!(f, "disj"),
+            TokenKind::Else => write!(f, "else"),
+            TokenKind::False => write!(f, "false"),
+            TokenKind::Fn => write!(f, "fn"),
+            TokenKind::For => write!(f, "for"),
+            TokenKind::If => write!(f, "if"),
+            TokenKind::Import => write!(f, "import"),
+            TokenKind::In => write!(f, "in"),
             TokenKind::Let => write!(f, "let"),
+            TokenKind::Match => write!(f, "match"),
             TokenKind::Rel => write!(f, "rel"),
+            TokenKind::Return => write!(f, "return"),
+            TokenKind::True => write!(f, "true"),
             TokenKind::Var => write!(f, "var"),
+            TokenKind::While => write!(f, "while"),
             TokenKind::Literal(s) => write!(f, "{}", s),
+            TokenKind::String(s) => write!(f, "\"{}\"", s),
         }
     }
 }
@@ -86,6 +118,13 @@ pub fn scan(src: &str) -> Result<Vec<Token>, TokenizerError> {
                     });
                     chars.next();
                     offset += 1;
+                } else if let Some('>') = chars.peek() {
+                    tokens.push(Token {
+                        kind: TokenKind::FatArrow,
+                        offset,
+                    });
+                    chars.next();
+                    offset += 1;
                 } else {
                     tokens.push(Token {
                         kind: TokenKind::Equals,
@@ -125,8 +164,64 @@ pub fn scan(src: &str) -> Result<Vec<Token>, TokenizerError> {
                 kind: TokenKind::Tick,
                 offset,
             }),
-            '#' => {
+            '_' => {
+                // A name may follow `_` to document what it stands for, e.g.
+                // `_parent`, but the variable is still anonymous.
+                while let Some(c) = chars.peek() {
+                    if c.is_alphanumeric() || *c == '_' {
+                        chars.next();
+                        offset += 1;
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token {
+                    kind: TokenKind::Underscore,
+                    offset,
+                })
+            }
+            '"' => {
+                let start = offset;
+                let mut v = Vec::new();
+                let mut terminated = false;
                 while let Some(c) = chars.next() {
+                    offset += 1;
+                    match c {
+                        '"' => {
+                            terminated = true;
+                            break;
+                        }
+                        '\\' => {
+                            offset += 1;
+                            match chars.next() {
+                                Some('"') => v.push('"'),
+                                Some('\\') => v.push('\\'),
+                                Some('n') => v.push('\n'),
+                                Some('t') => v.push('\t'),
+                                _ => {
+                                    return Err(TokenizerError {
+                                        msg: "Invalid escape sequence in string.".to_string(),
+                                        offset,
+                                    });
+                                }
+                            }
+                        }
+                        _ => v.push(c),
+                    }
+                }
+                if !terminated {
+                    return Err(TokenizerError {
+                        msg: "Unterminated string.".to_string(),
+                        offset: start,
+                    });
+                }
+                tokens.push(Token {
+                    kind: TokenKind::String(v.into_iter().collect()),
+                    offset,
+                });
+            }
+            '#' => {
+                for c in chars.by_ref() {
                     offset += 1;
                     if c == '\n' {
                         break;
@@ -137,7 +232,7 @@ pub fn scan(src: &str) -> Result<Vec<Token>, TokenizerError> {
             _ => {
                 let mut v = vec![c];
                 while let Some(c) = chars.peek() {
-                    if c.is_alphanumeric() {
+                    if c.is_alphanumeric() || *c == '_' {
                         v.push(*c);
                         chars.next();
                         offset += 1;
@@ -147,6 +242,14 @@ pub fn scan(src: &str) -> Result<Vec<Token>, TokenizerError> {
                 }
                 let s: String = v.into_iter().collect();
                 match &s[..] {
+                    "as" => tokens.push(Token {
+                        kind: TokenKind::As,
+                        offset,
+                    }),
+                    "break" => tokens.push(Token {
+                        kind: TokenKind::Break,
+                        offset,
+                    }),
                     "conj" => tokens.push(Token {
                         kind: TokenKind::Conj,
                         offset,
@@ -155,18 +258,62 @@ pub fn scan(src: &str) -> Result<Vec<Token>, TokenizerError> {
                         kind: TokenKind::Disj,
                         offset,
                     }),
+                    "else" => tokens.push(Token {
+                        kind: TokenKind::Else,
+                        offset,
+                    }),
+                    "false" => tokens.push(Token {
+                        kind: TokenKind::False,
+                        offset,
+                    }),
+                    "fn" => tokens.push(Token {
+                        kind: TokenKind::Fn,
+                        offset,
+                    }),
+                    "for" => tokens.push(Token {
+                        kind: TokenKind::For,
+                        offset,
+                    }),
+                    "if" => tokens.push(Token {
+                        kind: TokenKind::If,
+                        offset,
+                    }),
+                    "import" => tokens.push(Token {
+                        kind: TokenKind::Import,
+                        offset,
+                    }),
+                    "in" => tokens.push(Token {
+                        kind: TokenKind::In,
+                        offset,
+                    }),
                     "let" => tokens.push(Token {
                         kind: TokenKind::Let,
                         offset,
                     }),
+                    "match" => tokens.push(Token {
+                        kind: TokenKind::Match,
+                        offset,
+                    }),
                     "rel" => tokens.push(Token {
                         kind: TokenKind::Rel,
                         offset,
                     }),
+                    "return" => tokens.push(Token {
+                        kind: TokenKind::Return,
+                        offset,
+                    }),
+                    "true" => tokens.push(Token {
+                        kind: TokenKind::True,
+                        offset,
+                    }),
                     "var" => tokens.push(Token {
                         kind: TokenKind::Var,
                         offset,
                     }),
+                    "while" => tokens.push(Token {
+                        kind: TokenKind::While,
+                        offset,
+                    }),
                     _ => tokens.push(Token {
                         kind: TokenKind::Literal(s),
                         offset,
@@ -297,8 +444,8 @@ mod tests {
             TokenKind::RightBrace
         );
         scan!(
-            "fn('olive == 'olive)",
-            TokenKind::Literal("fn".to_string()),
+            "f('olive == 'olive)",
+            TokenKind::Literal("f".to_string()),
             TokenKind::LeftParen,
             TokenKind::Tick,
             TokenKind::Literal("olive".to_string()),
@@ -337,6 +484,45 @@ mod tests {
             TokenKind::Literal("bean".to_string()),
             TokenKind::RightBrace
         );
+        scan!(
+            "conj { _ == 'olive, Female(_) }",
+            TokenKind::Conj,
+            TokenKind::LeftBrace,
+            TokenKind::Underscore,
+            TokenKind::DoubleEquals,
+            TokenKind::Tick,
+            TokenKind::Literal("olive".to_string()),
+            TokenKind::Comma,
+            TokenKind::Literal("Female".to_string()),
+            TokenKind::LeftParen,
+            TokenKind::Underscore,
+            TokenKind::RightParen,
+            TokenKind::RightBrace
+        );
+        scan!(
+            "load_csv(\"people.csv\", Person)",
+            TokenKind::Literal("load_csv".to_string()),
+            TokenKind::LeftParen,
+            TokenKind::String("people.csv".to_string()),
+            TokenKind::Comma,
+            TokenKind::Literal("Person".to_string()),
+            TokenKind::RightParen
+        );
+        scan!(
+            "\"say \\\"hi\\\"\\n\" == x",
+            TokenKind::String("say \"hi\"\n".to_string()),
+            TokenKind::DoubleEquals,
+            TokenKind::Literal("x".to_string())
+        );
+        scan!(
+            "42 == -7",
+            TokenKind::Literal("42".to_string()),
+            TokenKind::DoubleEquals,
+            TokenKind::Literal("-7".to_string())
+        );
+        scan!("\"\"", TokenKind::String("".to_string()));
+        scanfails!("x == \"olive", "Unterminated string.", 5);
+        scanfails!("\"ol\\ive\"", "Invalid escape sequence in string.", 4);
         scan!("{}", TokenKind::LeftBrace, TokenKind::RightBrace);
         scan!(
             "{'olive: 'oil}",
@@ -384,5 +570,76 @@ mod tests {
             TokenKind::Literal("apple".to_string()),
             TokenKind::RightBrace
         );
+        scan!(
+            "fn f(x) { return x }",
+            TokenKind::Fn,
+            TokenKind::Literal("f".to_string()),
+            TokenKind::LeftParen,
+            TokenKind::Literal("x".to_string()),
+            TokenKind::RightParen,
+            TokenKind::LeftBrace,
+            TokenKind::Return,
+            TokenKind::Literal("x".to_string()),
+            TokenKind::RightBrace
+        );
+        scan!(
+            "match x { [h | _tail] => h }",
+            TokenKind::Match,
+            TokenKind::Literal("x".to_string()),
+            TokenKind::LeftBrace,
+            TokenKind::LeftBracket,
+            TokenKind::Literal("h".to_string()),
+            TokenKind::Pipe,
+            TokenKind::Underscore,
+            TokenKind::RightBracket,
+            TokenKind::FatArrow,
+            TokenKind::Literal("h".to_string()),
+            TokenKind::RightBrace
+        );
+        scan!(
+            "if true { } else { } while false { break } for a in s { }",
+            TokenKind::If,
+            TokenKind::True,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace,
+            TokenKind::Else,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace,
+            TokenKind::While,
+            TokenKind::False,
+            TokenKind::LeftBrace,
+            TokenKind::Break,
+            TokenKind::RightBrace,
+            TokenKind::For,
+            TokenKind::Literal("a".to_string()),
+            TokenKind::In,
+            TokenKind::Literal("s".to_string()),
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        );
+        scan!(
+            "import \"family.tern\" as f",
+            TokenKind::Import,
+            TokenKind::String("family.tern".to_string()),
+            TokenKind::As,
+            TokenKind::Literal("f".to_string())
+        );
+    }
+
+    #[test]
+    fn anonymous_variables() {
+        // A name after `_` documents the variable, which is still anonymous.
+        scan!(
+            "_ _x _parent_1 __, x_",
+            TokenKind::Underscore,
+            TokenKind::Underscore,
+            TokenKind::Underscore,
+            TokenKind::Underscore,
+            TokenKind::Comma,
+            TokenKind::Literal("x_".to_string())
+        );
+        let tokens = scan("[_tail]").unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::Underscore);
+        assert_eq!(tokens[2].kind, TokenKind::RightBracket);
     }
 }
@@ -1,13 +1,21 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 pub type Substitutions<T> = HashMap<u64, Term<T>>;
 
-#[derive(Debug, Eq, Hash)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub enum Term<T> {
     Atom(T),
     Variable(u64),
     Tuple(Vec<Term<T>>),
+    // A list with a head and a tail, e.g. `[h | t]`. Lists are tuples, so a
+    // Cons unifies with any non-empty tuple, binding the tail to the rest of
+    // the tuple.
+    Cons(Box<Term<T>>, Box<Term<T>>),
+    Integer(i64),
+    // Strings are interned like atoms, but are distinct from them.
+    String(T),
 }
 
 impl<T: Clone> Clone for Term<T> {
@@ -16,34 +24,9 @@ impl<T: Clone> Clone for Term<T> {
             Term::Atom(u) => Term::Atom(u.clone()),
             Term::Variable(u) => Term::Variable(*u),
             Term::Tuple(u) => Term::Tuple(u.to_vec()),
-        }
-    }
-}
-
-impl<T: std::cmp::PartialEq> PartialEq for Term<T> {
-    fn eq(&self, other: &Term<T>) -> bool {
-        match self {
-            Term::Atom(u) => {
-                if let Term::Atom(v) = other {
-                    u == v
-                } else {
-                    false
-                }
-            }
-            Term::Variable(u) => {
-                if let Term::Variable(v) = other {
-                    u == v
-                } else {
-                    false
-                }
-            }
-            Term::Tuple(u) => {
-                if let Term::Tuple(v) = other {
-                    u == v
-                } else {
-                    false
-                }
-            }
+            Term::Cons(h, t) => Term::Cons(h.clone(), t.clone()),
+            Term::Integer(u) => Term::Integer(*u),
+            Term::String(u) => Term::String(u.clone()),
         }
     }
 }
@@ -54,7 +37,7 @@ impl<T: std::cmp::PartialEq> PartialEq for Term<T> {
 // until an unbound variable or an atom is encountered. E.g, given substitutions that map x -> y,
 // y -> z, and z -> "ceviche", calling `walk` with the variable `x` will result in the atom
 // "ceviche".
-fn walk<'a, T: Clone>(x: &'a Term<T>, substs: &'a Substitutions<T>) -> &'a Term<T> {
+pub fn walk<'a, T: Clone>(x: &'a Term<T>, substs: &'a Substitutions<T>) -> &'a Term<T> {
     if let Term::Variable(var) = x {
         if let Some(t) = substs.get(var) {
             walk(t, substs)
@@ -66,6 +49,26 @@ fn walk<'a, T: Clone>(x: &'a Term<T>, substs: &'a Substitutions<T>) -> &'a Term<
     }
 }
 
+// Resolve the value of x in the substitutions, including the values of any
+// variables nested inside of tuples.
+//
+// Unlike `walk`, which stops at the first tuple it encounters, `reify` produces
+// a term that contains no bound variables, which makes it suitable for
+// reporting answers. A list whose tail is bound to a tuple becomes a tuple.
+pub fn reify<T: Clone>(x: &Term<T>, substs: &Substitutions<T>) -> Term<T> {
+    match walk(x, substs) {
+        Term::Tuple(terms) => Term::Tuple(terms.iter().map(|t| reify(t, substs)).collect()),
+        Term::Cons(h, t) => match reify(t, substs) {
+            Term::Tuple(mut terms) => {
+                terms.insert(0, reify(h, substs));
+                Term::Tuple(terms)
+            }
+            t => Term::Cons(Box::new(reify(h, substs)), Box::new(t)),
+        },
+        term => term.clone(),
+    }
+}
+
 // Attempt to unify the left and right hand terms using the given substitutions, returning
 // true if the terms unify, false otherwise.
 //
@@ -79,62 +82,62 @@ pub fn unify<T: std::cmp::PartialEq + Clone>(
     right: &Term<T>,
     substs: &mut Substitutions<T>,
 ) -> bool {
-    match left {
-        Term::Atom(u) => match right {
-            Term::Atom(v) => u == v,
-            Term::Variable(_) => {
-                let x = walk(right, substs);
-                if let Term::Variable(var) = x {
-                    substs.insert(*var, left.clone());
-                    true
-                } else {
-                    left == x
-                }
-            }
-            Term::Tuple(_) => false,
-        },
-        Term::Variable(_) => {
-            let x = walk(left, substs);
-            // Check for equality early to avoid binding a variable to itself,
-            // which will lead to infinite recursion while unifying.
-            if right == x {
-                true
-            } else if let Term::Variable(var) = x {
-                let y = walk(right, substs);
-                // Only introduce a binding if y resolves to something other
-                // than x, to avoid cycles that will lead to infinite recursion
-                // while unifying.
-                if x != y {
-                    substs.insert(*var, right.clone());
-                }
-                true
-            } else {
-                false
-            }
+    let x = walk(left, substs).clone();
+    let y = walk(right, substs).clone();
+    // Check for equality early to avoid binding a variable to itself, which
+    // will lead to infinite recursion while unifying.
+    if x == y {
+        return true;
+    }
+    match (x, y) {
+        (Term::Variable(var), y) => {
+            substs.insert(var, y);
+            true
         }
-        Term::Tuple(u) => match right {
-            Term::Atom(_) => false,
-            Term::Variable(var) => {
-                let x = walk(right, substs);
-                if let Term::Variable(var) = x {
-                    substs.insert(*var, left.clone());
-                    true
-                } else {
-                    right == x
-                }
-            }
-            Term::Tuple(v) => {
-                if u.len() != v.len() {
-                    return false;
-                }
-                for (u0, v0) in u.iter().zip(v.iter()) {
-                    if !unify(u0, v0, substs) {
-                        return false;
-                    }
-                }
-                return true;
-            }
-        },
+        (x, Term::Variable(var)) => {
+            substs.insert(var, x);
+            true
+        }
+        (Term::Tuple(u), Term::Tuple(v)) => {
+            u.len() == v.len() && u.iter().zip(v.iter()).all(|(u0, v0)| unify(u0, v0, substs))
+        }
+        (Term::Cons(h, t), Term::Cons(h0, t0)) => unify(&h, &h0, substs) && unify(&t, &t0, substs),
+        (Term::Cons(h, t), Term::Tuple(v)) | (Term::Tuple(v), Term::Cons(h, t)) => {
+            !v.is_empty()
+                && unify(&h, &v[0], substs)
+                && unify(&t, &Term::Tuple(v[1..].to_vec()), substs)
+        }
+        _ => false,
+    }
+}
+
+// Fresh variables are numbered from FRESH upwards, so that they never clash
+// with the variables of a program.
+const FRESH: u64 = 1 << 62;
+
+static NEXT_FRESH: AtomicU64 = AtomicU64::new(FRESH);
+
+// Return a new variable that is distinct from every other variable.
+pub fn fresh_variable() -> u64 {
+    NEXT_FRESH.fetch_add(1, Ordering::Relaxed)
+}
+
+pub fn is_fresh(var: u64) -> bool {
+    var >= FRESH
+}
+
+// Rename the variables of a term, replacing each variable with the fresh
+// variable it is mapped to by `renaming`, or adding a new mapping the first
+// time a variable is seen. Calls to relations use this to rename the
+// variables of the relation body apart from those of every other call.
+pub fn rename<T: Clone>(x: &Term<T>, renaming: &mut HashMap<u64, u64>) -> Term<T> {
+    match x {
+        Term::Variable(var) => Term::Variable(*renaming.entry(*var).or_insert_with(fresh_variable)),
+        Term::Tuple(terms) => Term::Tuple(terms.iter().map(|t| rename(t, renaming)).collect()),
+        Term::Cons(h, t) => {
+            Term::Cons(Box::new(rename(h, renaming)), Box::new(rename(t, renaming)))
+        }
+        term => term.clone(),
     }
 }
 
@@ -160,6 +163,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reify() {
+        let mut substs = HashMap::new();
+        substs.insert(1, Term::Variable(2));
+        substs.insert(2, Term::Tuple(vec![Term::Variable(3), Term::Variable(4)]));
+        substs.insert(3, Term::Atom("a".to_string()));
+        assert_eq!(
+            reify(&Term::Variable(1), &substs),
+            Term::Tuple(vec![Term::Atom("a".to_string()), Term::Variable(4)])
+        );
+        assert_eq!(reify(&Term::Variable(4), &substs), Term::Variable(4));
+    }
+
     #[test]
     fn test_unify() {
         let mut substs = HashMap::new();
@@ -179,6 +195,16 @@ mod tests {
         assert!(unify(&Term::Atom(1), &Term::Atom(1), &mut substs));
         assert_eq!(substs.len(), 0);
 
+        let mut substs = HashMap::new();
+        assert!(unify(&Term::Integer(1), &Term::Integer(1), &mut substs));
+        assert!(!unify(&Term::Integer(1), &Term::Integer(2), &mut substs));
+        assert!(!unify(&Term::Integer(1), &Term::Atom(1), &mut substs));
+        assert!(!unify(&Term::String(1), &Term::Atom(1), &mut substs));
+        assert!(unify(&Term::Variable(1), &Term::String(1), &mut substs));
+        assert!(!unify(&Term::Variable(1), &Term::Atom(1), &mut substs));
+        assert!(unify(&Term::Integer(42), &Term::Variable(2), &mut substs));
+        assert_eq!(*substs.get(&2).unwrap(), Term::Integer(42));
+
         let mut substs = HashMap::new();
         assert!(unify(&Term::Variable(1), &Term::Atom(1), &mut substs));
         assert_eq!(substs.len(), 1);
@@ -241,4 +267,89 @@ mod tests {
         assert_eq!(*substs.get(&1).unwrap(), Term::Variable(2));
         assert_eq!(*substs.get(&2).unwrap(), Term::Atom(42));
     }
+    #[test]
+    fn test_unify_bound() {
+        // A bound variable unifies with an unbound variable by binding it
+        // to the value.
+        let mut substs = HashMap::new();
+        substs.insert(1, Term::Atom(1));
+        assert!(unify(&Term::Variable(1), &Term::Variable(2), &mut substs));
+        assert_eq!(*substs.get(&2).unwrap(), Term::Atom(1));
+
+        let mut substs = HashMap::new();
+        substs.insert(1, Term::Tuple(vec![Term::Atom(1), Term::Variable(3)]));
+        assert!(unify(
+            &Term::Variable(1),
+            &Term::Tuple(vec![Term::Variable(2), Term::Atom(2)]),
+            &mut substs
+        ));
+        assert_eq!(*substs.get(&2).unwrap(), Term::Atom(1));
+        assert_eq!(*substs.get(&3).unwrap(), Term::Atom(2));
+        assert!(!unify(
+            &Term::Variable(1),
+            &Term::Tuple(vec![Term::Atom(2), Term::Atom(2)]),
+            &mut substs
+        ));
+    }
+
+    #[test]
+    fn test_unify_cons() {
+        let list = |t: Term<i32>| Term::Cons(Box::new(Term::Variable(1)), Box::new(t));
+        // [h | t] unifies with [1, 2], binding the tail to [2].
+        let mut substs = HashMap::new();
+        assert!(unify(
+            &list(Term::Variable(2)),
+            &Term::Tuple(vec![Term::Atom(1), Term::Atom(2)]),
+            &mut substs
+        ));
+        assert_eq!(*substs.get(&1).unwrap(), Term::Atom(1));
+        assert_eq!(*substs.get(&2).unwrap(), Term::Tuple(vec![Term::Atom(2)]));
+        // But not with the empty tuple.
+        let mut substs = HashMap::new();
+        assert!(!unify(
+            &list(Term::Variable(2)),
+            &Term::Tuple(vec![]),
+            &mut substs
+        ));
+        // A list becomes a tuple once its tail is bound to a tuple.
+        let mut substs = HashMap::new();
+        assert!(unify(
+            &Term::Variable(3),
+            &list(Term::Variable(2)),
+            &mut substs
+        ));
+        assert!(unify(&Term::Variable(1), &Term::Atom(1), &mut substs));
+        assert_eq!(
+            reify(&Term::Variable(3), &substs),
+            Term::Cons(Box::new(Term::Atom(1)), Box::new(Term::Variable(2)))
+        );
+        assert!(unify(&Term::Variable(2), &Term::Tuple(vec![]), &mut substs));
+        assert_eq!(
+            reify(&Term::Variable(3), &substs),
+            Term::Tuple(vec![Term::Atom(1)])
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut renaming = HashMap::new();
+        let term = rename(
+            &Term::Tuple(vec![Term::Variable(1), Term::Atom(1), Term::Variable(1)]),
+            &mut renaming,
+        );
+        let fresh = *renaming.get(&1).unwrap();
+        assert!(is_fresh(fresh));
+        assert_eq!(
+            term,
+            Term::Tuple(vec![
+                Term::Variable(fresh),
+                Term::Atom(1),
+                Term::Variable(fresh)
+            ])
+        );
+        assert_ne!(
+            rename(&Term::<i32>::Variable(1), &mut HashMap::new()),
+            Term::Variable(fresh)
+        );
+    }
 }
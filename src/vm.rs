@@ -1,12 +1,26 @@
+use crate::bottomup;
+use crate::builtins;
+use crate::csv;
 use crate::errors::RuntimeError;
+use crate::facts;
+use crate::foreign;
+use crate::json;
 use crate::logic;
+use crate::relation;
 use crate::unification;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Write};
 use std::rc::Rc;
 
 pub type AtomType = u64;
 
+// The number of rows `load_csv` and `load_json` read before adding them to a
+// fact relation, and propagating them to the relations materialised from it.
+const LOAD_BATCH: usize = 4096;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CallableKind {
     Function,
@@ -21,6 +35,12 @@ pub enum Opcode {
     // Push a new variable term to the stack.
     // -> Term
     Variable(u64),
+    // Push a new integer term to the stack.
+    // -> Term
+    Integer(i64),
+    // Push a new string term to the stack.
+    // -> Term
+    String(AtomType),
     // Pop two goals from the stack and construct a new Conj2 goal using them.
     // Goal Goal -> Conj2
     Conj2,
@@ -30,12 +50,9 @@ pub enum Opcode {
     // Pop two terms from the stack and attempt to unify them.
     // Term Term -> Unify
     Unify,
-    // Solve the goal to produce a stream.
-    // Goal -> Stream
-    Solve,
-    // Call next on the stream, pushing a table to the stack.
-    // Stream -> Stream Table
-    Next,
+    // Call the builtin function with the index, passing n arguments to it.
+    // arg1 arg2 ... argn -> value
+    CallNative(usize, usize),
     // Pop the value from the top of the stack.
     // Value ->
     Pop,
@@ -46,26 +63,85 @@ pub enum Opcode {
     // Table key value -> Table
     SetTable,
     // Get the field in the table with `key`. Pushes `None` for missing keys.
-    // Table key -> Table value
+    // Table key -> value
     GetTable,
-    // Set a variable `name` in the environment to `value`.
+    // Set a variable `name` in the environment to `value`. Inside a function
+    // call, this sets a local variable of the call.
     // name value ->
     SetEnv,
-    // Get the value variable `name` in the environment.
+    // Get the value variable `name` in the environment, looking in the local
+    // variables of each call, innermost first, and then in the global
+    // environment.
     // name -> value
     GetEnv,
-    // Call the callable, passing n arguments to it.
+    // Call the callable, passing n arguments to it. Calling a relation
+    // produces a goal for the call.
     // arg1 arg2 ... argn callable -> value
-    Call,
-    // Return from a call.
+    Call(usize),
+    // Produce a goal that calls the relation bound to a term, passing n
+    // arguments to it.
+    // arg1 arg2 ... argn term -> Goal
+    Apply(usize),
+    // Add a row of n terms to the fact relation `name`, creating the relation
+    // if it does not exist.
+    // name term1 term2 ... termn ->
+    Fact(usize),
+    // Return from a call. A relation returns a goal, and a function returns
+    // the value at the top of its stack, or None if it has none.
     Ret,
-    // Create a new callable
+    // Create a new callable. Relations are created as closures with no
+    // captured variables.
     Callable {
         kind: CallableKind,
         parameters: Rc<Vec<u64>>,
         instructions: Rc<Vec<Opcode>>,
         ip: usize,
     },
+    // Capture the current values of the variables in a new copy of the
+    // closure.
+    // Closure -> Closure
+    Capture(Rc<Vec<u64>>),
+    // Pop n terms from the stack and push a tuple of them.
+    // term1 term2 ... termn -> Term
+    Tuple(usize),
+    // Pop n terms and a tail from the stack and push a list of the terms
+    // followed by the tail.
+    // term1 term2 ... termn tail -> Term
+    Cons(usize),
+    // Pop a pattern and unify it with the value below it. If they unify, pop
+    // the value and set each of the variables to its value in the match,
+    // otherwise skip the next n instructions, leaving the value on the stack.
+    // Term Pattern -> | Term
+    Match(Rc<Vec<u64>>, usize),
+    // Skip the next n instructions.
+    Jump(usize),
+    // Pop a value and skip the next n instructions if it is false or None.
+    // Value ->
+    JumpIfFalse(usize),
+    // If the value at the top of the stack is None, pop it and skip the next
+    // n instructions.
+    // Value -> Value |
+    JumpIfNone(usize),
+    // Go back to the instruction n before this one.
+    Loop(usize),
+    // Swap the two values at the top of the stack.
+    // a b -> b a
+    Swap,
+    // Push None to the stack.
+    // -> None
+    None,
+    // Push a boolean to the stack.
+    // -> Boolean
+    Boolean(bool),
+    // Get the next answer from the stream, pushing the stream and a table of
+    // the answer, or None and discarding the stream if there are no more
+    // answers.
+    // Stream -> Stream Table | None
+    Next,
+    // Set the variables that are reported in every answer of the stream,
+    // even if they are unbound, e.g. the free variables of a query.
+    // Stream -> Stream
+    Report(Rc<Vec<u64>>),
 }
 
 impl fmt::Display for CallableKind {
@@ -77,18 +153,78 @@ impl fmt::Display for CallableKind {
     }
 }
 
+// A relation defined in Tern, together with the values of the variables it
+// captured from the function it was defined in.
+pub struct Closure {
+    pub parameters: Rc<Vec<u64>>,
+    pub instructions: Rc<Vec<Opcode>>,
+    pub captured: HashMap<u64, Value>,
+    pub body: Rc<RefCell<relation::Body<AtomType>>>,
+}
+
 pub enum Value {
     Term(unification::Term<AtomType>),
     Goal(Rc<dyn logic::Goal<AtomType>>),
-    Stream(Box<dyn Iterator<Item = unification::Substitutions<AtomType>>>),
-    Table(HashMap<unification::Term<AtomType>, unification::Term<AtomType>>),
+    // A stream of answers, with the variables that are reported in each
+    // answer even if they are unbound.
+    Stream(
+        Box<dyn Iterator<Item = unification::Substitutions<AtomType>>>,
+        Rc<Vec<u64>>,
+    ),
+    // Tables are shared, so a change through one reference is seen through
+    // every other.
+    Table(Rc<RefCell<HashMap<unification::Term<AtomType>, Value>>>),
+    Facts(Rc<RefCell<facts::FactTable<AtomType>>>),
+    Foreign(Rc<foreign::ForeignRelation<AtomType>>),
     None,
+    Boolean(bool),
     Callable {
         kind: CallableKind,
         parameters: Rc<Vec<u64>>,
         instructions: Rc<Vec<Opcode>>,
         ip: usize,
     },
+    Closure(Rc<Closure>),
+}
+
+impl Value {
+    pub fn new_table(table: HashMap<unification::Term<AtomType>, Value>) -> Value {
+        Value::Table(Rc::new(RefCell::new(table)))
+    }
+
+    // Return a copy of the value, or None for streams, which can't be
+    // copied.
+    pub fn try_clone(&self) -> Option<Value> {
+        match self {
+            Value::Term(t) => Some(Value::Term(t.clone())),
+            Value::Goal(g) => Some(Value::Goal(g.clone())),
+            Value::Stream(..) => None,
+            Value::Table(t) => Some(Value::Table(t.clone())),
+            Value::Facts(t) => Some(Value::Facts(t.clone())),
+            Value::Foreign(f) => Some(Value::Foreign(f.clone())),
+            Value::None => Some(Value::None),
+            Value::Boolean(b) => Some(Value::Boolean(*b)),
+            Value::Callable {
+                kind,
+                parameters,
+                instructions,
+                ip,
+            } => Some(Value::Callable {
+                kind: *kind,
+                parameters: parameters.clone(),
+                instructions: instructions.clone(),
+                ip: *ip,
+            }),
+            Value::Closure(c) => Some(Value::Closure(c.clone())),
+        }
+    }
+
+    pub fn as_term(&self) -> Option<&unification::Term<AtomType>> {
+        match self {
+            Value::Term(term) => Some(term),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -100,42 +236,88 @@ impl fmt::Display for Value {
             Value::Goal(_) => {
                 write!(f, "<goal>")
             }
-            Value::Stream(_) => write!(f, "<stream>"),
+            Value::Stream(..) => write!(f, "<stream>"),
             Value::Table(values) => {
                 write!(f, "<table (")?;
                 let mut first = true;
-                for value in values {
+                for value in values.borrow().iter() {
                     if !first {
-                        write!(f, ", {:?}: {:?}", value.0, value.1)?;
+                        write!(f, ", {:?}: {}", value.0, value.1)?;
                     } else {
                         first = false;
-                        write!(f, "{:?}: {:?}", value.0, value.1)?;
+                        write!(f, "{:?}: {}", value.0, value.1)?;
                     }
                 }
                 write!(f, ")>")
             }
+            Value::Facts(_) => write!(f, "<facts>"),
+            Value::Foreign(_) => write!(f, "<foreign relation>"),
             Value::None => write!(f, "<none>"),
+            Value::Boolean(b) => write!(f, "{}", b),
             Value::Callable {
                 kind,
                 parameters: _,
                 instructions: _,
                 ip: _,
             } => write!(f, "<{}>", kind),
+            Value::Closure(_) => write!(f, "<relation>"),
         }
     }
 }
 
+// A call on the callstack.
+pub struct Frame {
+    pub kind: CallableKind,
+    pub instructions: Rc<Vec<Opcode>>,
+    pub ip: usize,
+    // The local variables of a function call, starting with its parameters.
+    pub locals: HashMap<u64, Value>,
+    // The height of the stack when the call started, which is restored when
+    // a function returns.
+    pub base: usize,
+}
+
 pub struct VirtualMachine {
     next_id: u64,
     pub interned: HashMap<String, u64>,
     pub variables: HashMap<u64, u64>,
+    // Variables that are never reported in answers, e.g. anonymous variables
+    // and variables local to relations.
+    pub hidden: HashSet<u64>,
 
     pub stack: Vec<Value>,
-    pub callstack: Vec<Value>,
+    pub callstack: Vec<Frame>,
 
-    // Because we don't currently support user functions, all let bindings
-    // occur at global scope.
+    // Let bindings at the top level. Bindings inside a function are stored
+    // in the locals of its frame.
     pub env: HashMap<u64, Value>,
+
+    // Names defined by the host program rather than by Tern source, e.g.
+    // foreign relations, and the variables they are bound to.
+    pub globals: HashMap<String, u64>,
+
+    // Relations that have been passed as terms.
+    pub relations: relation::Relations<AtomType>,
+    // The error raised while solving a goal, which is reported when the next
+    // answer is taken from its stream.
+    pub goal_error: relation::Error,
+    // The atom standing for each relation passed as a term, keyed by the
+    // address of its body, table or foreign relation, so that passing the
+    // same relation again reuses it. `relations` keeps these alive, so an
+    // address is never reused.
+    relation_atoms: HashMap<*const (), AtomType>,
+
+    // The Datalog rules of the relations defined at the top level, so they
+    // can be materialised, or why a relation isn't Datalog.
+    pub rules: HashMap<u64, Result<Rc<bottomup::Definition>, String>>,
+
+    // The materialised relations, which are kept up to date as facts are
+    // asserted and retracted.
+    pub views: HashMap<u64, bottomup::View>,
+
+    // Where `print` and `println` write to, which is stdout unless the host
+    // program sets it.
+    pub output: Box<dyn Write>,
 }
 
 macro_rules! err {
@@ -149,9 +331,11 @@ macro_rules! err {
 
 macro_rules! buildgoal {
     ($vm:expr, $type:tt, $goal:tt, $ip: expr) => {{
+        // The right operand is at the top of the stack, so that goals are
+        // solved in the order they are written.
         match $vm.stack.pop() {
-            Some(Value::$type(left)) => match $vm.stack.pop() {
-                Some(Value::$type(right)) => {
+            Some(Value::$type(right)) => match $vm.stack.pop() {
+                Some(Value::$type(left)) => {
                     $vm.stack
                         .push(Value::Goal(Rc::new(logic::$goal::new(left, right))));
                 }
@@ -198,7 +382,17 @@ impl VirtualMachine {
         self.next_id += 1;
         let interned = self.intern(variable);
         self.variables.insert(id, interned);
-        return id;
+        id
+    }
+
+    pub fn new_hidden_variable(&mut self, variable: &String) -> u64 {
+        let id = self.new_variable(variable);
+        self.hidden.insert(id);
+        id
+    }
+
+    pub fn new_anonymous_variable(&mut self) -> u64 {
+        self.new_hidden_variable(&"_".to_string())
     }
 
     pub fn lookup_variable(&self, id: &u64) -> Option<&String> {
@@ -209,95 +403,566 @@ impl VirtualMachine {
         }
     }
 
+    // Render a term using the interned names of its atoms, strings and
+    // variables.
+    pub fn term_to_string(&self, term: &unification::Term<AtomType>) -> String {
+        match term {
+            unification::Term::Atom(a) => match self.lookup_interned(a) {
+                Some(interned) => interned.to_string(),
+                None => a.to_string(),
+            },
+            unification::Term::Variable(v) => match self.lookup_variable(v) {
+                Some(name) => name.to_string(),
+                None if unification::is_fresh(*v) => "_".to_string(),
+                None => v.to_string(),
+            },
+            unification::Term::Integer(n) => n.to_string(),
+            unification::Term::String(s) => match self.lookup_interned(s) {
+                Some(interned) => format!("{:?}", interned),
+                None => s.to_string(),
+            },
+            unification::Term::Tuple(terms) => {
+                let terms: Vec<String> = terms.iter().map(|t| self.term_to_string(t)).collect();
+                format!("[{}]", terms.join(", "))
+            }
+            unification::Term::Cons(h, t) => {
+                let mut terms = vec![self.term_to_string(h)];
+                let mut tail = t;
+                while let unification::Term::Cons(h, t) = &**tail {
+                    terms.push(self.term_to_string(h));
+                    tail = t;
+                }
+                format!("[{} | {}]", terms.join(", "), self.term_to_string(tail))
+            }
+        }
+    }
+
+    // Render a value for output, showing strings without quotes and the
+    // fields of tables by name, e.g. `{x: abraham}`.
+    pub fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Term(unification::Term::String(s)) => match self.lookup_interned(s) {
+                Some(interned) => interned.to_string(),
+                None => s.to_string(),
+            },
+            Value::Term(term) => self.term_to_string(term),
+            Value::Table(table) => {
+                let mut fields: Vec<String> = table
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| {
+                        format!("{}: {}", self.term_to_string(key), self.display(value))
+                    })
+                    .collect();
+                fields.sort();
+                format!("{{{}}}", fields.join(", "))
+            }
+            value => value.to_string(),
+        }
+    }
+
+    // Build the table of answers from substitutions, reifying the value of
+    // every variable that is not hidden, and of `variables`. The fresh
+    // variables of relation calls are hidden too. Variables that are unbound
+    // are reported as `_`.
+    pub fn answer(
+        &self,
+        substs: &unification::Substitutions<AtomType>,
+        variables: &[u64],
+    ) -> HashMap<unification::Term<AtomType>, unification::Term<AtomType>> {
+        let mut table = HashMap::new();
+        for var in substs.keys().chain(variables) {
+            if !self.hidden.contains(var) && !unification::is_fresh(*var) {
+                let term = unification::Term::Variable(*var);
+                let value = match unification::reify(&term, substs) {
+                    unification::Term::Variable(v) if v == *var => {
+                        unification::Term::Variable(unification::fresh_variable())
+                    }
+                    value => value,
+                };
+                table.insert(term, value);
+            }
+        }
+        table
+    }
+
+    // Build a table value of the answer, as returned by `next`.
+    pub fn answer_table(
+        &self,
+        substs: &unification::Substitutions<AtomType>,
+        variables: &[u64],
+    ) -> Value {
+        Value::new_table(
+            self.answer(substs, variables)
+                .into_iter()
+                .map(|(key, value)| (key, Value::Term(value)))
+                .collect(),
+        )
+    }
+
+    // Convert a JSON value to a term. Numbers that aren't integers become
+    // strings, nested arrays become tuples, and nested objects become tuples
+    // of [key, value] pairs.
+    fn json_to_term(&mut self, value: &json::Value) -> unification::Term<AtomType> {
+        match value {
+            json::Value::Null => unification::Term::Atom(self.intern(&"null".to_string())),
+            json::Value::Bool(b) => unification::Term::Atom(self.intern(&b.to_string())),
+            json::Value::Integer(n) => unification::Term::Integer(*n),
+            json::Value::Number(text) => unification::Term::String(self.intern(text)),
+            json::Value::String(s) => unification::Term::String(self.intern(s)),
+            json::Value::Array(values) => {
+                unification::Term::Tuple(values.iter().map(|v| self.json_to_term(v)).collect())
+            }
+            json::Value::Object(fields) => unification::Term::Tuple(
+                fields
+                    .iter()
+                    .map(|(key, value)| {
+                        unification::Term::Tuple(vec![
+                            unification::Term::String(self.intern(key)),
+                            self.json_to_term(value),
+                        ])
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    // Bind `name` to a relation implemented in Rust, which can then be called
+    // from Tern like any other relation.
+    pub fn register_relation<F>(&mut self, name: &str, arity: usize, relation: F) -> u64
+    where
+        F: Fn(
+                &[unification::Term<AtomType>],
+                &unification::Substitutions<AtomType>,
+            ) -> Box<dyn Iterator<Item = unification::Substitutions<AtomType>>>
+            + 'static,
+    {
+        let id = self.new_variable(&name.to_string());
+        self.env.insert(
+            id,
+            Value::Foreign(Rc::new(foreign::ForeignRelation {
+                arity,
+                relation: Rc::new(relation),
+            })),
+        );
+        self.globals.insert(name.to_string(), id);
+        id
+    }
+
+    // Pop the arguments of a call to a relation from the stack. Relations
+    // passed as arguments are converted to terms.
+    fn pop_args(
+        &mut self,
+        arity: usize,
+        ip: usize,
+    ) -> Result<Vec<unification::Term<AtomType>>, RuntimeError> {
+        if self.stack.len() < arity {
+            err!(self, "Stack underflow.", ip);
+        }
+        let mut args = Vec::new();
+        for value in self.stack.split_off(self.stack.len() - arity) {
+            args.push(self.relation_term(value, ip)?);
+        }
+        Ok(args)
+    }
+
+    // Check that a call passes as many arguments as the callee expects.
+    fn check_arity(&self, arity: usize, argc: usize, ip: usize) -> Result<(), RuntimeError> {
+        if arity != argc {
+            err!(
+                self,
+                format!(
+                    "TypeError: Expected {} argument(s) but found {}.",
+                    arity, argc
+                ),
+                ip
+            );
+        }
+        Ok(())
+    }
+
+    // Set a variable in the environment, or in the local variables of the
+    // current call. The outermost frame is the top-level program.
+    fn set_env(&mut self, key: u64, value: Value) {
+        if self.callstack.len() > 1 {
+            if let Some(frame) = self.callstack.last_mut() {
+                frame.locals.insert(key, value);
+            }
+        } else {
+            self.env.insert(key, value);
+        }
+    }
+
+    // Return the field of a table with `key`. The answers returned by `next`
+    // are tables keyed by variables, so an atom key also finds the variable
+    // with the same name, e.g. `answer.x`.
+    fn field<'a>(
+        &self,
+        table: &'a HashMap<unification::Term<AtomType>, Value>,
+        key: &unification::Term<AtomType>,
+    ) -> Option<&'a Value> {
+        // Variable names are interned like atoms.
+        table.get(key).or_else(|| match key {
+            unification::Term::Atom(a) => table.iter().find_map(|(key, value)| match key {
+                unification::Term::Variable(v) if self.variables.get(v) == Some(a) => Some(value),
+                _ => None,
+            }),
+            _ => None,
+        })
+    }
+
+    // Pop n terms from the stack, in the order they were pushed.
+    fn pop_terms(
+        &mut self,
+        n: usize,
+        ip: usize,
+    ) -> Result<Vec<unification::Term<AtomType>>, RuntimeError> {
+        if self.stack.len() < n {
+            err!(self, "Stack underflow.", ip);
+        }
+        let mut terms = Vec::new();
+        for value in self.stack.split_off(self.stack.len() - n) {
+            match value {
+                Value::Term(term) => terms.push(term),
+                _ => err!(self, "TypeError: Expected term.", ip),
+            }
+        }
+        Ok(terms)
+    }
+
+    // Convert a value to a term. A relation is converted to a new atom that
+    // stands for the relation, so that it can be called through a variable.
+    fn relation_term(
+        &mut self,
+        value: Value,
+        ip: usize,
+    ) -> Result<unification::Term<AtomType>, RuntimeError> {
+        let key = match &value {
+            Value::Term(term) => return Ok(term.clone()),
+            Value::Closure(closure) => Rc::as_ptr(&closure.body) as *const (),
+            Value::Facts(table) => Rc::as_ptr(table) as *const (),
+            Value::Foreign(foreign) => Rc::as_ptr(foreign) as *const (),
+            _ => err!(
+                self,
+                "TypeError: Expected term as argument to relation.",
+                ip
+            ),
+        };
+        if let Some(id) = self.relation_atoms.get(&key) {
+            return Ok(unification::Term::Atom(*id));
+        }
+        let relation = match value {
+            Value::Closure(closure) => {
+                self.build(&closure)?;
+                relation::Relation::Defined(closure.parameters.clone(), closure.body.clone())
+            }
+            Value::Facts(table) => relation::Relation::Facts(table),
+            Value::Foreign(foreign) => relation::Relation::Foreign(foreign),
+            _ => unreachable!("Only relations have keys"),
+        };
+        let name = format!("<relation {}>", self.relations.borrow().len());
+        let id = self.intern(&name);
+        self.relations.borrow_mut().insert(id, Rc::new(relation));
+        self.relation_atoms.insert(key, id);
+        Ok(unification::Term::Atom(id))
+    }
+
+    // Build the body of a relation by running it, unless it has already been
+    // built or is being built.
+    fn build(&mut self, closure: &Closure) -> Result<(), RuntimeError> {
+        if !matches!(*closure.body.borrow(), relation::Body::Unbuilt) {
+            return Ok(());
+        }
+        *closure.body.borrow_mut() = relation::Body::Building;
+        let mut locals = HashMap::new();
+        for (key, value) in &closure.captured {
+            if let Some(value) = value.try_clone() {
+                locals.insert(*key, value);
+            }
+        }
+        let depth = self.callstack.len();
+        self.callstack.push(Frame {
+            kind: CallableKind::Relation,
+            instructions: closure.instructions.clone(),
+            ip: 0,
+            locals,
+            base: self.stack.len(),
+        });
+        self.execute(depth)?;
+        match self.stack.pop() {
+            Some(Value::Goal(goal)) => {
+                *closure.body.borrow_mut() = relation::Body::Built(goal);
+                Ok(())
+            }
+            // TODO: include type in message.
+            _ => err!(
+                self,
+                "TypeError: Unexpected type returned from relation.",
+                0
+            ),
+        }
+    }
+
+    // Get the fact relation bound to `key`, creating it if it does not exist.
+    pub fn fact_table(
+        &mut self,
+        key: u64,
+        arity: usize,
+    ) -> Result<Rc<RefCell<facts::FactTable<AtomType>>>, &'static str> {
+        match self.env.get(&key) {
+            Some(Value::Facts(table)) => {
+                if table.borrow().arity != arity {
+                    Err("TypeError: Fact has the wrong number of arguments.")
+                } else {
+                    Ok(table.clone())
+                }
+            }
+            Some(_) => Err("TypeError: Expected fact relation."),
+            None => {
+                let table = Rc::new(RefCell::new(facts::FactTable::new(arity)));
+                self.env.insert(key, Value::Facts(table.clone()));
+                Ok(table)
+            }
+        }
+    }
+
+    // Add the rows loaded so far from a file to its fact relation, returning
+    // the number of new rows.
+    fn load_rows(
+        &mut self,
+        path: &str,
+        table: &Rc<RefCell<facts::FactTable<AtomType>>>,
+        rows: &mut Vec<Vec<unification::Term<AtomType>>>,
+    ) -> Result<i64, String> {
+        bottomup::assert(self, table, std::mem::take(rows))
+            .map_err(|msg| format!("{}: {}", path, msg))
+    }
+
+    // Open a CSV file, or a TSV file if the filename ends with `.tsv`,
+    // returning its reader and the number of fields in its header, if it has
+    // one.
+    fn csv_reader(
+        path: &str,
+        header: bool,
+    ) -> Result<(csv::Reader<BufReader<File>>, Option<usize>), String> {
+        let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+        let delimiter = if path.ends_with(".tsv") { '\t' } else { ',' };
+        let mut reader = csv::Reader::new(BufReader::new(file), delimiter);
+        let mut arity = None;
+        if header {
+            match reader.next() {
+                Some(Ok(record)) => arity = Some(record.fields.len()),
+                Some(Err(err)) => {
+                    return Err(format!("{}:{}:{}: {}", path, err.line, err.column, err.msg))
+                }
+                None => {}
+            }
+        }
+        Ok((reader, arity))
+    }
+
+    // Load the rows of a CSV file, or a TSV file if the filename ends with
+    // `.tsv`, into the fact relation bound to `key`, returning the number of
+    // new rows. Quoted fields become strings, and unquoted fields become
+    // integers, atoms or strings, depending upon their contents.
+    //
+    // The file is read twice: first to check every record, so that no rows
+    // are loaded from a file with an error, then to add its rows in batches.
+    pub fn load_csv(&mut self, path: &str, key: u64, header: bool) -> Result<i64, String> {
+        let (reader, mut arity) = Self::csv_reader(path, header)?;
+        let mut first = None;
+        for record in reader {
+            let record = record
+                .map_err(|err| format!("{}:{}:{}: {}", path, err.line, err.column, err.msg))?;
+            let arity = *arity.get_or_insert(record.fields.len());
+            if record.fields.len() != arity {
+                return Err(format!(
+                    "{}:{}: Expected {} fields but found {}.",
+                    path,
+                    record.line,
+                    arity,
+                    record.fields.len()
+                ));
+            }
+            first.get_or_insert(record.line);
+        }
+        let (Some(arity), Some(line)) = (arity, first) else {
+            return Ok(0);
+        };
+        let table = self
+            .fact_table(key, arity)
+            .map_err(|msg| format!("{}:{}: {}", path, line, msg))?;
+
+        let (reader, _) = Self::csv_reader(path, header)?;
+        let mut rows = Vec::new();
+        let mut count = 0;
+        for record in reader {
+            let record = record
+                .map_err(|err| format!("{}:{}:{}: {}", path, err.line, err.column, err.msg))?;
+            let row = record
+                .fields
+                .iter()
+                .map(|field| {
+                    if field.quoted {
+                        unification::Term::String(self.intern(&field.value))
+                    } else if let Ok(n) = field.value.parse::<i64>() {
+                        unification::Term::Integer(n)
+                    } else if !field.value.is_empty()
+                        && field.value.chars().all(|c| c.is_alphanumeric() || c == '_')
+                    {
+                        unification::Term::Atom(self.intern(&field.value))
+                    } else {
+                        unification::Term::String(self.intern(&field.value))
+                    }
+                })
+                .collect();
+            rows.push(row);
+            if rows.len() == LOAD_BATCH {
+                count += self.load_rows(path, &table, &mut rows)?;
+            }
+        }
+        Ok(count + self.load_rows(path, &table, &mut rows)?)
+    }
+
+    // Load the objects in a JSON file into the fact relation bound to `key`,
+    // returning the number of new rows. The file may hold either an array of
+    // objects or one object per line. The keys of the first object determine
+    // the order of the arguments, and every object must have the same keys.
+    // Every object is checked before any rows are added, so no rows are
+    // loaded from a file with an error.
+    pub fn load_json(&mut self, path: &str, key: u64) -> Result<i64, String> {
+        let src = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let mut values = Vec::new();
+        for value in json::Parser::new(&src) {
+            values.push(
+                value
+                    .map_err(|err| format!("{}:{}:{}: {}", path, err.line, err.column, err.msg))?,
+            );
+        }
+        if let [json::Value::Array(_)] = values.as_slice() {
+            if let Some(json::Value::Array(rows)) = values.pop() {
+                values = rows;
+            }
+        }
+        let mut keys: Option<Vec<String>> = None;
+        for (i, value) in values.iter().enumerate() {
+            let fields = match value {
+                json::Value::Object(fields) if !fields.is_empty() => fields,
+                _ => {
+                    return Err(format!(
+                        "{}: row {}: Expected non-empty object.",
+                        path,
+                        i + 1
+                    ))
+                }
+            };
+            let keys =
+                keys.get_or_insert_with(|| fields.iter().map(|(key, _)| key.clone()).collect());
+            if fields.len() != keys.len() {
+                return Err(format!(
+                    "{}: row {}: Expected {} keys but found {}.",
+                    path,
+                    i + 1,
+                    keys.len(),
+                    fields.len()
+                ));
+            }
+            if let Some(key) = keys
+                .iter()
+                .find(|key| !fields.iter().any(|(k, _)| k == *key))
+            {
+                return Err(format!("{}: row {}: Expected key {:?}.", path, i + 1, key));
+            }
+        }
+        let Some(keys) = keys else {
+            return Ok(0);
+        };
+        let table = self
+            .fact_table(key, keys.len())
+            .map_err(|msg| format!("{}: {}", path, msg))?;
+
+        let mut rows = Vec::new();
+        let mut count = 0;
+        for value in &values {
+            if let json::Value::Object(fields) = value {
+                let row = keys
+                    .iter()
+                    .filter_map(|key| fields.iter().find(|(k, _)| k == key))
+                    .map(|(_, value)| self.json_to_term(value))
+                    .collect();
+                rows.push(row);
+            }
+            if rows.len() == LOAD_BATCH {
+                count += self.load_rows(path, &table, &mut rows)?;
+            }
+        }
+        Ok(count + self.load_rows(path, &table, &mut rows)?)
+    }
+
     pub fn run(&mut self, instr: Rc<Vec<Opcode>>) -> Result<(), RuntimeError> {
-        self.callstack.push(Value::Callable {
+        self.callstack.push(Frame {
             kind: CallableKind::Function,
-            parameters: Rc::new(Vec::new()),
             instructions: instr,
             ip: 0,
+            locals: HashMap::new(),
+            base: 0,
         });
+        self.execute(0)
+    }
 
+    // Run until the callstack has been reduced to `depth` frames.
+    fn execute(&mut self, depth: usize) -> Result<(), RuntimeError> {
         loop {
-            let (ip, opcode) = match self.callstack.last_mut() {
-                Some(Value::Callable {
-                    kind: _,
-                    parameters: _,
-                    instructions,
-                    ip,
-                }) => {
+            let (ip, instructions) = match self.callstack.last() {
+                Some(frame) if self.callstack.len() > depth => {
                     // Implicit return if we run out of instructions.
                     // TODO: We should explicitly return from all code.
-                    if *ip >= instructions.len() {
+                    if frame.ip >= frame.instructions.len() {
                         self.callstack.pop();
                         continue;
                     }
-                    (*ip, &instructions[*ip])
-                }
-                None => {
-                    return Ok(());
+                    (frame.ip, frame.instructions.clone())
                 }
                 _ => {
-                    unreachable!("Callstack must only contain callables.");
+                    return Ok(());
                 }
             };
+            let opcode = &instructions[ip];
             match opcode {
                 Opcode::Atom(atom) => self.stack.push(Value::Term(unification::Term::Atom(*atom))),
                 Opcode::Variable(var) => self
                     .stack
                     .push(Value::Term(unification::Term::Variable(*var))),
+                Opcode::Integer(n) => self.stack.push(Value::Term(unification::Term::Integer(*n))),
+                Opcode::String(s) => self.stack.push(Value::Term(unification::Term::String(*s))),
                 Opcode::Conj2 => buildgoal!(self, Goal, Conj2, ip),
                 Opcode::Disj2 => buildgoal!(self, Goal, Disj2, ip),
                 Opcode::Unify => buildgoal!(self, Term, Unify, ip),
-                Opcode::Solve => match self.stack.pop() {
-                    Some(Value::Goal(goal)) => {
-                        let substs = HashMap::new();
-                        self.stack.push(Value::Stream(goal.solve(&substs)));
-                    }
-                    None => {
+                Opcode::CallNative(index, argc) => {
+                    let (index, argc) = (*index, *argc);
+                    if self.stack.len() < argc {
                         err!(self, "Stack underflow.", ip);
                     }
-                    _ => {
-                        err!(self, "TypeError: Expected goal.", ip);
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    if let Err(msg) = (builtins::BUILTINS[index].function)(self, args) {
+                        err!(self, msg, ip);
                     }
-                },
-                Opcode::Next => match self.stack.pop() {
-                    Some(Value::Stream(mut stream)) => match stream.next() {
-                        Some(substs) => {
-                            let mut table = HashMap::new();
-                            for subst in substs {
-                                table.insert(unification::Term::Variable(subst.0), subst.1);
-                            }
-                            self.stack.push(Value::Stream(stream));
-                            self.stack.push(Value::Table(table));
-                        }
-                        None => self.stack.push(Value::None),
-                    },
-                    None => {
-                        err!(self, "Stack underflow.", ip);
-                    }
-                    Some(_) => {
-                        // TODO: Add value to error message...
-                        err!(self, "Unexpected value.", ip);
-                    }
-                },
+                }
                 Opcode::Pop => {
                     if self.stack.pop().is_none() {
                         err!(self, "Stack underflow.", ip);
                     }
                 }
                 Opcode::NewTable => {
-                    let table = HashMap::new();
-                    self.stack.push(Value::Table(table));
+                    self.stack.push(Value::new_table(HashMap::new()));
                 }
                 Opcode::SetTable => {
-                    let value = if let Some(value) = self.stack.pop() {
-                        if let Value::Term(term) = value {
-                            term
-                        } else {
-                            err!(self, "TypeError: Expected term.", ip);
+                    let value = match self.stack.pop() {
+                        Some(Value::Stream(..)) => {
+                            err!(self, "TypeError: Streams can't be stored in tables.", ip)
                         }
-                    } else {
-                        err!(self, "Stack underflow.", ip);
+                        Some(value) => value,
+                        None => err!(self, "Stack underflow.", ip),
                     };
                     let key = if let Some(value) = self.stack.pop() {
                         if let Value::Term(term) = value {
@@ -310,7 +975,7 @@ impl VirtualMachine {
                     };
                     if let Some(table) = self.stack.last_mut() {
                         if let Value::Table(table) = table {
-                            table.insert(key, value);
+                            table.borrow_mut().insert(key, value);
                         } else {
                             err!(self, "Expected table.", ip);
                         }
@@ -328,94 +993,59 @@ impl VirtualMachine {
                     } else {
                         err!(self, "Stack underflow.", ip);
                     };
-                    if let Some(table) = self.stack.last_mut() {
-                        if let Value::Table(table) = table {
-                            if let Some(term) = table.get(&key) {
-                                let term = term.clone();
-                                self.stack.push(Value::Term(term));
-                            } else {
-                                self.stack.push(Value::None);
-                            }
-                        } else {
-                            err!(self, "TypeError: Expected table.", ip);
-                        }
-                    } else {
-                        err!(self, "Stack underflow.", ip);
+                    let value = match self.stack.pop() {
+                        Some(Value::Table(table)) => match self.field(&table.borrow(), &key) {
+                            Some(value) => value.try_clone().unwrap_or(Value::None),
+                            None => Value::None,
+                        },
+                        Some(_) => err!(self, "TypeError: Expected table.", ip),
+                        None => err!(self, "Stack underflow.", ip),
                     };
+                    self.stack.push(value);
                 }
                 Opcode::SetEnv => {
                     if let Some(value) = self.stack.pop() {
                         let key = if let Some(value) = self.stack.pop() {
-                            if let Value::Term(term) = value {
-                                if let unification::Term::Variable(v) = term {
-                                    v
-                                } else {
-                                    err!(self, "TypeError: Expected variable.", ip);
-                                }
+                            if let Value::Term(unification::Term::Variable(v)) = value {
+                                v
                             } else {
                                 err!(self, "TypeError: Expected variable.", ip);
                             }
                         } else {
                             err!(self, "Stack underflow.", ip);
                         };
-                        self.env.insert(key, value);
+                        self.set_env(key, value);
                     } else {
                         err!(self, "Stack underflow.", ip);
                     };
                 }
                 Opcode::GetEnv => {
                     let key = if let Some(value) = self.stack.pop() {
-                        if let Value::Term(term) = value {
-                            if let unification::Term::Variable(v) = term {
-                                v
-                            } else {
-                                err!(self, "TypeError: Expected variable.", ip);
-                            }
+                        if let Value::Term(unification::Term::Variable(v)) = value {
+                            v
                         } else {
                             err!(self, "TypeError: Expected variable.", ip);
                         }
                     } else {
                         err!(self, "Stack underflow.", ip);
                     };
-                    if let Some(value) = self.env.get(&key) {
-                        match value {
-                            Value::Term(t) => {
-                                self.stack.push(Value::Term(t.clone()));
-                            }
-                            Value::Goal(g) => {
-                                self.stack.push(Value::Goal(g.clone()));
-                            }
-                            Value::None => {
-                                self.stack.push(Value::None);
-                            }
-                            Value::Stream(_) => {
+                    let value = self
+                        .callstack
+                        .iter()
+                        .rev()
+                        .find_map(|frame| frame.locals.get(&key))
+                        .or_else(|| self.env.get(&key));
+                    if let Some(value) = value {
+                        match value.try_clone() {
+                            Some(value) => self.stack.push(value),
+                            None => {
                                 // TODO: We'll probably want to use a RefCell
                                 // and introduce a reference value to get this
                                 // working properly.
                                 return Err(RuntimeError {
                                     msg: "Accessing streams through variables is not implemented."
                                         .to_string(),
-                                    ip: ip,
-                                });
-                            }
-                            Value::Table(t) => {
-                                // TODO: Right now, tables are inmutable, so
-                                // we can just return a copy. But we'll need
-                                // to reconsider this if we make tables
-                                // mutable.
-                                self.stack.push(Value::Table(t.clone()));
-                            }
-                            Value::Callable {
-                                kind,
-                                parameters,
-                                instructions,
-                                ip,
-                            } => {
-                                self.stack.push(Value::Callable {
-                                    kind: *kind,
-                                    parameters: parameters.clone(),
-                                    instructions: instructions.clone(),
-                                    ip: *ip,
+                                    ip,
                                 });
                             }
                         }
@@ -424,21 +1054,61 @@ impl VirtualMachine {
                         err!(self, "Undefined variable.", ip);
                     }
                 }
-                Opcode::Call => match self.stack.pop() {
+                Opcode::Call(argc) => match self.stack.pop() {
                     Some(Value::Callable {
                         kind,
                         parameters,
                         instructions,
                         ip,
                     }) => {
-                        self.callstack.push(Value::Callable {
+                        // Function arguments are bound to its parameters.
+                        let mut locals = HashMap::new();
+                        if kind == CallableKind::Function {
+                            self.check_arity(parameters.len(), *argc, ip)?;
+                            if self.stack.len() < parameters.len() {
+                                err!(self, "Stack underflow.", ip);
+                            }
+                            let args = self.stack.split_off(self.stack.len() - parameters.len());
+                            locals.extend(parameters.iter().copied().zip(args));
+                        }
+                        self.callstack.push(Frame {
                             kind,
-                            parameters,
                             instructions,
                             ip,
+                            locals,
+                            base: self.stack.len(),
                         });
                         continue;
                     }
+                    Some(Value::Closure(closure)) => {
+                        self.check_arity(closure.parameters.len(), *argc, ip)?;
+                        self.build(&closure)?;
+                        let args = self.pop_args(closure.parameters.len(), ip)?;
+                        let relation = relation::Relation::Defined(
+                            closure.parameters.clone(),
+                            closure.body.clone(),
+                        );
+                        self.stack.push(Value::Goal(Rc::new(relation::Call::new(
+                            Rc::new(relation),
+                            args,
+                            self.goal_error.clone(),
+                        ))));
+                    }
+                    Some(Value::Facts(table)) => {
+                        let arity = table.borrow().arity;
+                        self.check_arity(arity, *argc, ip)?;
+                        let args = self.pop_args(arity, ip)?;
+                        self.stack
+                            .push(Value::Goal(Rc::new(facts::Facts::new(table, args))));
+                    }
+                    Some(Value::Foreign(foreign)) => {
+                        self.check_arity(foreign.arity, *argc, ip)?;
+                        let args = self.pop_args(foreign.arity, ip)?;
+                        self.stack.push(Value::Goal(Rc::new(foreign::Foreign::new(
+                            foreign.relation.clone(),
+                            args,
+                        ))));
+                    }
                     None => {
                         err!(self, "Stack underflow.", ip);
                     }
@@ -446,67 +1116,74 @@ impl VirtualMachine {
                         err!(self, "TypeError: Expected callable.", ip);
                     }
                 },
+                Opcode::Fact(arity) => {
+                    let arity = *arity;
+                    if self.stack.len() < arity + 1 {
+                        err!(self, "Stack underflow.", ip);
+                    }
+                    let mut row = Vec::new();
+                    for value in self.stack.drain(self.stack.len() - arity..) {
+                        match value {
+                            Value::Term(
+                                term @ (unification::Term::Atom(_)
+                                | unification::Term::Integer(_)
+                                | unification::Term::String(_)),
+                            ) => row.push(term),
+                            _ => {
+                                err!(self, "TypeError: Expected constant in fact.", ip);
+                            }
+                        }
+                    }
+                    let key = if let Some(Value::Term(unification::Term::Variable(v))) =
+                        self.stack.pop()
+                    {
+                        v
+                    } else {
+                        err!(self, "TypeError: Expected variable.", ip);
+                    };
+                    match self.fact_table(key, arity) {
+                        Ok(table) => {
+                            if let Err(msg) = bottomup::assert(self, &table, vec![row]) {
+                                err!(self, msg, ip);
+                            }
+                        }
+                        Err(msg) => {
+                            err!(self, msg, ip);
+                        }
+                    }
+                }
                 Opcode::Ret => {
                     match self.callstack.pop() {
-                        Some(Value::Callable {
+                        Some(Frame {
                             kind,
-                            parameters,
                             instructions: _,
                             ip,
+                            locals: _,
+                            base,
                         }) => {
-                            if kind == CallableKind::Relation {
-                                // The relation should have pushed a Goal to the stack.
-                                match self.stack.pop() {
-                                    Some(Value::Goal(goal)) => {
-                                        // Create a series of Conj that bind each parameter to argument using unify.
-                                        let mut result = goal;
-                                        for parameter in parameters.iter().rev() {
-                                            match self.stack.pop() {
-                                                Some(Value::Term(term)) => match term {
-                                                    unification::Term::Atom(_) => {
-                                                        result = Rc::new(logic::Conj2::new(
-                                                            Rc::new(logic::Unify::new(
-                                                                unification::Term::Variable(
-                                                                    *parameter,
-                                                                ),
-                                                                term,
-                                                            )),
-                                                            result,
-                                                        ));
-                                                    }
-                                                    _ => {}
-                                                },
-                                                Some(_) => {
-                                                    // TODO: include type in message.
-                                                    err!(self, "TypeError: Expected term as argument to relation.", ip);
-                                                }
-                                                None => {
-                                                    err!(self, "Stack underflow.", ip);
-                                                }
-                                            }
-                                        }
-                                        self.stack.push(Value::Goal(result));
-                                    }
-                                    Some(_) => {
-                                        // TODO: include type in message.
-                                        err!(
-                                            self,
-                                            "TypeError: Unexpected type returned from relation.",
-                                            ip
-                                        );
-                                    }
-                                    None => {
-                                        err!(self, "Stack underflow.", ip);
-                                    }
-                                }
+                            // Discard anything else the call left on the
+                            // stack.
+                            let value = if self.stack.len() > base {
+                                self.stack.pop().unwrap_or(Value::None)
+                            } else {
+                                Value::None
+                            };
+                            self.stack.truncate(base);
+                            // The relation should have pushed a Goal to the
+                            // stack.
+                            if kind == CallableKind::Relation && !matches!(value, Value::Goal(_)) {
+                                // TODO: include type in message.
+                                err!(
+                                    self,
+                                    "TypeError: Unexpected type returned from relation.",
+                                    ip
+                                );
                             }
+                            self.stack.push(value);
                         }
                         None => {
                             return Ok(());
                         }
-                        _ => unreachable!(
-                            "Callstack must only contain callables while handling `ret`."
-                        ),
                     }
                 }
                 Opcode::Callable {
@@ -515,28 +1192,174 @@ impl VirtualMachine {
                     instructions,
                     ip,
                 } => {
-                    self.stack.push(Value::Callable {
-                        kind: *kind,
-                        parameters: parameters.clone(),
-                        instructions: instructions.clone(),
-                        ip: *ip,
-                    });
+                    if *kind == CallableKind::Relation {
+                        self.stack.push(Value::Closure(Rc::new(Closure {
+                            parameters: parameters.clone(),
+                            instructions: instructions.clone(),
+                            captured: HashMap::new(),
+                            body: Rc::new(RefCell::new(relation::Body::Unbuilt)),
+                        })));
+                    } else {
+                        self.stack.push(Value::Callable {
+                            kind: *kind,
+                            parameters: parameters.clone(),
+                            instructions: instructions.clone(),
+                            ip: *ip,
+                        });
+                    }
                 }
-            }
-            match self.callstack.last_mut() {
-                Some(Value::Callable {
-                    kind: _,
-                    parameters: _,
-                    instructions: _,
-                    ip,
-                }) => {
-                    *ip += 1;
+                Opcode::Capture(variables) => {
+                    let closure = match self.stack.pop() {
+                        Some(Value::Closure(closure)) => closure,
+                        Some(_) => err!(self, "TypeError: Expected relation.", ip),
+                        None => err!(self, "Stack underflow.", ip),
+                    };
+                    let mut captured = HashMap::new();
+                    for key in variables.iter() {
+                        let value = self
+                            .callstack
+                            .iter()
+                            .rev()
+                            .find_map(|frame| frame.locals.get(key))
+                            .or_else(|| self.env.get(key))
+                            .and_then(|value| value.try_clone());
+                        if let Some(value) = value {
+                            captured.insert(*key, value);
+                        }
+                    }
+                    self.stack.push(Value::Closure(Rc::new(Closure {
+                        parameters: closure.parameters.clone(),
+                        instructions: closure.instructions.clone(),
+                        captured,
+                        body: Rc::new(RefCell::new(relation::Body::Unbuilt)),
+                    })));
                 }
-                None => {
-                    return Ok(());
+                Opcode::Tuple(n) => {
+                    let terms = self.pop_terms(*n, ip)?;
+                    self.stack
+                        .push(Value::Term(unification::Term::Tuple(terms)));
                 }
-                _ => unreachable!("Callstack must only contain callables."),
-            };
+                Opcode::Cons(n) => {
+                    let mut list = match self.stack.pop() {
+                        Some(Value::Term(tail)) => tail,
+                        Some(_) => err!(self, "TypeError: Expected term.", ip),
+                        None => err!(self, "Stack underflow.", ip),
+                    };
+                    for term in self.pop_terms(*n, ip)?.into_iter().rev() {
+                        list = unification::Term::Cons(Box::new(term), Box::new(list));
+                    }
+                    self.stack.push(Value::Term(list));
+                }
+                Opcode::Match(variables, skip) => {
+                    let pattern = match self.stack.pop() {
+                        Some(Value::Term(pattern)) => pattern,
+                        Some(_) => err!(self, "TypeError: Expected term.", ip),
+                        None => err!(self, "Stack underflow.", ip),
+                    };
+                    let value = match self.stack.last() {
+                        Some(Value::Term(value)) => value,
+                        Some(_) => err!(self, "TypeError: Expected term to match.", ip),
+                        None => err!(self, "Stack underflow.", ip),
+                    };
+                    // Each match starts from fresh substitutions, so only the
+                    // variables of the pattern are bound.
+                    let mut substs = HashMap::new();
+                    if unification::unify(&pattern, value, &mut substs) {
+                        self.stack.pop();
+                        for var in variables.iter() {
+                            let value =
+                                unification::reify(&unification::Term::Variable(*var), &substs);
+                            self.set_env(*var, Value::Term(value));
+                        }
+                    } else if let Some(frame) = self.callstack.last_mut() {
+                        frame.ip += skip;
+                    }
+                }
+                Opcode::Jump(n) => {
+                    if let Some(frame) = self.callstack.last_mut() {
+                        frame.ip += n;
+                    }
+                }
+                Opcode::JumpIfFalse(n) => match self.stack.pop() {
+                    Some(Value::Boolean(false) | Value::None) => {
+                        if let Some(frame) = self.callstack.last_mut() {
+                            frame.ip += n;
+                        }
+                    }
+                    Some(_) => {}
+                    None => err!(self, "Stack underflow.", ip),
+                },
+                Opcode::JumpIfNone(n) => match self.stack.last() {
+                    Some(Value::None) => {
+                        self.stack.pop();
+                        if let Some(frame) = self.callstack.last_mut() {
+                            frame.ip += n;
+                        }
+                    }
+                    Some(_) => {}
+                    None => err!(self, "Stack underflow.", ip),
+                },
+                Opcode::Loop(n) => {
+                    // The ip is set directly, rather than being advanced past
+                    // this instruction.
+                    if let Some(frame) = self.callstack.last_mut() {
+                        frame.ip = ip - n;
+                    }
+                    continue;
+                }
+                Opcode::Swap => {
+                    let len = self.stack.len();
+                    if len < 2 {
+                        err!(self, "Stack underflow.", ip);
+                    }
+                    self.stack.swap(len - 1, len - 2);
+                }
+                Opcode::None => self.stack.push(Value::None),
+                Opcode::Boolean(b) => self.stack.push(Value::Boolean(*b)),
+                Opcode::Next => match self.stack.pop() {
+                    Some(Value::Stream(mut stream, variables)) => match stream.next() {
+                        _ if self.goal_error.borrow().is_some() => {
+                            err!(self, self.goal_error.take().unwrap_or_default(), ip)
+                        }
+                        Some(substs) => {
+                            let table = self.answer_table(&substs, &variables);
+                            self.stack.push(Value::Stream(stream, variables));
+                            self.stack.push(table);
+                        }
+                        None => self.stack.push(Value::None),
+                    },
+                    Some(_) => err!(self, "TypeError: Expected stream.", ip),
+                    None => err!(self, "Stack underflow.", ip),
+                },
+                Opcode::Report(variables) => match self.stack.pop() {
+                    Some(Value::Stream(stream, _)) => {
+                        self.stack.push(Value::Stream(stream, variables.clone()))
+                    }
+                    Some(_) => err!(self, "TypeError: Expected stream.", ip),
+                    None => err!(self, "Stack underflow.", ip),
+                },
+                Opcode::Apply(argc) => {
+                    let argc = *argc;
+                    let relation = match self.stack.pop() {
+                        Some(Value::Term(term)) => term,
+                        Some(_) => err!(self, "TypeError: Expected term.", ip),
+                        None => err!(self, "Stack underflow.", ip),
+                    };
+                    let args = self.pop_args(argc, ip)?;
+                    self.stack.push(Value::Goal(Rc::new(relation::Apply::new(
+                        self.relations.clone(),
+                        relation,
+                        args,
+                        self.goal_error.clone(),
+                    ))));
+                }
+            }
+            if self.callstack.len() <= depth {
+                return Ok(());
+            }
+            if let Some(frame) = self.callstack.last_mut() {
+                frame.ip += 1;
+            }
         }
     }
 
@@ -545,16 +1368,25 @@ impl VirtualMachine {
             next_id: 0,
             interned: HashMap::new(),
             variables: HashMap::new(),
+            hidden: HashSet::new(),
             stack: Vec::new(),
             callstack: Vec::new(),
             env: HashMap::new(),
+            globals: HashMap::new(),
+            rules: HashMap::new(),
+            views: HashMap::new(),
+            relations: Rc::new(RefCell::new(HashMap::new())),
+            goal_error: Rc::new(RefCell::new(None)),
+            relation_atoms: HashMap::new(),
+            output: Box::new(std::io::stdout()),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{logic, unification, vm};
+    use crate::{builtins, facts, logic, relation, unification, vm};
+    use std::cell::RefCell;
     use std::collections::HashMap;
     use std::rc::Rc;
 
@@ -597,10 +1429,10 @@ mod tests {
         let substs = HashMap::new();
         if let Some(vm::Value::Goal(goal)) = vm.stack.last() {
             vm.stack
-                .push(vm::Value::Stream(Box::new(goal.solve(&substs))));
+                .push(vm::Value::Stream(goal.solve(&substs), Rc::new(vec![])));
         }
         let table = HashMap::new();
-        vm.stack.push(vm::Value::Table(table));
+        vm.stack.push(vm::Value::new_table(table));
         vm.stack.push(vm::Value::None);
         vm.stack.push(vm::Value::Callable {
             kind: vm::CallableKind::Relation,
@@ -618,10 +1450,14 @@ mod tests {
         instr.push(vm::Opcode::Atom(1));
         instr.push(vm::Opcode::Atom(1));
         instr.push(vm::Opcode::Unify);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert!(substs.is_empty());
         } else {
             assert!(false);
@@ -632,8 +1468,11 @@ mod tests {
         instr.push(vm::Opcode::Atom(1));
         instr.push(vm::Opcode::Atom(2));
         instr.push(vm::Opcode::Unify);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         match vm.stack.last() {
             Some(vm::Value::None) => {}
@@ -645,12 +1484,19 @@ mod tests {
         instr.push(vm::Opcode::Variable(1));
         instr.push(vm::Opcode::Atom(2));
         instr.push(vm::Opcode::Unify);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(
-                substs.get(&unification::Term::Variable(1)).unwrap(),
+                substs
+                    .get(&unification::Term::Variable(1))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
                 &unification::Term::Atom(2)
             );
         } else {
@@ -669,13 +1515,20 @@ mod tests {
         instr.push(vm::Opcode::Atom(2));
         instr.push(vm::Opcode::Unify);
         instr.push(vm::Opcode::Disj2);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(
-                substs.get(&unification::Term::Variable(1)).unwrap(),
-                &unification::Term::Atom(2)
+                substs
+                    .get(&unification::Term::Variable(1))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
+                &unification::Term::Atom(1)
             );
         } else {
             assert!(false);
@@ -693,12 +1546,19 @@ mod tests {
         instr.push(vm::Opcode::Atom(2));
         instr.push(vm::Opcode::Unify);
         instr.push(vm::Opcode::Conj2);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(
-                substs.get(&unification::Term::Variable(1)).unwrap(),
+                substs
+                    .get(&unification::Term::Variable(1))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
                 &unification::Term::Atom(1)
             );
         } else {
@@ -714,12 +1574,19 @@ mod tests {
         instr.push(vm::Opcode::Atom(1));
         instr.push(vm::Opcode::Unify);
         instr.push(vm::Opcode::Conj2);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(
-                substs.get(&unification::Term::Variable(1)).unwrap(),
+                substs
+                    .get(&unification::Term::Variable(1))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
                 &unification::Term::Atom(1)
             );
         } else {
@@ -727,6 +1594,188 @@ mod tests {
         }
     }
 
+    #[test]
+    fn matching() {
+        // Match ['a, 'b] against [h | t]. The value is popped, and the Jump
+        // skips the None pushed when nothing matches.
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Atom(2));
+        instr.push(vm::Opcode::Tuple(2));
+        instr.push(vm::Opcode::Variable(3));
+        instr.push(vm::Opcode::Variable(4));
+        instr.push(vm::Opcode::Cons(1));
+        instr.push(vm::Opcode::Match(Rc::new(vec![3, 4]), 1));
+        instr.push(vm::Opcode::Jump(1));
+        instr.push(vm::Opcode::None);
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert!(vm.stack.is_empty());
+        match vm.env.get(&4) {
+            Some(vm::Value::Term(unification::Term::Tuple(terms))) => {
+                assert_eq!(terms, &vec![unification::Term::Atom(2)])
+            }
+            _ => assert!(false),
+        }
+
+        // The empty tuple doesn't match, so the value is left on the stack
+        // and the next instruction is skipped.
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Tuple(0));
+        instr.push(vm::Opcode::Variable(3));
+        instr.push(vm::Opcode::Variable(4));
+        instr.push(vm::Opcode::Cons(1));
+        instr.push(vm::Opcode::Match(Rc::new(vec![3, 4]), 1));
+        instr.push(vm::Opcode::None);
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 1);
+        assert!(matches!(
+            vm.stack.last(),
+            Some(vm::Value::Term(unification::Term::Tuple(_)))
+        ));
+        assert!(vm.env.is_empty());
+    }
+
+    #[test]
+    fn fact_calls() {
+        // A call to a fact relation must pass one argument for each column.
+        let call = |argc: usize| {
+            let mut vm = vm::VirtualMachine::new();
+            let mut table = facts::FactTable::new(2);
+            table.insert(vec![unification::Term::Atom(1), unification::Term::Atom(2)]);
+            vm.env
+                .insert(0, vm::Value::Facts(Rc::new(RefCell::new(table))));
+            let mut instr: Vec<vm::Opcode> = (0..argc as u64).map(vm::Opcode::Variable).collect();
+            instr.push(vm::Opcode::Variable(0));
+            instr.push(vm::Opcode::GetEnv);
+            instr.push(vm::Opcode::Call(argc));
+            vm.run(Rc::new(instr)).map(|()| vm.stack)
+        };
+        match call(2) {
+            Ok(stack) => {
+                assert_eq!(stack.len(), 1);
+                assert!(matches!(stack.last(), Some(vm::Value::Goal(_))));
+            }
+            Err(_) => assert!(false),
+        }
+        for argc in [1, 3] {
+            match call(argc) {
+                Err(err) => assert_eq!(
+                    err.msg,
+                    format!("TypeError: Expected 2 argument(s) but found {}.", argc)
+                ),
+                Ok(_) => assert!(false),
+            }
+        }
+    }
+
+    #[test]
+    fn function_calls() {
+        // fn f(x) { return x }, called with argc arguments.
+        let call = |argc: usize| {
+            let mut vm = vm::VirtualMachine::new();
+            let mut instr: Vec<vm::Opcode> = (1..=argc as u64).map(vm::Opcode::Atom).collect();
+            instr.push(vm::Opcode::Callable {
+                kind: vm::CallableKind::Function,
+                parameters: Rc::new(vec![9]),
+                instructions: Rc::new(vec![
+                    vm::Opcode::Variable(9),
+                    vm::Opcode::GetEnv,
+                    vm::Opcode::Ret,
+                ]),
+                ip: 0,
+            });
+            instr.push(vm::Opcode::Call(argc));
+            vm.run(Rc::new(instr)).map(|()| vm.stack)
+        };
+        match call(1) {
+            Ok(stack) => {
+                assert_eq!(stack.len(), 1);
+                assert!(matches!(
+                    stack.last(),
+                    Some(vm::Value::Term(unification::Term::Atom(1)))
+                ));
+            }
+            Err(_) => assert!(false),
+        }
+        // Extra arguments are an error rather than left on the stack.
+        match call(2) {
+            Err(err) => assert_eq!(err.msg, "TypeError: Expected 1 argument(s) but found 2."),
+            Ok(_) => assert!(false),
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        // JumpIfFalse skips the None, and JumpIfNone pops the None and skips
+        // the Atom.
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Boolean(false));
+        instr.push(vm::Opcode::JumpIfFalse(1));
+        instr.push(vm::Opcode::None);
+        instr.push(vm::Opcode::None);
+        instr.push(vm::Opcode::JumpIfNone(1));
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Boolean(true));
+        instr.push(vm::Opcode::JumpIfFalse(1));
+        instr.push(vm::Opcode::Atom(2));
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 1);
+        assert!(matches!(
+            vm.stack.last(),
+            Some(vm::Value::Term(unification::Term::Atom(2)))
+        ));
+
+        // Loop while a table field is true, clearing it in the body.
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Variable(0));
+        instr.push(vm::Opcode::NewTable);
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Boolean(true));
+        instr.push(vm::Opcode::SetTable);
+        instr.push(vm::Opcode::SetEnv);
+        instr.push(vm::Opcode::Variable(0));
+        instr.push(vm::Opcode::GetEnv);
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::GetTable);
+        instr.push(vm::Opcode::JumpIfFalse(7));
+        instr.push(vm::Opcode::Variable(0));
+        instr.push(vm::Opcode::GetEnv);
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Boolean(false));
+        instr.push(vm::Opcode::SetTable);
+        instr.push(vm::Opcode::Pop);
+        instr.push(vm::Opcode::Loop(11));
+        instr.push(vm::Opcode::Atom(2));
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 1);
+        assert!(matches!(
+            vm.stack.last(),
+            Some(vm::Value::Term(unification::Term::Atom(2)))
+        ));
+
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Atom(2));
+        instr.push(vm::Opcode::Swap);
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert!(matches!(
+            vm.stack.last(),
+            Some(vm::Value::Term(unification::Term::Atom(1)))
+        ));
+        let mut vm = vm::VirtualMachine::new();
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Swap);
+        match vm.run(Rc::new(instr)) {
+            Err(err) => assert_eq!(err.msg, "Stack underflow."),
+            _ => assert!(false),
+        }
+    }
+
     #[test]
     fn table() {
         // Test NewTable.
@@ -735,6 +1784,7 @@ mod tests {
         instr.push(vm::Opcode::NewTable);
         assert!(vm.run(Rc::new(instr)).is_ok());
         if let Some(vm::Value::Table(table)) = vm.stack.last() {
+            let table = table.borrow();
             assert_eq!(table.len(), 0);
         } else {
             assert!(false);
@@ -750,9 +1800,13 @@ mod tests {
         assert!(vm.run(Rc::new(instr)).is_ok());
         assert_eq!(vm.stack.len(), 1);
         if let Some(vm::Value::Table(table)) = vm.stack.last() {
+            let table = table.borrow();
             assert_eq!(table.len(), 1);
             assert_eq!(
-                table.get(&unification::Term::Variable(1)).unwrap(),
+                table
+                    .get(&unification::Term::Variable(1))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
                 &unification::Term::Atom(2)
             );
         } else {
@@ -769,7 +1823,7 @@ mod tests {
         instr.push(vm::Opcode::Variable(2));
         instr.push(vm::Opcode::GetTable);
         assert!(vm.run(Rc::new(instr)).is_ok());
-        assert_eq!(vm.stack.len(), 2);
+        assert_eq!(vm.stack.len(), 1);
         if let Some(vm::Value::None) = vm.stack.last() {
             // Ok.
         } else {
@@ -785,12 +1839,42 @@ mod tests {
         instr.push(vm::Opcode::Variable(1));
         instr.push(vm::Opcode::GetTable);
         assert!(vm.run(Rc::new(instr)).is_ok());
-        assert_eq!(vm.stack.len(), 2);
+        assert_eq!(vm.stack.len(), 1);
         if let Some(vm::Value::Term(unification::Term::Atom(2))) = vm.stack.last() {
             // Ok.
         } else {
             assert!(false);
         }
+
+        // An atom key finds the variable with the same name, and tables can
+        // hold any value except streams.
+        vm = vm::VirtualMachine::new();
+        let x = vm.new_variable(&"x".to_string());
+        let atom = vm.intern(&"x".to_string());
+        instr = Vec::new();
+        instr.push(vm::Opcode::NewTable);
+        instr.push(vm::Opcode::Variable(x));
+        instr.push(vm::Opcode::NewTable);
+        instr.push(vm::Opcode::SetTable);
+        instr.push(vm::Opcode::Atom(atom));
+        instr.push(vm::Opcode::GetTable);
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 1);
+        assert!(matches!(vm.stack.last(), Some(vm::Value::Table(_))));
+
+        vm = vm::VirtualMachine::new();
+        instr = Vec::new();
+        instr.push(vm::Opcode::NewTable);
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Atom(1));
+        instr.push(vm::Opcode::Unify);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::SetTable);
+        assert!(vm.run(Rc::new(instr)).is_err());
     }
 
     #[test]
@@ -845,11 +1929,14 @@ mod tests {
         instr.push(vm::Opcode::Atom(2));
         instr.push(vm::Opcode::Unify);
         instr.push(vm::Opcode::NewTable);
-        instr.push(vm::Opcode::Solve);
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
         instr.push(vm::Opcode::SetEnv);
         instr.push(vm::Opcode::Variable(1));
         instr.push(vm::Opcode::GetEnv);
-        assert!(!vm.run(Rc::new(instr)).is_ok());
+        assert!(vm.run(Rc::new(instr)).is_err());
 
         // Tables
         vm = vm::VirtualMachine::new();
@@ -873,7 +1960,7 @@ mod tests {
         instr.push(vm::Opcode::Atom(1));
         instr.push(vm::Opcode::Atom(2));
         instr.push(vm::Opcode::SetEnv);
-        assert!(!vm.run(Rc::new(instr)).is_ok());
+        assert!(vm.run(Rc::new(instr)).is_err());
     }
 
     #[test]
@@ -891,12 +1978,16 @@ mod tests {
             ]),
             ip: 0,
         });
-        instr.push(vm::Opcode::Call);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::Call(0));
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         assert_eq!(vm.stack.len(), 2);
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert!(substs.is_empty());
         } else {
             assert!(false);
@@ -905,8 +1996,8 @@ mod tests {
         let mut vm = vm::VirtualMachine::new();
         vm.stack
             .push(vm::Value::Term(unification::Term::Variable(1)));
-        vm.stack.push(vm::Value::Callable {
-            kind: vm::CallableKind::Relation,
+        // The parameters of a relation are renamed for each call.
+        vm.stack.push(vm::Value::Closure(Rc::new(vm::Closure {
             parameters: Rc::new(vec![1]),
             instructions: Rc::new(vec![
                 vm::Opcode::Variable(1),
@@ -914,21 +2005,61 @@ mod tests {
                 vm::Opcode::Unify,
                 vm::Opcode::Ret,
             ]),
-            ip: 0,
-        });
+            captured: HashMap::new(),
+            body: Rc::new(RefCell::new(relation::Body::Unbuilt)),
+        })));
         let mut instr = Vec::new();
-        instr.push(vm::Opcode::Call);
-        instr.push(vm::Opcode::Solve);
-        instr.push(vm::Opcode::Next);
+        instr.push(vm::Opcode::Call(1));
+        instr.push(vm::Opcode::CallNative(
+            builtins::lookup("solve").unwrap(),
+            1,
+        ));
+        instr.push(vm::Opcode::CallNative(builtins::lookup("next").unwrap(), 1));
         assert!(vm.run(Rc::new(instr)).is_ok());
         assert_eq!(vm.stack.len(), 2);
         if let Some(vm::Value::Table(substs)) = vm.stack.last() {
+            let substs = substs.borrow();
             assert_eq!(
-                substs.get(&unification::Term::Variable(1)).unwrap(),
+                substs
+                    .get(&unification::Term::Variable(1))
+                    .and_then(vm::Value::as_term)
+                    .unwrap(),
                 &unification::Term::Atom(2)
             );
         } else {
             assert!(false);
         }
+
+        // A function binds its arguments to its parameters in a new frame,
+        // and returns the value at the top of its stack.
+        let mut vm = vm::VirtualMachine::new();
+        vm.env
+            .insert(1, vm::Value::Term(unification::Term::Atom(10)));
+        let mut instr = Vec::new();
+        instr.push(vm::Opcode::Integer(42));
+        instr.push(vm::Opcode::Atom(3));
+        instr.push(vm::Opcode::Callable {
+            kind: vm::CallableKind::Function,
+            parameters: Rc::new(vec![1]),
+            instructions: Rc::new(vec![
+                vm::Opcode::Atom(4),
+                vm::Opcode::Variable(1),
+                vm::Opcode::GetEnv,
+                vm::Opcode::Ret,
+            ]),
+            ip: 0,
+        });
+        instr.push(vm::Opcode::Call(1));
+        assert!(vm.run(Rc::new(instr)).is_ok());
+        assert_eq!(vm.stack.len(), 2);
+        match vm.stack.last() {
+            Some(vm::Value::Term(unification::Term::Atom(3))) => {}
+            _ => assert!(false),
+        }
+        assert!(vm.callstack.is_empty());
+        match vm.env.get(&1) {
+            Some(vm::Value::Term(unification::Term::Atom(10))) => {}
+            _ => assert!(false),
+        }
     }
 }